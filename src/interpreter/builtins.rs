@@ -0,0 +1,73 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::parser::{error::Error, parser::Value};
+
+use super::interpreter::Environment;
+
+/// Defines the native functions every script can use.
+pub fn define(environment: &mut Environment) {
+    environment.define_native("len", 1, |arguments| match &arguments[0] {
+        Value::String(string) => Ok(Value::Integer(string.chars().count() as i64)),
+        Value::List(list) => Ok(Value::Integer(list.borrow().len() as i64)),
+        Value::Tuple(elements) => Ok(Value::Integer(elements.len() as i64)),
+        Value::Range(range) => Ok(Value::Integer(range.checked_len()?)),
+        value => Err(Error::new(format!("Value {} has no length", value))),
+    });
+    environment.define_native("contains", 2, |arguments| match &arguments[0] {
+        Value::Range(range) => Ok(Value::Boolean(range.contains(&arguments[1]))),
+        Value::List(list) => Ok(Value::Boolean(list.borrow().contains(&arguments[1]))),
+        value => Err(Error::new(format!(
+            "Expected range or list but got {}",
+            value
+        ))),
+    });
+    environment.define_native("push", 2, |arguments| {
+        expect_list(&arguments[0])?
+            .borrow_mut()
+            .push(arguments[1].clone());
+        Ok(Value::Nil)
+    });
+    environment.define_native("pop", 1, |arguments| {
+        expect_list(&arguments[0])?
+            .borrow_mut()
+            .pop()
+            .ok_or_else(|| Error::new("Can't pop from an empty list".to_string()))
+    });
+    environment.define_native("insert", 3, |arguments| {
+        let mut list = expect_list(&arguments[0])?.borrow_mut();
+        let index = match &arguments[1] {
+            // Inserting right after the last element appends
+            Value::Integer(int) if *int == list.len() as i64 => list.len(),
+            index => list_index(index, list.len())?,
+        };
+        list.insert(index, arguments[2].clone());
+        Ok(Value::Nil)
+    });
+    environment.define_native("remove", 2, |arguments| {
+        let mut list = expect_list(&arguments[0])?.borrow_mut();
+        let index = list_index(&arguments[1], list.len())?;
+        Ok(list.remove(index))
+    });
+}
+
+pub fn expect_list(value: &Value) -> Result<&Rc<RefCell<Vec<Value>>>, Error> {
+    match value {
+        Value::List(list) => Ok(list),
+        value => Err(Error::new(format!("Expected list but got {}", value))),
+    }
+}
+
+/// Checks that `index` is an integer in `0..len`.
+pub fn list_index(index: &Value, len: usize) -> Result<usize, Error> {
+    match index {
+        Value::Integer(int) if *int >= 0 && (*int as usize) < len => Ok(*int as usize),
+        Value::Integer(int) => Err(Error::new(format!(
+            "Index {} is out of bounds for a list of length {}",
+            int, len
+        ))),
+        value => Err(Error::new(format!(
+            "Expected integer index but got {}",
+            value
+        ))),
+    }
+}
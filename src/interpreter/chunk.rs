@@ -0,0 +1,191 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::parser::{
+    parser::{Pattern, TypeAnnotation, Value},
+    span::Span,
+    token::{Token, TokenType},
+};
+
+/// Where a variable lives once the compiler resolved it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variable {
+    // A slot of the current call frame
+    Local(u16),
+    // A slot of the current call frame holding a cell, because closures capture it
+    Cell(u16),
+    // A cell captured by the running closure
+    Upvalue(u16),
+    // An index into the globals of the VM
+    Global(u32),
+}
+
+/// Which check a conditional jump does on its operand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    If,
+    While,
+    Guard,
+}
+
+impl Condition {
+    pub fn error_message(&self) -> &'static str {
+        match self {
+            Condition::If => "Expected boolean in an if condition",
+            Condition::While => "Expected boolean in a while condition",
+            Condition::Guard => "Expected boolean in a match guard",
+        }
+    }
+}
+
+/// A single instruction. Operands index into the tables of the chunk or the slots of the
+/// current frame, jump targets are offsets into the code of the chunk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Constant(u32),
+    Pop,
+    Dup,
+    Get(Variable),
+    // Pops the value and stores it, globals are created if they don't exist yet
+    Define(Variable),
+    // Pops the value and stores it, globals have to exist already
+    Store(Variable),
+    // Converts the value on top to the type of `Chunk::annotations`, like an int to a real
+    Convert(u32),
+    // Puts a new cell into a slot, at the start of every scope declaring captured variables
+    NewCell(u16),
+    Unary(TokenType),
+    Binary(TokenType),
+    // Arithmetic and comparisons with a fast path for two integers or two reals, any other
+    // operands are handled like by Binary
+    Add,
+    Subtract,
+    Multiply,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
+    // Checks the boolean on top of the stack. If it decides the result of the `and` or `or`, it
+    // is kept and we jump, otherwise it is popped.
+    ShortCircuit(TokenType, u32),
+    // Checks that the right operand of `and` or `or` is a boolean
+    Logical(TokenType),
+    Jump(u32),
+    JumpIfFalse(u32, Condition),
+    Print,
+    Call(u8),
+    Return,
+    // Creates a closure of `Chunk::functions`, capturing the variables the function uses
+    Closure(u32),
+    // Pops the field values and the struct, which are described by `Chunk::constructs`
+    Construct(u32),
+    Member(u32),
+    Index,
+    List(u32),
+    Tuple(u32),
+    Interpolate(u32),
+    // Pops a range or list, storing it and the next index in two slots
+    ForStart(u16),
+    // Pushes the next element of a for loop, or jumps to the end of the loop
+    ForNext(u16, u32),
+    // Pops the subject and the enums named by the pattern. When it matches, the values it binds
+    // are pushed followed by true, otherwise only false is pushed.
+    Match(u32),
+    // Reports that no arm of a match expression matched the popped subject
+    NoMatch,
+    // Pops a value and pushes the values bound by the let pattern
+    Destructure(u32),
+    // The assignment targets are built on a separate stack of places, so swizzles can write
+    // the vector back to where it came from
+    PlaceVariable(Variable),
+    // Turns the place on top into the place of one of its members
+    PlaceMember(u32),
+    // Pops a value and makes a place of its member
+    PlaceTemporaryMember(u32),
+    // Pops an index and a list and makes a place of the element
+    PlaceIndex,
+    PlaceTuple(u32),
+    // Converts values written to the place on top to the type of `Chunk::annotations`
+    PlaceConvert(u32),
+    // Pushes the current value of the place on top
+    ReadPlace,
+    // Pops the place and writes the value on top of the stack to it, keeping the value
+    WritePlace,
+}
+
+/// The fields of a struct literal, in the order their values are pushed
+#[derive(Debug)]
+pub struct Construct {
+    pub name: Token,
+    pub fields: Vec<Token>,
+    pub span: Span,
+}
+
+/// A pattern of a match arm or a let declaration, with the tokens of the enums it names in
+/// the order their values are pushed
+#[derive(Debug)]
+pub struct CompiledPattern {
+    pub pattern: Pattern,
+    pub enums: Vec<Token>,
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    // Span of the source code each instruction was compiled from, for errors
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+    pub names: Vec<Token>,
+    pub functions: Vec<Rc<Function>>,
+    pub constructs: Vec<Construct>,
+    pub patterns: Vec<CompiledPattern>,
+    // Types that values are converted to when they are stored in annotated variables
+    pub annotations: Vec<TypeAnnotation>,
+}
+
+impl Chunk {
+    pub fn emit(&mut self, op: Op, span: Span) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+}
+
+/// A variable of an enclosing function captured by a closure
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capture {
+    // A cell in a slot of the function creating the closure
+    Local(u16),
+    // A cell the function creating the closure captured itself
+    Upvalue(u16),
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: Option<String>,
+    pub arity: usize,
+    // How many slots a call frame needs, for parameters, variables and temporaries
+    pub slots: usize,
+    pub captures: Vec<Capture>,
+    pub chunk: Chunk,
+}
+
+impl Display for Chunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (offset, op) in self.code.iter().enumerate() {
+            write!(f, "{:04} {:?}", offset, op)?;
+            match op {
+                Op::Constant(index) => write!(f, " {}", self.constants[*index as usize])?,
+                Op::Member(index) | Op::PlaceMember(index) | Op::PlaceTemporaryMember(index) => {
+                    write!(f, " {}", self.names[*index as usize].lexeme)?
+                }
+                _ => {}
+            }
+            writeln!(f)?;
+        }
+        for function in &self.functions {
+            let name = function.name.as_deref().unwrap_or("<fn>");
+            writeln!(f, "\n{}:\n{}", name, function.chunk)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1193 @@
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use super::{
+    chunk::{Capture, Chunk, CompiledPattern, Condition, Construct, Function, Op, Variable},
+    interpreter::{EnumType, StructType, VariantType},
+    vm::Globals,
+};
+use crate::parser::{
+    error::Error,
+    parser::{
+        Expression, FunctionDeclaration, MatchArm, Pattern, Statement, TypeAnnotation, Value,
+    },
+    resolver::Binding,
+    span::Span,
+    token::{Token, TokenType},
+};
+
+struct Scope {
+    // Every variable declared directly in the scope. Slots are reserved when the scope starts,
+    // so closures created in the scope can capture variables declared further down.
+    variables: HashMap<String, Variable>,
+    // Variables whose declaration was already compiled
+    declared: HashSet<String>,
+    // Annotations of variables that convert what is assigned to them, like an int to a real
+    annotations: HashMap<String, TypeAnnotation>,
+    // First slot of the scope, the slots from here on are free again once it ends
+    first_slot: usize,
+}
+
+struct Loop {
+    label: Option<String>,
+    start: usize,
+    // Jumps to patch with the end of the loop
+    breaks: Vec<usize>,
+}
+
+/// The function being compiled, the script itself is compiled as a function without parameters
+struct FunctionState {
+    name: Option<String>,
+    arity: usize,
+    // Returned values are converted to this type
+    return_type: Option<TypeAnnotation>,
+    chunk: Chunk,
+    // Local scopes, innermost last. Variables of the script outside of any block are globals.
+    scopes: Vec<Scope>,
+    next_slot: usize,
+    slots: usize,
+    captures: Vec<Capture>,
+    loops: Vec<Loop>,
+}
+
+impl FunctionState {
+    fn new(name: Option<String>, arity: usize, return_type: Option<TypeAnnotation>) -> Self {
+        Self {
+            name,
+            arity,
+            return_type,
+            chunk: Chunk::default(),
+            scopes: Vec::new(),
+            next_slot: 0,
+            slots: 0,
+            captures: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+
+    /// Finds a variable declared in this function. Declarations further down are only visible
+    /// to closures, which run after the scope was set up.
+    fn lookup(&self, name: &str, closure: bool) -> Option<Variable> {
+        self.scope(name, closure).map(|scope| scope.variables[name])
+    }
+
+    /// The scope `lookup` finds the variable in
+    fn scope(&self, name: &str, closure: bool) -> Option<&Scope> {
+        self.scopes.iter().rev().find(|scope| {
+            scope.variables.contains_key(name) && (closure || scope.declared.contains(name))
+        })
+    }
+
+    fn capture(&mut self, capture: Capture, span: Span) -> Result<Variable, Error> {
+        let index = match self.captures.iter().position(|other| *other == capture) {
+            Some(index) => index,
+            None => {
+                self.captures.push(capture);
+                self.captures.len() - 1
+            }
+        };
+        let index = u16::try_from(index).map_err(|_| {
+            Error::new("Too many captured variables in a function".to_string()).with_span(span)
+        })?;
+        Ok(Variable::Upvalue(index))
+    }
+}
+
+/// Compiles statements to bytecode, resolving every variable to a slot, a captured cell or a
+/// global at compile time
+struct Compiler<'g> {
+    // The function being compiled last, the functions it is nested in before it
+    functions: Vec<FunctionState>,
+    globals: &'g mut Globals,
+}
+
+impl<'g> Compiler<'g> {
+    fn function(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.function().chunk
+    }
+
+    fn emit(&mut self, op: Op, span: Span) -> usize {
+        self.chunk().emit(op, span)
+    }
+
+    fn emit_jump(&mut self, jump: impl Fn(u32) -> Op, span: Span) -> usize {
+        self.emit(jump(u32::MAX), span)
+    }
+
+    /// Points the jump at `offset` to the next instruction
+    fn patch_jump(&mut self, offset: usize) {
+        let target = self.chunk().code.len() as u32;
+        match &mut self.chunk().code[offset] {
+            Op::Jump(jump) | Op::JumpIfFalse(jump, _) | Op::ShortCircuit(_, jump) => *jump = target,
+            Op::ForNext(_, jump) => *jump = target,
+            op => unreachable!("{:?} is not a jump", op),
+        }
+    }
+
+    fn constant(&mut self, value: Value, span: Span) -> Result<(), Error> {
+        let index = table_index(&self.chunk().constants, span)?;
+        self.chunk().constants.push(value);
+        self.emit(Op::Constant(index), span);
+        Ok(())
+    }
+
+    fn name(&mut self, name: &Token) -> Result<u32, Error> {
+        let chunk = self.chunk();
+        if let Some(index) = chunk.names.iter().position(|other| other == name) {
+            return Ok(index as u32);
+        }
+        let index = table_index(&chunk.names, name.span)?;
+        chunk.names.push(name.clone());
+        Ok(index)
+    }
+
+    fn begin_scope(&mut self, names: &[&Token], captured: &HashSet<String>) -> Result<(), Error> {
+        let function = self.function();
+        let mut scope = Scope {
+            variables: HashMap::new(),
+            declared: HashSet::new(),
+            annotations: HashMap::new(),
+            first_slot: function.next_slot,
+        };
+        let mut cells = Vec::new();
+        for name in names {
+            if scope.variables.contains_key(&name.lexeme) {
+                // Declaring a name again in the same scope overwrites the variable
+                continue;
+            }
+            let slot = u16::try_from(function.next_slot).map_err(|_| {
+                Error::new("Too many local variables in a function".to_string())
+                    .with_span(name.span)
+            })?;
+            function.next_slot += 1;
+            let variable = match captured.contains(&name.lexeme) {
+                true => {
+                    cells.push((slot, name.span));
+                    Variable::Cell(slot)
+                }
+                false => Variable::Local(slot),
+            };
+            scope.variables.insert(name.lexeme.clone(), variable);
+        }
+        function.slots = function.slots.max(function.next_slot);
+        function.scopes.push(scope);
+
+        for (slot, span) in cells {
+            self.emit(Op::NewCell(slot), span);
+        }
+        Ok(())
+    }
+
+    fn end_scope(&mut self) {
+        let function = self.function();
+        let scope = function.scopes.pop().unwrap();
+        function.next_slot = scope.first_slot;
+    }
+
+    /// Reserves slots for values the compiled code keeps around, like the subject of a match.
+    /// They are freed with the scope they were reserved in.
+    fn temporary(&mut self, count: usize, span: Span) -> Result<u16, Error> {
+        let function = self.function();
+        let slot = u16::try_from(function.next_slot + count).map_err(|_| {
+            Error::new("Too many local variables in a function".to_string()).with_span(span)
+        })? - count as u16;
+        function.next_slot += count;
+        function.slots = function.slots.max(function.next_slot);
+        Ok(slot)
+    }
+
+    fn declare(&mut self, name: &Token) -> Variable {
+        self.declare_annotated(name, None)
+    }
+
+    /// Declares a variable whose values are converted to the type it was annotated with
+    fn declare_annotated(&mut self, name: &Token, annotation: Option<&TypeAnnotation>) -> Variable {
+        self.annotate(&name.lexeme, annotation);
+        let function = self.functions.last_mut().unwrap();
+        let Some(scope) = function.scopes.last_mut() else {
+            return Variable::Global(self.globals.index(&name.lexeme));
+        };
+        scope.declared.insert(name.lexeme.clone());
+        scope.variables[&name.lexeme]
+    }
+
+    fn annotate(&mut self, name: &str, annotation: Option<&TypeAnnotation>) {
+        let annotation = annotation
+            .filter(|annotation| annotation.converts())
+            .cloned();
+        let Some(scope) = self.function().scopes.last_mut() else {
+            self.globals.annotate(name, annotation);
+            return;
+        };
+        match annotation {
+            Some(annotation) => scope.annotations.insert(name.to_string(), annotation),
+            None => scope.annotations.remove(name),
+        };
+    }
+
+    /// Annotates the variables declared by the statements of the current scope up front,
+    /// because closures compiled before a declaration can already assign to the variable
+    fn annotate_declarations(&mut self, statements: &[Statement]) {
+        let mut annotated = HashSet::new();
+        for statement in statements {
+            for name in declared_names(statement) {
+                if annotated.insert(&name.lexeme) {
+                    self.annotate(&name.lexeme, declared_annotation(statement));
+                }
+            }
+        }
+    }
+
+    /// The annotation of the variable an assignment to `name` writes to, found like `resolve`
+    fn annotation(&self, name: &Token) -> Option<TypeAnnotation> {
+        let (function, enclosing) = self.functions.split_last().unwrap();
+        let scope = function.scope(&name.lexeme, false).or_else(|| {
+            enclosing
+                .iter()
+                .rev()
+                .find_map(|function| function.scope(&name.lexeme, true))
+        });
+        match scope {
+            Some(scope) => scope.annotations.get(&name.lexeme).cloned(),
+            None => self.globals.annotation(&name.lexeme).cloned(),
+        }
+    }
+
+    /// Converts the value on top of the stack to the annotated type
+    fn convert(&mut self, annotation: Option<&TypeAnnotation>, span: Span) -> Result<(), Error> {
+        if let Some(annotation) = annotation.filter(|annotation| annotation.converts()) {
+            let index = table_index(&self.chunk().annotations, span)?;
+            self.chunk().annotations.push(annotation.clone());
+            self.emit(Op::Convert(index), span);
+        }
+        Ok(())
+    }
+
+    fn resolve(&mut self, name: &Token) -> Result<Variable, Error> {
+        let depth = self.functions.len() - 1;
+        if let Some(variable) = self.functions[depth].lookup(&name.lexeme, false) {
+            return Ok(variable);
+        }
+        match self.resolve_capture(depth, name)? {
+            Some(variable) => Ok(variable),
+            None => Ok(Variable::Global(self.globals.index(&name.lexeme))),
+        }
+    }
+
+    /// Resolves a variable of a function enclosing the function at `depth`
+    fn resolve_capture(&mut self, depth: usize, name: &Token) -> Result<Option<Variable>, Error> {
+        if depth == 0 {
+            return Ok(None);
+        }
+        let capture = match self.functions[depth - 1].lookup(&name.lexeme, true) {
+            Some(Variable::Cell(slot)) => Capture::Local(slot),
+            Some(variable) => {
+                unreachable!("captured variable {} is in {:?}", name.lexeme, variable)
+            }
+            None => match self.resolve_capture(depth - 1, name)? {
+                Some(Variable::Upvalue(index)) => Capture::Upvalue(index),
+                variable => return Ok(variable),
+            },
+        };
+        self.functions[depth].capture(capture, name.span).map(Some)
+    }
+
+    fn statements(&mut self, statements: &[Statement]) -> Result<(), Error> {
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, statements: &[Statement]) -> Result<(), Error> {
+        let names = statements
+            .iter()
+            .flat_map(declared_names)
+            .collect::<Vec<_>>();
+        self.begin_scope(
+            &names,
+            &captured_names(statements.iter().map(Node::Statement)),
+        )?;
+        self.annotate_declarations(statements);
+        self.statements(statements)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), Error> {
+        let span = statement.span();
+        match statement {
+            Statement::Expression { expr } => {
+                self.expression(expr)?;
+                self.emit(Op::Pop, span);
+            }
+            Statement::Print { expr } => {
+                self.expression(expr)?;
+                self.emit(Op::Print, span);
+            }
+            Statement::Let {
+                name,
+                annotation,
+                initializer,
+                ..
+            }
+            | Statement::Const {
+                name,
+                annotation,
+                initializer,
+            } => {
+                self.expression(initializer)?;
+                self.convert(annotation.as_ref(), name.span)?;
+                let variable = self.declare_annotated(name, annotation.as_ref());
+                self.emit(Op::Define(variable), name.span);
+            }
+            Statement::Destructure {
+                pattern,
+                initializer,
+                ..
+            } => {
+                self.expression(initializer)?;
+                let index = self.pattern(pattern, span)?;
+                self.emit(Op::Destructure(index), span);
+                self.define_bindings(pattern);
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition)?;
+                let else_jump = self.emit_jump(
+                    |jump| Op::JumpIfFalse(jump, Condition::If),
+                    condition.span(),
+                );
+                self.statement(then_branch)?;
+                let end_jump = self.emit_jump(Op::Jump, span);
+                self.patch_jump(else_jump);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.patch_jump(end_jump);
+            }
+            Statement::Block { statements, .. } => self.block(statements)?,
+            Statement::For {
+                label,
+                variable,
+                range,
+                body,
+            } => {
+                self.begin_scope(&[], &HashSet::new())?;
+                let iterator = self.temporary(2, range.span())?;
+                self.expression(range)?;
+                self.emit(Op::ForStart(iterator), range.span());
+
+                let start = self.chunk().code.len();
+                let exit = self.emit_jump(|jump| Op::ForNext(iterator, jump), span);
+                self.begin_loop(label, start);
+                // Every iteration has its own variable, closures capture a fresh one
+                self.begin_scope(&[variable], &captured_names([Node::Statement(body)]))?;
+                let slot = self.declare(variable);
+                self.emit(Op::Define(slot), variable.span);
+                self.statement(body)?;
+                self.end_scope();
+                self.emit(Op::Jump(start as u32), span);
+                self.patch_jump(exit);
+                self.end_loop();
+                self.end_scope();
+            }
+            Statement::While {
+                label,
+                condition,
+                body,
+            } => {
+                let start = self.chunk().code.len();
+                self.expression(condition)?;
+                let exit = self.emit_jump(
+                    |jump| Op::JumpIfFalse(jump, Condition::While),
+                    condition.span(),
+                );
+                self.begin_loop(label, start);
+                self.statement(body)?;
+                self.emit(Op::Jump(start as u32), span);
+                self.patch_jump(exit);
+                self.end_loop();
+            }
+            Statement::Loop { label, body } => {
+                let start = self.chunk().code.len();
+                self.begin_loop(label, start);
+                self.statement(body)?;
+                self.emit(Op::Jump(start as u32), span);
+                self.end_loop();
+            }
+            Statement::Break { label, .. } => {
+                let jump = self.emit_jump(Op::Jump, span);
+                self.find_loop(label).breaks.push(jump);
+            }
+            Statement::Continue { label, .. } => {
+                let start = self.find_loop(label).start;
+                self.emit(Op::Jump(start as u32), span);
+            }
+            Statement::Function { declaration } => {
+                self.closure(declaration)?;
+                if let Some(name) = &declaration.name {
+                    let variable = self.declare(name);
+                    self.emit(Op::Define(variable), name.span);
+                }
+            }
+            Statement::Struct { name, fields, .. } => {
+                let struct_type = StructType {
+                    name: name.lexeme.clone(),
+                    fields: fields.iter().map(|field| field.lexeme.clone()).collect(),
+                };
+                self.constant(Value::Struct(Rc::new(struct_type)), span)?;
+                let variable = self.declare(name);
+                self.emit(Op::Define(variable), name.span);
+            }
+            Statement::Enum { name, variants, .. } => {
+                let enum_type = EnumType {
+                    name: name.lexeme.clone(),
+                    variants: variants
+                        .iter()
+                        .map(|variant| VariantType {
+                            name: variant.name.lexeme.clone(),
+                            fields: variant
+                                .fields
+                                .iter()
+                                .map(|field| field.lexeme.clone())
+                                .collect(),
+                        })
+                        .collect(),
+                };
+                self.constant(Value::Enum(Rc::new(enum_type)), span)?;
+                let variable = self.declare(name);
+                self.emit(Op::Define(variable), name.span);
+            }
+            Statement::Return { value, .. } => {
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => self.constant(Value::Nil, span)?,
+                }
+                let return_type = self.function().return_type.clone();
+                self.convert(return_type.as_ref(), span)?;
+                self.emit(Op::Return, span);
+            }
+            Statement::Match { value, arms, .. } => {
+                // Values no arm matches are ignored, the parser warns about that
+                let end_jumps = self.match_arms(value, arms, Self::statement)?;
+                for jump in end_jumps {
+                    self.patch_jump(jump);
+                }
+                self.end_scope();
+            }
+            Statement::Documented { statement, .. } => self.statement(statement)?,
+        }
+        Ok(())
+    }
+
+    fn begin_loop(&mut self, label: &Option<Token>, start: usize) {
+        self.function().loops.push(Loop {
+            label: label.as_ref().map(|label| label.lexeme.clone()),
+            start,
+            breaks: Vec::new(),
+        });
+    }
+
+    fn end_loop(&mut self) {
+        let exit = self.function().loops.pop().unwrap();
+        for jump in exit.breaks {
+            self.patch_jump(jump);
+        }
+    }
+
+    // The parser makes sure break and continue are inside of a loop with their label
+    fn find_loop(&mut self, label: &Option<Token>) -> &mut Loop {
+        let loops = &mut self.function().loops;
+        match label {
+            Some(label) => loops
+                .iter_mut()
+                .rev()
+                .find(|other| other.label.as_ref() == Some(&label.lexeme))
+                .unwrap(),
+            None => loops.last_mut().unwrap(),
+        }
+    }
+
+    /// Compiles the arms of a match, leaving the scope holding the subject open. Returns the
+    /// jumps to patch with the end of the match.
+    fn match_arms<B>(
+        &mut self,
+        value: &Expression,
+        arms: &[MatchArm<B>],
+        body: fn(&mut Self, &B) -> Result<(), Error>,
+    ) -> Result<Vec<usize>, Error>
+    where
+        for<'b> &'b B: Into<Node<'b>>,
+    {
+        self.begin_scope(&[], &HashSet::new())?;
+        let subject = self.temporary(1, value.span())?;
+        self.expression(value)?;
+        self.emit(Op::Define(Variable::Local(subject)), value.span());
+
+        let mut end_jumps = Vec::new();
+        for arm in arms {
+            let span = arm.pattern.span();
+            let mut names = Vec::new();
+            pattern_bindings(&arm.pattern, &mut names);
+            let nodes = arm.guard.iter().map(Node::Expression);
+            let captured = captured_names(nodes.chain([(&arm.body).into()]));
+            self.begin_scope(&names, &captured)?;
+
+            self.emit(Op::Get(Variable::Local(subject)), value.span());
+            let index = self.pattern(&arm.pattern, span)?;
+            let mut enums = Vec::new();
+            pattern_enums(&arm.pattern, &mut enums);
+            for name in enums {
+                let variable = self.resolve(name)?;
+                self.emit(Op::Get(variable), name.span);
+            }
+            self.emit(Op::Match(index), span);
+            let next_arm = self.emit_jump(|jump| Op::JumpIfFalse(jump, Condition::Guard), span);
+            self.define_bindings(&arm.pattern);
+
+            let guard_jump = match &arm.guard {
+                Some(guard) => {
+                    self.expression(guard)?;
+                    let condition = |jump| Op::JumpIfFalse(jump, Condition::Guard);
+                    Some(self.emit_jump(condition, guard.span()))
+                }
+                None => None,
+            };
+            body(self, &arm.body)?;
+            end_jumps.push(self.emit_jump(Op::Jump, span));
+            self.patch_jump(next_arm);
+            if let Some(guard_jump) = guard_jump {
+                self.patch_jump(guard_jump);
+            }
+            self.end_scope();
+        }
+        Ok(end_jumps)
+    }
+
+    fn pattern(&mut self, pattern: &Pattern, span: Span) -> Result<u32, Error> {
+        let mut enums = Vec::new();
+        pattern_enums(pattern, &mut enums);
+        let pattern = CompiledPattern {
+            pattern: pattern.clone(),
+            enums: enums.into_iter().cloned().collect(),
+        };
+        let index = table_index(&self.chunk().patterns, span)?;
+        self.chunk().patterns.push(pattern);
+        Ok(index)
+    }
+
+    /// Stores the values bound by a pattern, which are pushed in the order of the bindings
+    fn define_bindings(&mut self, pattern: &Pattern) {
+        let mut names = Vec::new();
+        pattern_bindings(pattern, &mut names);
+        for name in names.iter().rev() {
+            let variable = self.declare(name);
+            self.emit(Op::Define(variable), name.span);
+        }
+    }
+
+    fn closure(&mut self, declaration: &FunctionDeclaration) -> Result<(), Error> {
+        let name = declaration.name.as_ref().map(|name| name.lexeme.clone());
+        self.functions.push(FunctionState::new(
+            name,
+            declaration.params.len(),
+            declaration.return_type.clone(),
+        ));
+
+        // Parameters are in the first slots, where the call puts the arguments
+        let mut names = declaration
+            .params
+            .iter()
+            .map(|param| &param.name)
+            .collect::<Vec<_>>();
+        names.extend(declaration.body.iter().flat_map(declared_names));
+        let captured = captured_names(declaration.body.iter().map(Node::Statement));
+
+        // Captured arguments are moved into the cells the scope starts with
+        let arity = u16::try_from(declaration.params.len()).map_err(|_| {
+            Error::new("Too many local variables in a function".to_string())
+                .with_span(declaration.span)
+        })?;
+        for (slot, param) in (0..arity).zip(&declaration.params) {
+            if param
+                .annotation
+                .as_ref()
+                .is_some_and(TypeAnnotation::converts)
+            {
+                self.emit(Op::Get(Variable::Local(slot)), param.name.span);
+                self.convert(param.annotation.as_ref(), param.name.span)?;
+                self.emit(Op::Store(Variable::Local(slot)), param.name.span);
+            }
+        }
+        let moved = (0..arity)
+            .filter(|slot| captured.contains(&names[*slot as usize].lexeme))
+            .collect::<Vec<_>>();
+        for slot in &moved {
+            self.emit(Op::Get(Variable::Local(*slot)), declaration.span);
+        }
+        self.begin_scope(&names, &captured)?;
+        self.annotate_declarations(&declaration.body);
+        for slot in moved.iter().rev() {
+            self.emit(Op::Define(Variable::Cell(*slot)), declaration.span);
+        }
+        for param in &declaration.params {
+            self.declare(&param.name);
+        }
+        self.statements(&declaration.body)?;
+        self.constant(Value::Nil, declaration.span)?;
+        self.emit(Op::Return, declaration.span);
+        self.end_scope();
+
+        let function = self.functions.pop().unwrap();
+        let function = Function {
+            name: function.name,
+            arity: function.arity,
+            slots: function.slots,
+            captures: function.captures,
+            chunk: function.chunk,
+        };
+        let index = table_index(&self.chunk().functions, declaration.span)?;
+        self.chunk().functions.push(Rc::new(function));
+        self.emit(Op::Closure(index), declaration.span);
+        Ok(())
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Result<(), Error> {
+        let span = expression.span();
+        match expression {
+            Expression::Value { value, .. } => self.constant(value.clone(), span)?,
+            Expression::Variable { name } => {
+                let variable = self.resolve(name)?;
+                self.emit(Op::Get(variable), name.span);
+            }
+            Expression::Unary { operator, right } => {
+                self.expression(right)?;
+                self.emit(Op::Unary(operator.token_type), span);
+            }
+            Expression::BinaryExpr {
+                operator,
+                left,
+                right,
+            } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.emit(binary_op(operator.token_type), span);
+            }
+            Expression::LogicalExpr {
+                operator,
+                left,
+                right,
+            } => {
+                self.expression(left)?;
+                let operator = operator.token_type;
+                let end = self.emit_jump(|jump| Op::ShortCircuit(operator, jump), left.span());
+                self.expression(right)?;
+                self.emit(Op::Logical(operator), right.span());
+                self.patch_jump(end);
+            }
+            Expression::Grouping { expr } => self.expression(expr)?,
+            Expression::Interpolation { parts, .. } => {
+                for part in parts {
+                    self.expression(part)?;
+                }
+                let count = count(parts.len(), span)?;
+                self.emit(Op::Interpolate(count), span);
+            }
+            Expression::Assign { assignee, value } => {
+                self.expression(value)?;
+                if let Expression::Variable { name } = assignee.as_ref() {
+                    let variable = self.resolve(name)?;
+                    self.emit(Op::Dup, span);
+                    self.convert(self.annotation(name).as_ref(), name.span)?;
+                    self.emit(Op::Store(variable), name.span);
+                } else {
+                    self.place(assignee)?;
+                    self.emit(Op::WritePlace, span);
+                }
+            }
+            Expression::CompoundAssign {
+                operator,
+                assignee,
+                value,
+            } => {
+                let Some(binary_operator) = operator.token_type.compound_operator() else {
+                    return Err(
+                        Error::new("Expected compound assignment operator".to_string())
+                            .with_span(operator.span),
+                    );
+                };
+                // The target is only evaluated once, so `a[next()] += 1` calls next once
+                if let Expression::Variable { name } = assignee.as_ref() {
+                    let variable = self.resolve(name)?;
+                    self.emit(Op::Get(variable), name.span);
+                    self.expression(value)?;
+                    self.emit(binary_op(binary_operator), operator.span);
+                    self.emit(Op::Dup, span);
+                    self.convert(self.annotation(name).as_ref(), name.span)?;
+                    self.emit(Op::Store(variable), name.span);
+                } else {
+                    self.place(assignee)?;
+                    self.emit(Op::ReadPlace, span);
+                    self.expression(value)?;
+                    self.emit(binary_op(binary_operator), operator.span);
+                    self.emit(Op::WritePlace, span);
+                }
+            }
+            Expression::Member { object, name } => {
+                self.expression(object)?;
+                let name = self.name(name)?;
+                self.emit(Op::Member(name), span);
+            }
+            Expression::Construct { name, fields, span } => {
+                let variable = self.resolve(name)?;
+                self.emit(Op::Get(variable), name.span);
+                for (_, value) in fields {
+                    self.expression(value)?;
+                }
+                let construct = Construct {
+                    name: name.clone(),
+                    fields: fields.iter().map(|(field, _)| field.clone()).collect(),
+                    span: *span,
+                };
+                let index = table_index(&self.chunk().constructs, *span)?;
+                self.chunk().constructs.push(construct);
+                self.emit(Op::Construct(index), *span);
+            }
+            Expression::Call {
+                callee,
+                arguments,
+                span,
+            } => {
+                self.expression(callee)?;
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                let count = u8::try_from(arguments.len()).map_err(|_| {
+                    Error::new("Can't call a function with more than 255 arguments".to_string())
+                        .with_span(*span)
+                })?;
+                self.emit(Op::Call(count), *span);
+            }
+            Expression::Function { declaration } => self.closure(declaration)?,
+            Expression::List { elements, .. } => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                let count = count(elements.len(), span)?;
+                self.emit(Op::List(count), span);
+            }
+            Expression::Tuple { elements, .. } => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                let count = count(elements.len(), span)?;
+                self.emit(Op::Tuple(count), span);
+            }
+            Expression::Index { object, index, .. } => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.emit(Op::Index, span);
+            }
+            Expression::Match { value, arms, .. } => {
+                let end_jumps = self.match_arms(value, arms, Self::expression)?;
+                let subject = self.subject_slot();
+                self.emit(Op::Get(Variable::Local(subject)), value.span());
+                self.emit(Op::NoMatch, value.span());
+                for jump in end_jumps {
+                    self.patch_jump(jump);
+                }
+                self.end_scope();
+            }
+        }
+        Ok(())
+    }
+
+    // The subject of a match is the only temporary of the scope match_arms leaves open
+    fn subject_slot(&mut self) -> u16 {
+        self.function().scopes.last().unwrap().first_slot as u16
+    }
+
+    /// Pushes the place an assignment writes to
+    fn place(&mut self, assignee: &Expression) -> Result<(), Error> {
+        match assignee {
+            Expression::Variable { name } => {
+                let variable = self.resolve(name)?;
+                self.emit(Op::PlaceVariable(variable), name.span);
+                if let Some(annotation) = self.annotation(name) {
+                    let index = table_index(&self.chunk().annotations, name.span)?;
+                    self.chunk().annotations.push(annotation);
+                    self.emit(Op::PlaceConvert(index), name.span);
+                }
+            }
+            Expression::Member { object, name } => {
+                let index = self.name(name)?;
+                // Vectors are values, so writing a component writes the whole vector back
+                match object.as_ref() {
+                    Expression::Variable { .. }
+                    | Expression::Member { .. }
+                    | Expression::Index { .. } => {
+                        self.place(object)?;
+                        self.emit(Op::PlaceMember(index), name.span);
+                    }
+                    _ => {
+                        self.expression(object)?;
+                        self.emit(Op::PlaceTemporaryMember(index), name.span);
+                    }
+                }
+            }
+            Expression::Index { object, index, .. } => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.emit(Op::PlaceIndex, assignee.span());
+            }
+            Expression::Tuple { elements, span } => {
+                for element in elements {
+                    self.place(element)?;
+                }
+                let count = count(elements.len(), *span)?;
+                self.emit(Op::PlaceTuple(count), *span);
+            }
+            _ => return Err(Error::new("Expected variable".to_string()).with_span(assignee.span())),
+        }
+        Ok(())
+    }
+}
+
+// Operators that are mostly used on numbers get instructions with a fast path for them
+fn binary_op(operator: TokenType) -> Op {
+    match operator {
+        TokenType::Plus => Op::Add,
+        TokenType::Minus => Op::Subtract,
+        TokenType::Star => Op::Multiply,
+        TokenType::Less => Op::Less,
+        TokenType::LessEquals => Op::LessEquals,
+        TokenType::Greater => Op::Greater,
+        TokenType::GreaterEquals => Op::GreaterEquals,
+        operator => Op::Binary(operator),
+    }
+}
+
+fn table_index<T>(table: &[T], span: Span) -> Result<u32, Error> {
+    u32::try_from(table.len())
+        .map_err(|_| Error::new("Too many values in a function".to_string()).with_span(span))
+}
+
+fn count(len: usize, span: Span) -> Result<u32, Error> {
+    u32::try_from(len)
+        .map_err(|_| Error::new("Too many values in an expression".to_string()).with_span(span))
+}
+
+/// Names a statement declares in the scope it is in
+fn declared_names(statement: &Statement) -> Vec<&Token> {
+    match statement {
+        Statement::Let { name, .. }
+        | Statement::Const { name, .. }
+        | Statement::Struct { name, .. }
+        | Statement::Enum { name, .. } => vec![name],
+        Statement::Function { declaration } => declaration.name.iter().collect(),
+        Statement::Destructure { pattern, .. } => {
+            let mut names = Vec::new();
+            pattern_bindings(pattern, &mut names);
+            names
+        }
+        Statement::Documented { statement, .. } => declared_names(statement),
+        _ => Vec::new(),
+    }
+}
+
+/// How a statement declares its names, for the globals code loaded later sees
+fn declared_binding(statement: &Statement) -> Binding {
+    match statement {
+        Statement::Let { mutable: true, .. } | Statement::Destructure { mutable: true, .. } => {
+            Binding::MutableVariable
+        }
+        Statement::Const { .. } => Binding::Constant,
+        Statement::Function { .. } => Binding::Function,
+        Statement::Struct { .. } => Binding::Struct,
+        Statement::Enum { .. } => Binding::Enum,
+        Statement::Documented { statement, .. } => declared_binding(statement),
+        _ => Binding::Variable,
+    }
+}
+
+/// The annotation of the variables a statement declares
+fn declared_annotation(statement: &Statement) -> Option<&TypeAnnotation> {
+    match statement {
+        Statement::Let { annotation, .. } | Statement::Const { annotation, .. } => {
+            annotation.as_ref()
+        }
+        Statement::Documented { statement, .. } => declared_annotation(statement),
+        _ => None,
+    }
+}
+
+/// Variables bound by a pattern, in the order the pattern matching binds them
+fn pattern_bindings<'p>(pattern: &'p Pattern, names: &mut Vec<&'p Token>) {
+    match pattern {
+        Pattern::Binding { name } => names.push(name),
+        Pattern::Tuple { elements, .. } => {
+            for element in elements {
+                pattern_bindings(element, names);
+            }
+        }
+        Pattern::Variant { fields, .. } => {
+            for field in fields {
+                pattern_bindings(field, names);
+            }
+        }
+        Pattern::Or { alternatives } => {
+            for alternative in alternatives {
+                pattern_bindings(alternative, names);
+            }
+        }
+        Pattern::Wildcard { .. } | Pattern::Literal { .. } | Pattern::Range { .. } => {}
+    }
+}
+
+/// Names of the enums variant patterns refer to, in the order their values are pushed
+fn pattern_enums<'p>(pattern: &'p Pattern, enums: &mut Vec<&'p Token>) {
+    match pattern {
+        Pattern::Variant {
+            enum_name, fields, ..
+        } => {
+            enums.push(enum_name);
+            for field in fields {
+                pattern_enums(field, enums);
+            }
+        }
+        Pattern::Tuple { elements, .. } => {
+            for element in elements {
+                pattern_enums(element, enums);
+            }
+        }
+        Pattern::Or { alternatives } => {
+            for alternative in alternatives {
+                pattern_enums(alternative, enums);
+            }
+        }
+        Pattern::Wildcard { .. }
+        | Pattern::Binding { .. }
+        | Pattern::Literal { .. }
+        | Pattern::Range { .. } => {}
+    }
+}
+
+/// A statement or an expression, so bodies of both kinds of match arms can be searched
+#[derive(Clone, Copy)]
+enum Node<'a> {
+    Statement(&'a Statement),
+    Expression(&'a Expression),
+}
+
+impl<'a> From<&'a Statement> for Node<'a> {
+    fn from(statement: &'a Statement) -> Self {
+        Node::Statement(statement)
+    }
+}
+
+impl<'a> From<&'a Expression> for Node<'a> {
+    fn from(expression: &'a Expression) -> Self {
+        Node::Expression(expression)
+    }
+}
+
+/// Names used by the functions created in the given code. Variables with these names live in
+/// cells, which closures share with the scope declaring them.
+fn captured_names<'a>(nodes: impl IntoIterator<Item = Node<'a>>) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for node in nodes {
+        match node {
+            Node::Statement(statement) => statement_names(statement, false, &mut names),
+            Node::Expression(expression) => expression_names(expression, false, &mut names),
+        }
+    }
+    names
+}
+
+fn function_names(declaration: &FunctionDeclaration, names: &mut HashSet<String>) {
+    for statement in &declaration.body {
+        statement_names(statement, true, names);
+    }
+}
+
+fn statement_names(statement: &Statement, in_function: bool, names: &mut HashSet<String>) {
+    let expression = |expression: &Expression, names: &mut HashSet<String>| {
+        expression_names(expression, in_function, names)
+    };
+    match statement {
+        Statement::Expression { expr } | Statement::Print { expr } => expression(expr, names),
+        Statement::Let { initializer, .. }
+        | Statement::Const { initializer, .. }
+        | Statement::Destructure { initializer, .. } => expression(initializer, names),
+        Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            expression(condition, names);
+            statement_names(then_branch, in_function, names);
+            if let Some(else_branch) = else_branch {
+                statement_names(else_branch, in_function, names);
+            }
+        }
+        Statement::Block { statements, .. } => {
+            for statement in statements {
+                statement_names(statement, in_function, names);
+            }
+        }
+        Statement::For { range, body, .. } => {
+            expression(range, names);
+            statement_names(body, in_function, names);
+        }
+        Statement::While {
+            condition, body, ..
+        } => {
+            expression(condition, names);
+            statement_names(body, in_function, names);
+        }
+        Statement::Loop { body, .. } => statement_names(body, in_function, names),
+        Statement::Function { declaration } => function_names(declaration, names),
+        Statement::Return { value, .. } => {
+            if let Some(value) = value {
+                expression(value, names);
+            }
+        }
+        Statement::Match { value, arms, .. } => {
+            expression(value, names);
+            for arm in arms {
+                pattern_names(&arm.pattern, in_function, names);
+                if let Some(guard) = &arm.guard {
+                    expression(guard, names);
+                }
+                statement_names(&arm.body, in_function, names);
+            }
+        }
+        Statement::Documented { statement, .. } => statement_names(statement, in_function, names),
+        Statement::Struct { .. }
+        | Statement::Enum { .. }
+        | Statement::Break { .. }
+        | Statement::Continue { .. } => {}
+    }
+}
+
+fn pattern_names(pattern: &Pattern, in_function: bool, names: &mut HashSet<String>) {
+    if in_function {
+        let mut enums = Vec::new();
+        pattern_enums(pattern, &mut enums);
+        names.extend(enums.into_iter().map(|name| name.lexeme.clone()));
+    }
+}
+
+fn expression_names(expression: &Expression, in_function: bool, names: &mut HashSet<String>) {
+    let visit = |expression: &Expression, names: &mut HashSet<String>| {
+        expression_names(expression, in_function, names)
+    };
+    match expression {
+        Expression::Value { .. } => {}
+        Expression::Variable { name } | Expression::Construct { name, .. } if in_function => {
+            names.insert(name.lexeme.clone());
+            if let Expression::Construct { fields, .. } = expression {
+                for (_, value) in fields {
+                    visit(value, names);
+                }
+            }
+        }
+        Expression::Variable { .. } => {}
+        Expression::Construct { fields, .. } => {
+            for (_, value) in fields {
+                visit(value, names);
+            }
+        }
+        Expression::Unary { right, .. } => visit(right, names),
+        Expression::BinaryExpr { left, right, .. }
+        | Expression::LogicalExpr { left, right, .. } => {
+            visit(left, names);
+            visit(right, names);
+        }
+        Expression::Grouping { expr } => visit(expr, names),
+        Expression::Interpolation { parts, .. } => {
+            for part in parts {
+                visit(part, names);
+            }
+        }
+        Expression::Assign { assignee, value }
+        | Expression::CompoundAssign {
+            assignee, value, ..
+        } => {
+            visit(assignee, names);
+            visit(value, names);
+        }
+        Expression::Member { object, .. } => visit(object, names),
+        Expression::Call {
+            callee, arguments, ..
+        } => {
+            visit(callee, names);
+            for argument in arguments {
+                visit(argument, names);
+            }
+        }
+        Expression::List { elements, .. } | Expression::Tuple { elements, .. } => {
+            for element in elements {
+                visit(element, names);
+            }
+        }
+        Expression::Index { object, index, .. } => {
+            visit(object, names);
+            visit(index, names);
+        }
+        Expression::Function { declaration } => function_names(declaration, names),
+        Expression::Match { value, arms, .. } => {
+            visit(value, names);
+            for arm in arms {
+                pattern_names(&arm.pattern, in_function, names);
+                if let Some(guard) = &arm.guard {
+                    visit(guard, names);
+                }
+                visit(&arm.body, names);
+            }
+        }
+    }
+}
+
+/// Compiles a program to the function the VM runs. Variables that aren't declared by the
+/// program are globals, which are added to `globals` when they don't exist yet.
+pub fn compile(statements: &[Statement], globals: &mut Globals) -> Result<Rc<Function>, Error> {
+    let mut compiler = Compiler {
+        functions: vec![FunctionState::new(None, 0, None)],
+        globals,
+    };
+    compiler.annotate_declarations(statements);
+    compiler.statements(statements)?;
+    let span = statements
+        .last()
+        .map(|statement| statement.span())
+        .unwrap_or_default();
+    compiler.constant(Value::Nil, span)?;
+    compiler.emit(Op::Return, span);
+
+    for statement in statements {
+        for name in declared_names(statement) {
+            compiler
+                .globals
+                .bind(&name.lexeme, declared_binding(statement));
+        }
+    }
+    let function = compiler.functions.pop().unwrap();
+    Ok(Rc::new(Function {
+        name: function.name,
+        arity: 0,
+        slots: function.slots,
+        captures: Vec::new(),
+        chunk: function.chunk,
+    }))
+}
@@ -0,0 +1,523 @@
+use std::rc::Rc;
+
+use glam::{DVec2, DVec3, Vec2, Vec3};
+
+use super::interpreter::{tuple_elements, NativeFunction};
+use crate::parser::{error::Error, parser::Value, resolver::type_name};
+
+/// Converts script values to Rust values, for the arguments of registered functions and the
+/// results of calls into scripts
+pub trait FromValue: Sized {
+    /// The script type the value needs to have, for errors
+    fn type_name() -> String;
+
+    /// Returns the value back when it doesn't have the right type
+    fn from_value(value: Value) -> Result<Self, Value>;
+
+    /// What was expected and what was got instead, for the error when `from_value` rejected
+    /// `value`. Types that only take some values of a script type say which ones.
+    fn mismatch(value: &Value) -> (String, String) {
+        (Self::type_name(), type_name(value))
+    }
+}
+
+/// Converts Rust values to script values
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+impl FromValue for Value {
+    fn type_name() -> String {
+        "any".to_string()
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+/// Ignores the value, for calling functions whose result isn't needed
+impl FromValue for () {
+    fn type_name() -> String {
+        "any".to_string()
+    }
+
+    fn from_value(_: Value) -> Result<Self, Value> {
+        Ok(())
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Nil
+    }
+}
+
+impl FromValue for i64 {
+    fn type_name() -> String {
+        "int".to_string()
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Integer(int) => Ok(int),
+            value => Err(value),
+        }
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Integer(self)
+    }
+}
+
+impl FromValue for i32 {
+    fn type_name() -> String {
+        i64::type_name()
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Integer(int) => i32::try_from(int).map_err(|_| value),
+            value => Err(value),
+        }
+    }
+
+    fn mismatch(value: &Value) -> (String, String) {
+        match value {
+            Value::Integer(int) => ("an int that fits in 32 bits".to_string(), int.to_string()),
+            value => (Self::type_name(), type_name(value)),
+        }
+    }
+}
+
+impl IntoValue for i32 {
+    fn into_value(self) -> Value {
+        Value::Integer(self as i64)
+    }
+}
+
+// Integers are promoted to reals like in arithmetic
+impl FromValue for f64 {
+    fn type_name() -> String {
+        "real".to_string()
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Integer(int) => Ok(int as f64),
+            Value::Real(real) => Ok(real),
+            value => Err(value),
+        }
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Real(self)
+    }
+}
+
+impl FromValue for f32 {
+    fn type_name() -> String {
+        f64::type_name()
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        f64::from_value(value).map(|real| real as f32)
+    }
+}
+
+impl IntoValue for f32 {
+    fn into_value(self) -> Value {
+        Value::Real(self as f64)
+    }
+}
+
+impl FromValue for bool {
+    fn type_name() -> String {
+        "bool".to_string()
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Boolean(bool) => Ok(bool),
+            value => Err(value),
+        }
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromValue for String {
+    fn type_name() -> String {
+        "string".to_string()
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        match value {
+            Value::String(string) => Ok(string),
+            value => Err(value),
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl FromValue for DVec2 {
+    fn type_name() -> String {
+        "vec2".to_string()
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Vec2(vector) => Ok(vector),
+            value => Err(value),
+        }
+    }
+}
+
+impl IntoValue for DVec2 {
+    fn into_value(self) -> Value {
+        Value::Vec2(self)
+    }
+}
+
+impl FromValue for DVec3 {
+    fn type_name() -> String {
+        "vec3".to_string()
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Vec3(vector) => Ok(vector),
+            value => Err(value),
+        }
+    }
+}
+
+impl IntoValue for DVec3 {
+    fn into_value(self) -> Value {
+        Value::Vec3(self)
+    }
+}
+
+// The renderer works with single precision vectors
+impl FromValue for Vec2 {
+    fn type_name() -> String {
+        DVec2::type_name()
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        DVec2::from_value(value).map(|vector| vector.as_vec2())
+    }
+}
+
+impl IntoValue for Vec2 {
+    fn into_value(self) -> Value {
+        Value::Vec2(self.as_dvec2())
+    }
+}
+
+impl FromValue for Vec3 {
+    fn type_name() -> String {
+        DVec3::type_name()
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        DVec3::from_value(value).map(|vector| vector.as_vec3())
+    }
+}
+
+impl IntoValue for Vec3 {
+    fn into_value(self) -> Value {
+        Value::Vec3(self.as_dvec3())
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn type_name() -> String {
+        format!("{} or nil", T::type_name())
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Nil => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+
+    fn mismatch(value: &Value) -> (String, String) {
+        T::mismatch(value)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(value) => value.into_value(),
+            None => Value::Nil,
+        }
+    }
+}
+
+// Copies the elements, so changing the vector doesn't change the list
+impl<T: FromValue> FromValue for Vec<T> {
+    fn type_name() -> String {
+        format!("list of {}", T::type_name())
+    }
+
+    fn from_value(value: Value) -> Result<Self, Value> {
+        let Value::List(list) = &value else {
+            return Err(value);
+        };
+        let elements = list.borrow().clone();
+        elements
+            .into_iter()
+            .map(T::from_value)
+            .collect::<Result<_, _>>()
+            .map_err(|_| value)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::list(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+macro_rules! tuple_conversions {
+    ($($element:ident $value:ident),+) => {
+        impl<$($element: FromValue),+> FromValue for ($($element,)+) {
+            fn type_name() -> String {
+                let elements = [$($element::type_name()),+];
+                match elements.len() {
+                    1 => format!("({},)", elements[0]),
+                    _ => format!("({})", elements.join(", ")),
+                }
+            }
+
+            fn from_value(value: Value) -> Result<Self, Value> {
+                let len = [$(stringify!($element)),+].len();
+                let Ok(elements) = tuple_elements(value.clone(), len) else {
+                    return Err(value);
+                };
+                let mut elements = elements.iter().cloned();
+                $(let $value = $element::from_value(elements.next().unwrap()).map_err(|_| value.clone())?;)+
+                Ok(($($value,)+))
+            }
+        }
+
+        impl<$($element: IntoValue),+> IntoValue for ($($element,)+) {
+            fn into_value(self) -> Value {
+                let ($($value,)+) = self;
+                Value::Tuple(Rc::new([$($value.into_value()),+]))
+            }
+        }
+
+        impl<$($element: IntoValue),+> IntoArguments for ($($element,)+) {
+            fn into_arguments(self) -> Vec<Value> {
+                let ($($value,)+) = self;
+                vec![$($value.into_value()),+]
+            }
+        }
+    };
+}
+
+/// Arguments for calling a script function, a tuple of values that convert to script values
+pub trait IntoArguments {
+    fn into_arguments(self) -> Vec<Value>;
+}
+
+impl IntoArguments for () {
+    fn into_arguments(self) -> Vec<Value> {
+        Vec::new()
+    }
+}
+
+impl IntoArguments for Vec<Value> {
+    fn into_arguments(self) -> Vec<Value> {
+        self
+    }
+}
+
+tuple_conversions!(A a);
+tuple_conversions!(A a, B b);
+tuple_conversions!(A a, B b, C c);
+tuple_conversions!(A a, B b, C c, D d);
+tuple_conversions!(A a, B b, C c, D d, E e);
+tuple_conversions!(A a, B b, C c, D d, E e, F f);
+
+/// What a registered function returns, a value or a result with an error for the script
+pub trait NativeResult {
+    fn into_result(self) -> Result<Value, Error>;
+}
+
+impl<T: IntoValue> NativeResult for T {
+    fn into_result(self) -> Result<Value, Error> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue> NativeResult for Result<T, Error> {
+    fn into_result(self) -> Result<Value, Error> {
+        self.map(IntoValue::into_value)
+    }
+}
+
+/// A Rust closure that can be called from scripts, `Args` are the types of its parameters
+pub trait IntoNative<Args> {
+    fn into_native(self, name: &str) -> NativeFunction;
+}
+
+fn argument<T: FromValue>(value: Value, index: usize) -> Result<T, Error> {
+    T::from_value(value).map_err(|value| {
+        let (expected, got) = T::mismatch(&value);
+        Error::new(format!(
+            "Expected {} for argument {} but got {}",
+            expected,
+            index + 1,
+            got
+        ))
+    })
+}
+
+/// Converts the result of a call into a script function
+pub fn return_value<T: FromValue>(value: Value) -> Result<T, Error> {
+    T::from_value(value).map_err(|value| {
+        let (expected, got) = T::mismatch(&value);
+        Error::new(format!(
+            "Expected {} for the return value but got {}",
+            expected, got
+        ))
+    })
+}
+
+macro_rules! native_functions {
+    ($($argument:ident $value:ident),*) => {
+        impl<F, R, $($argument),*> IntoNative<($($argument,)*)> for F
+        where
+            F: Fn($($argument),*) -> R + 'static,
+            R: NativeResult,
+            $($argument: FromValue,)*
+        {
+            #[allow(unused_mut, unused_variables)]
+            fn into_native(self, name: &str) -> NativeFunction {
+                let arity = <[&str]>::len(&[$(stringify!($argument)),*]);
+                NativeFunction {
+                    name: name.to_string(),
+                    arity,
+                    // The VM checks the number of arguments before calling
+                    function: Box::new(move |arguments| {
+                        let mut arguments = arguments.into_iter().enumerate();
+                        $(
+                            let (index, $value) = arguments.next().unwrap();
+                            let $value = argument::<$argument>($value, index)?;
+                        )*
+                        self($($value),*).into_result()
+                    }),
+                }
+            }
+        }
+    };
+}
+
+native_functions!();
+native_functions!(A1 a1);
+native_functions!(A1 a1, A2 a2);
+native_functions!(A1 a1, A2 a2, A3 a3);
+native_functions!(A1 a1, A2 a2, A3 a3, A4 a4);
+native_functions!(A1 a1, A2 a2, A3 a3, A4 a4, A5 a5);
+native_functions!(A1 a1, A2 a2, A3 a3, A4 a4, A5 a5, A6 a6);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(f64::from_value(Value::Integer(2)), Ok(2.0));
+        assert_eq!(i64::from_value(Value::Real(2.0)), Err(Value::Real(2.0)));
+        assert_eq!(
+            <(i64, String)>::from_value((1, "a").into_value()),
+            Ok((1, "a".to_string()))
+        );
+        assert_eq!(
+            Vec::<f64>::from_value(vec![1.5, 2.0].into_value()),
+            Ok(vec![1.5, 2.0])
+        );
+        assert!(Vec::<i64>::from_value(vec![1.5].into_value()).is_err());
+        assert_eq!(Option::<bool>::from_value(Value::Nil), Ok(None));
+        assert_eq!(
+            Vec3::from_value(DVec3::new(1.0, 2.0, 3.0).into_value()),
+            Ok(Vec3::new(1.0, 2.0, 3.0))
+        );
+        assert_eq!(<(i64, f64)>::type_name(), "(int, real)");
+        assert_eq!(Option::<Vec<i64>>::type_name(), "list of int or nil");
+    }
+
+    #[test]
+    fn test_native_functions() {
+        let add = (|a: i64, b: f64| a as f64 + b).into_native("add");
+        assert_eq!(add.arity, 2);
+        assert_eq!(
+            (add.function)(vec![Value::Integer(1), Value::Integer(2)]),
+            Ok(Value::Real(3.0))
+        );
+        let error = (add.function)(vec![Value::Integer(1), Value::Boolean(true)]).unwrap_err();
+        assert_eq!(error.message, "Expected real for argument 2 but got bool");
+
+        let half = (|n: i32, m: Option<i32>| n / 2 + m.unwrap_or(0)).into_native("half");
+        assert_eq!(
+            (half.function)(vec![Value::Integer(4), Value::Nil]),
+            Ok(Value::Integer(2))
+        );
+        let error = (half.function)(vec![Value::Integer(1 << 40), Value::Nil]).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Expected an int that fits in 32 bits for argument 1 but got 1099511627776"
+        );
+        let error = (half.function)(vec![Value::Integer(0), Value::Integer(-1 << 40)]).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Expected an int that fits in 32 bits for argument 2 but got -1099511627776"
+        );
+        let error = (half.function)(vec![Value::Real(1.0), Value::Nil]).unwrap_err();
+        assert_eq!(error.message, "Expected int for argument 1 but got real");
+
+        let check = (|n: i64| {
+            if n < 0 {
+                return Err(Error::new("Negative".to_string()));
+            }
+            Ok(n)
+        })
+        .into_native("check");
+        assert_eq!(
+            (check.function)(vec![Value::Integer(-1)])
+                .unwrap_err()
+                .message,
+            "Negative"
+        );
+    }
+}
@@ -1,333 +1,2342 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, fmt::Debug, fmt::Write as _, io::Write, rc::Rc};
 
+use super::{
+    builtins::{self, list_index},
+    math,
+    random::{self, Random},
+    range::Range,
+    vector,
+};
 use crate::parser::{
     error::Error,
-    parser::{Expression, Statement, Value},
+    parser::{
+        Expression, FunctionDeclaration, MatchArm, Pattern, Statement, TypeAnnotation, Value,
+        VariantDeclaration,
+    },
+    span::Span,
     token::{Token, TokenType},
 };
 
+// Every script call recurses through the tree walker. Calling from inside nested blocks and
+// expressions takes up to about 15 KB of native stack per call in unoptimized builds, so
+// this keeps runaway recursion an error even on a 2 MB thread.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 64;
+
+pub type Scope = Rc<RefCell<Variables>>;
+
+/// The variables declared in one scope
+#[derive(Debug, Default)]
+pub struct Variables {
+    values: HashMap<String, Value>,
+    // Annotations of variables that convert what is assigned to them, like an int to a real
+    annotations: HashMap<String, TypeAnnotation>,
+    // Functions declared in a local scope. Their closure is made when they are looked up,
+    // because a closure stored here would keep alive the scope it captured.
+    functions: HashMap<String, Rc<FunctionDeclaration>>,
+}
+
+impl Variables {
+    fn contains(&self, name: &str) -> bool {
+        self.values.contains_key(name) || self.functions.contains_key(name)
+    }
+
+    fn declare_function(&mut self, name: &str, declaration: &Rc<FunctionDeclaration>) {
+        self.values.remove(name);
+        self.annotations.remove(name);
+        self.functions.insert(name.to_string(), declaration.clone());
+    }
+
+    fn declare(&mut self, name: &str, value: Value, annotation: Option<&TypeAnnotation>) {
+        self.functions.remove(name);
+        let value = match annotation.filter(|annotation| annotation.converts()) {
+            Some(annotation) => {
+                self.annotations
+                    .insert(name.to_string(), annotation.clone());
+                annotation.convert(value)
+            }
+            None => {
+                self.annotations.remove(name);
+                value
+            }
+        };
+        self.values.insert(name.to_string(), value);
+    }
+
+    fn assign(&mut self, name: &str, value: Value) -> bool {
+        if self.functions.remove(name).is_none() && !self.values.contains_key(name) {
+            return false;
+        }
+        let value = match self.annotations.get(name) {
+            Some(annotation) => annotation.convert(value),
+            None => value,
+        };
+        self.values.insert(name.to_string(), value);
+        true
+    }
+}
+
 #[derive(Debug)]
-pub struct Environment<'a> {
-    pub variables: HashMap<&'a str, Value>,
+pub struct Environment {
+    variables: Variables,
+    // Functions implemented in Rust, they can be shadowed by script variables
+    natives: HashMap<String, Value>,
+    // Shared with the random natives, so hosts can save and restore it
+    random: Random,
+    // Local scopes of the blocks we are currently in, innermost last.
+    // They are reference counted, because closures created inside of them share them.
+    scopes: Vec<Scope>,
 }
 
-impl<'a> Environment<'a> {
+impl Environment {
     pub fn new() -> Self {
-        Self {
-            variables: HashMap::new(),
+        let mut environment = Self {
+            variables: Variables::default(),
+            natives: HashMap::new(),
+            random: Random::from_time(),
+            scopes: Vec::new(),
+        };
+        builtins::define(&mut environment);
+        vector::define(&mut environment);
+        math::define(&mut environment);
+        let random = environment.random.clone();
+        random::define(&mut environment, &random);
+        environment
+    }
+
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(Vec<Value>) -> Result<Value, Error> + 'static,
+    ) {
+        let native = NativeFunction {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        };
+        self.natives
+            .insert(name.to_string(), Value::NativeFunction(Rc::new(native)));
+    }
+
+    pub fn random(&self) -> &Random {
+        &self.random
+    }
+
+    /// Defines a value like `PI` that scripts can read but not assign to
+    pub fn define_constant(&mut self, name: &str, value: Value) {
+        self.natives.insert(name.to_string(), value);
+    }
+
+    /// Whether `name` refers to a constant defined with `define_constant`
+    pub fn is_constant(&self, name: &str) -> bool {
+        !self.variables.values.contains_key(name)
+            && matches!(self.natives.get(name), Some(value) if !matches!(value, Value::NativeFunction(_)))
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Rc::default());
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub fn declare(&mut self, name: &str, value: Value) {
+        self.declare_annotated(name, value, None);
+    }
+
+    /// Declares a variable with the type it was annotated with. Values stored in it are
+    /// converted to that type, so an int declared or assigned as a real becomes a real.
+    pub fn declare_annotated(
+        &mut self,
+        name: &str,
+        value: Value,
+        annotation: Option<&TypeAnnotation>,
+    ) {
+        match self.scopes.last() {
+            Some(scope) => scope.borrow_mut().declare(name, value, annotation),
+            None => self.variables.declare(name, value, annotation),
+        }
+    }
+
+    /// Declares a function with a name. A function of a local scope is kept as its declaration,
+    /// while top level functions capture no scopes and are stored like any other value.
+    pub fn declare_function(&mut self, name: &str, declaration: &Rc<FunctionDeclaration>) {
+        match self.scopes.last() {
+            Some(scope) => scope.borrow_mut().declare_function(name, declaration),
+            None => {
+                let closure = Closure {
+                    declaration: declaration.clone(),
+                    scopes: Vec::new(),
+                };
+                self.variables
+                    .declare(name, Value::Function(Rc::new(closure)), None);
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        for (depth, scope) in self.scopes.iter().enumerate().rev() {
+            let scope = scope.borrow();
+            if let Some(value) = scope.values.get(name) {
+                return Some(value.clone());
+            }
+            if let Some(declaration) = scope.functions.get(name) {
+                // The function sees the scopes up to the one it was declared in
+                return Some(Value::Function(Rc::new(Closure {
+                    declaration: declaration.clone(),
+                    scopes: self.scopes[..=depth].to_vec(),
+                })));
+            }
+        }
+        self.variables
+            .values
+            .get(name)
+            .cloned()
+            .or_else(|| self.natives.get(name).cloned())
+    }
+
+    /// Natives and top level variables, variables last so they win over natives of the same name
+    pub fn globals(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.natives.iter().chain(&self.variables.values)
+    }
+
+    /// Overwrites the innermost binding of `name`. Returns false if it was never declared.
+    pub fn assign(&mut self, name: &str, value: Value) -> bool {
+        match self
+            .scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.borrow().contains(name))
+        {
+            Some(scope) => scope.borrow_mut().assign(name, value),
+            None => self.variables.assign(name, value),
         }
     }
 }
 
-impl<'a> Default for Environment<'a> {
+impl Default for Environment {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// A function of the tree walker with the scopes it was created in.
+///
+/// The scopes are kept alive by the closure. Functions declared in a scope, with `fn` or by
+/// binding a function literal with `let`, are kept in it as their declaration, so the scope
+/// doesn't hold on to a closure holding on to the scope. Storing a closure somewhere else in
+/// the scopes it captured, like in a list declared next to it, still forms a reference cycle
+/// that is never freed.
+pub struct Closure {
+    pub declaration: Rc<FunctionDeclaration>,
+    // Scopes that were visible where the function was created
+    pub scopes: Vec<Scope>,
+}
+
+impl Debug for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Captured scopes can contain the closure itself, so they are not printed
+        match &self.declaration.name {
+            Some(name) => write!(f, "Closure({})", name.lexeme),
+            None => write!(f, "Closure"),
+        }
+    }
+}
+
+// A local function gets a new closure every time it is looked up. Closures of the same
+// declaration with the same scopes behave the same, so they are equal.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.declaration, &other.declaration)
+            && self.scopes.len() == other.scopes.len()
+            && self
+                .scopes
+                .iter()
+                .zip(&other.scopes)
+                .all(|(scope, other)| Rc::ptr_eq(scope, other))
+    }
+}
+
+#[derive(Debug)]
+pub struct StructType {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+impl PartialEq for StructType {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl StructType {
+    pub fn field_index(&self, field: &str) -> Option<usize> {
+        self.fields.iter().position(|other| other == field)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Instance {
+    pub struct_type: Rc<StructType>,
+    // Values of the fields, in the order of `struct_type.fields`
+    pub fields: Vec<Value>,
+}
+
+#[derive(Debug)]
+pub struct EnumType {
+    pub name: String,
+    pub variants: Vec<VariantType>,
+}
+
+impl PartialEq for EnumType {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl EnumType {
+    pub fn variant_index(&self, variant: &str) -> Option<usize> {
+        self.variants.iter().position(|other| other.name == variant)
+    }
+}
+
 #[derive(Debug)]
-struct Interpreter<'a> {
-    environment: &'a mut Environment<'a>,
+pub struct VariantType {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Variant {
+    pub enum_type: Rc<EnumType>,
+    // Index into `enum_type.variants`
+    pub index: usize,
+    pub payload: Vec<Value>,
+}
+
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    pub function: Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>,
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NativeFunction({})", self.name)
+    }
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Target of an assignment, with the object and index already evaluated
+enum Place {
+    Variable(Token),
+    Field(Rc<RefCell<Instance>>, usize),
+    Element(Rc<RefCell<Vec<Value>>>, usize),
+    // Components of the vector stored in another place
+    Swizzle(Box<Place>, Vec<usize>),
+    // `a, b = b, a` writes the elements of a tuple to several places
+    Tuple(Vec<Place>),
+}
+
+enum ControlFlow {
+    Normal,
+    Return(Value),
+    Break(Option<String>),
+    Continue(Option<String>),
+}
+
+impl ControlFlow {
+    /// What a loop labeled `label` does after its body finished with this flow,
+    /// `None` keeps looping and `Some` leaves the loop with the returned flow
+    fn exit_loop(self, label: &Option<Token>) -> Option<ControlFlow> {
+        let targets = |target: &Option<String>| match target {
+            Some(target) => label.as_ref().is_some_and(|label| &label.lexeme == target),
+            None => true,
+        };
+
+        match self {
+            ControlFlow::Normal => None,
+            ControlFlow::Continue(target) if targets(&target) => None,
+            ControlFlow::Break(target) if targets(&target) => Some(ControlFlow::Normal),
+            flow => Some(flow),
+        }
+    }
+}
+
+pub struct Interpreter<'a> {
+    environment: &'a mut Environment,
+    output: &'a mut dyn Write,
+    call_depth: usize,
+    max_call_depth: usize,
 }
 
 impl<'a> Interpreter<'a> {
-    fn new(environment: &'a mut Environment<'a>) -> Self {
-        Self { environment }
+    pub fn new(environment: &'a mut Environment, output: &'a mut dyn Write) -> Self {
+        Self {
+            environment,
+            output,
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+        }
+    }
+
+    /// Limits how deep script functions can call each other. The default of 64 is what fits on
+    /// a 2 MB thread, the smallest stack Rust gives a thread by default, in an unoptimized
+    /// build, where a call from inside nested blocks and expressions takes up to about 15 KB.
+    /// Hosts that run scripts on a thread with a bigger stack can allow more.
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    pub fn interpret(&mut self, statements: &[Statement]) -> Result<(), Error> {
+        for statement in statements {
+            self.execute(statement)?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, statement: &Statement) -> Result<ControlFlow, Error> {
+        self.execute_statement(statement)
+            .map_err(|err| err.or_span(statement.span()))
     }
 
-    fn execute(&mut self, statement: &'a Statement) -> Result<(), Error> {
+    fn execute_statement(&mut self, statement: &Statement) -> Result<ControlFlow, Error> {
+        // Every statement is executed by its own method, so the stack frame of this one stays
+        // small. It is part of every script call, which limits how deep scripts can recurse.
         match statement {
-            Statement::Expression { expr } => {
-                self.evaluate(expr)?;
-            }
-            Statement::Print { expr } => {
-                let value = self.evaluate(expr)?;
-                println!("{}", value);
-            }
+            Statement::Expression { expr } => self.evaluate(expr).map(|_| ControlFlow::Normal),
+            Statement::Print { expr } => self.print_statement(expr),
             Statement::If {
                 condition,
                 then_branch,
                 else_branch,
-            } => {
-                let condition = self.evaluate(condition)?;
-
-                let truthiness = match condition {
-                    Value::Boolean(bool) => bool,
-                    _ => {
-                        return Err(Error::new(
-                            "Expected boolean in an if condition".to_string(),
-                        ))
-                    }
-                };
-
-                if truthiness {
-                    self.execute(then_branch)?;
-                } else if let Some(else_branch) = else_branch {
-                    self.execute(else_branch)?;
-                }
-            }
-            Statement::Block { statements } => {
-                // TODO: Add nested scopes to environments
-                for statement in statements {
-                    self.execute(statement)?;
-                }
+            } => self.if_statement(condition, then_branch, else_branch.as_deref()),
+            Statement::Let {
+                name,
+                annotation,
+                initializer,
+                ..
             }
+            | Statement::Const {
+                name,
+                annotation,
+                initializer,
+            } => self.let_statement(name, annotation.as_ref(), initializer),
+            Statement::Destructure {
+                pattern,
+                initializer,
+                ..
+            } => self.destructure_statement(pattern, initializer),
+            Statement::Block { statements, .. } => self.block_statement(statements),
             Statement::For {
+                label,
                 variable,
                 range,
                 body,
-            } => self.for_statement(variable, range, body)?,
-        };
-        Ok(())
+            } => self.for_statement(label, variable, range, body),
+            Statement::While {
+                label,
+                condition,
+                body,
+            } => self.while_statement(label, condition, body),
+            Statement::Loop { label, body } => self.loop_statement(label, body),
+            Statement::Break { label, .. } => Ok(ControlFlow::Break(label_name(label))),
+            Statement::Continue { label, .. } => Ok(ControlFlow::Continue(label_name(label))),
+            Statement::Function { declaration } => self.function_statement(declaration),
+            Statement::Struct { name, fields, .. } => self.struct_statement(name, fields),
+            Statement::Enum { name, variants, .. } => self.enum_statement(name, variants),
+            Statement::Return { value, .. } => self.return_statement(value.as_deref()),
+            Statement::Match { value, arms, .. } => self.match_statement(value, arms),
+            Statement::Documented { statement, .. } => self.execute(statement),
+        }
     }
 
-    fn for_statement(
-        &mut self,
-        name: &'a Token,
-        range: &'a Expression,
-        body: &'a Statement,
-    ) -> Result<(), Error> {
-        let range = self.evaluate(range)?;
-
-        match range {
-            Value::Range(a, b) => {
-                let mut i = a;
-                while i < b {
-                    self.environment
-                        .variables
-                        .insert(&name.lexeme, Value::Integer(i));
-                    self.execute(body)?;
-                    i += 1;
-                }
+    fn execute_statements(&mut self, statements: &[Statement]) -> Result<ControlFlow, Error> {
+        for statement in statements {
+            match self.execute(statement)? {
+                ControlFlow::Normal => {}
+                flow => return Ok(flow),
             }
-            _ => return Err(Error::new("Expected range".to_string())),
         }
-
-        Ok(())
+        Ok(ControlFlow::Normal)
     }
 
-    fn evaluate(&mut self, expression: &'a Expression) -> Result<Value, Error> {
-        match expression {
-            Expression::Value(value) => self.evaluate_value(*value),
-            Expression::Unary { operator, right } => self.evaluate_unary(operator, right),
-            Expression::BinaryExpr {
-                operator,
-                left,
-                right,
-            } => self.evaluate_binary(operator, left, right),
-            Expression::LogicalExpr {
-                operator,
-                left,
-                right,
-            } => self.evaluate_logical(operator, left, right),
-            Expression::Grouping { expr } => self.evaluate(expr),
-            Expression::Assign { assignee, value } => self.evaluate_assign(assignee, value),
-            Expression::Variable { name, member } => self.evaluate_variable(name, member),
+    fn let_statement(
+        &mut self,
+        name: &Token,
+        annotation: Option<&TypeAnnotation>,
+        initializer: &Expression,
+    ) -> Result<ControlFlow, Error> {
+        if let Expression::Function { declaration } = initializer {
+            self.environment.declare_function(&name.lexeme, declaration);
+            return Ok(ControlFlow::Normal);
         }
+        let value = self.evaluate(initializer)?;
+        self.environment
+            .declare_annotated(&name.lexeme, value, annotation);
+        Ok(ControlFlow::Normal)
     }
 
-    fn evaluate_value(&mut self, value: Value) -> Result<Value, Error> {
-        match value {
-            Value::Integer(int) => Ok(Value::Integer(int)),
-            Value::Boolean(bool) => Ok(Value::Boolean(bool)),
-            Value::Real(real) => Ok(Value::Real(real)),
-            Value::Range(a, b) => Ok(Value::Range(a, b)),
+    fn block_statement(&mut self, statements: &[Statement]) -> Result<ControlFlow, Error> {
+        self.environment.push_scope();
+        let result = self.execute_statements(statements);
+        self.environment.pop_scope();
+        result
+    }
+
+    fn function_statement(
+        &mut self,
+        declaration: &Rc<FunctionDeclaration>,
+    ) -> Result<ControlFlow, Error> {
+        if let Some(name) = &declaration.name {
+            self.environment.declare_function(&name.lexeme, declaration);
         }
+        Ok(ControlFlow::Normal)
     }
 
-    fn evaluate_unary(&mut self, operator: &Token, right: &'a Expression) -> Result<Value, Error> {
-        let right = self.evaluate(right)?;
+    fn print_statement(&mut self, expr: &Expression) -> Result<ControlFlow, Error> {
+        let value = self.evaluate(expr)?;
+        writeln!(self.output, "{}", value)
+            .map_err(|err| Error::new(format!("Failed to print: {}", err)))?;
+        Ok(ControlFlow::Normal)
+    }
+
+    fn if_statement(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Statement,
+        else_branch: Option<&Statement>,
+    ) -> Result<ControlFlow, Error> {
+        let truthiness = match self.evaluate(condition)? {
+            Value::Boolean(bool) => bool,
+            _ => {
+                return Err(
+                    Error::new("Expected boolean in an if condition".to_string())
+                        .with_span(condition.span()),
+                )
+            }
+        };
 
-        match operator.token_type {
-            TokenType::Minus => match right {
-                Value::Integer(int) => Ok(Value::Integer(-int)),
-                Value::Real(real) => Ok(Value::Real(-real)),
-                _ => Err(Error::new("Expected number".to_string())),
-            },
-            TokenType::Bang => match right {
-                Value::Boolean(bool) => Ok(Value::Boolean(!bool)),
-                _ => Err(Error::new("Expected boolean".to_string())),
-            },
-            _ => Err(Error::new("Expected unary operator".to_string())),
+        if truthiness {
+            return self.execute(then_branch);
+        } else if let Some(else_branch) = else_branch {
+            return self.execute(else_branch);
         }
+        Ok(ControlFlow::Normal)
     }
 
-    fn evaluate_binary(
+    fn destructure_statement(
         &mut self,
-        operator: &'a Token,
-        left: &'a Expression,
-        right: &'a Expression,
-    ) -> Result<Value, Error> {
-        let left = self.evaluate(left)?;
-        let right = self.evaluate(right)?;
+        pattern: &Pattern,
+        initializer: &Expression,
+    ) -> Result<ControlFlow, Error> {
+        let value = self.evaluate(initializer)?;
+        let mut bindings = Vec::new();
+        destructure(pattern, value, &mut bindings)?;
+        for (name, value) in bindings {
+            self.environment.declare(&name, value);
+        }
+        Ok(ControlFlow::Normal)
+    }
 
-        match operator.token_type {
-            TokenType::Star => match (left, right) {
-                (Value::Integer(left), Value::Integer(right)) => Ok(Value::Integer(left * right)),
-                (Value::Real(left), Value::Real(right)) => Ok(Value::Real(left * right)),
-                (Value::Integer(left), Value::Real(right)) => Ok(Value::Real(left as f64 * right)),
-                (Value::Real(left), Value::Integer(right)) => Ok(Value::Real(left * right as f64)),
-                _ => Err(Error::new("Expected number".to_string())),
-            },
-            TokenType::Slash => match (left, right) {
-                (Value::Integer(left), Value::Integer(right)) => Ok(Value::Integer(left / right)),
-                (Value::Real(left), Value::Real(right)) => Ok(Value::Real(left / right)),
-                (Value::Integer(left), Value::Real(right)) => Ok(Value::Real(left as f64 / right)),
-                (Value::Real(left), Value::Integer(right)) => Ok(Value::Real(left / right as f64)),
-                _ => Err(Error::new("Expected number".to_string())),
-            },
-            TokenType::Plus => match (left, right) {
-                (Value::Integer(left), Value::Integer(right)) => Ok(Value::Integer(left + right)),
-                (Value::Real(left), Value::Real(right)) => Ok(Value::Real(left + right)),
-                (Value::Integer(left), Value::Real(right)) => Ok(Value::Real(left as f64 + right)),
-                (Value::Real(left), Value::Integer(right)) => Ok(Value::Real(left + right as f64)),
-                _ => Err(Error::new("Expected number".to_string())),
-            },
-            TokenType::Minus => match (left, right) {
-                (Value::Integer(left), Value::Integer(right)) => Ok(Value::Integer(left - right)),
-                (Value::Real(left), Value::Real(right)) => Ok(Value::Real(left - right)),
-                (Value::Integer(left), Value::Real(right)) => Ok(Value::Real(left as f64 - right)),
-                (Value::Real(left), Value::Integer(right)) => Ok(Value::Real(left - right as f64)),
-                _ => Err(Error::new("Expected number".to_string())),
-            },
-            TokenType::EqualsEquals => Ok(Value::Boolean(left == right)),
-            TokenType::BangEquals => Ok(Value::Boolean(left != right)),
-            TokenType::Less => match (left, right) {
-                (Value::Integer(left), Value::Integer(right)) => Ok(Value::Boolean(left < right)),
-                (Value::Real(left), Value::Real(right)) => Ok(Value::Boolean(left < right)),
-                (Value::Integer(left), Value::Real(right)) => {
-                    Ok(Value::Boolean((left as f64) < right))
-                }
-                (Value::Real(left), Value::Integer(right)) => {
-                    Ok(Value::Boolean(left < right as f64))
-                }
-                _ => Err(Error::new("Expected number".to_string())),
-            },
-            TokenType::LessEquals => match (left, right) {
-                (Value::Integer(left), Value::Integer(right)) => Ok(Value::Boolean(left <= right)),
-                (Value::Real(left), Value::Real(right)) => Ok(Value::Boolean(left <= right)),
-                (Value::Integer(left), Value::Real(right)) => {
-                    Ok(Value::Boolean(left as f64 <= right))
-                }
-                (Value::Real(left), Value::Integer(right)) => {
-                    Ok(Value::Boolean(left <= right as f64))
-                }
-                _ => Err(Error::new("Expected number".to_string())),
-            },
-            TokenType::Greater => match (left, right) {
-                (Value::Integer(left), Value::Integer(right)) => Ok(Value::Boolean(left > right)),
-                (Value::Real(left), Value::Real(right)) => Ok(Value::Boolean(left > right)),
-                (Value::Integer(left), Value::Real(right)) => {
-                    Ok(Value::Boolean(left as f64 > right))
-                }
-                (Value::Real(left), Value::Integer(right)) => {
-                    Ok(Value::Boolean(left > right as f64))
-                }
-                _ => Err(Error::new("Expected number".to_string())),
-            },
-            TokenType::GreaterEquals => match (left, right) {
-                (Value::Integer(left), Value::Integer(right)) => Ok(Value::Boolean(left >= right)),
-                (Value::Real(left), Value::Real(right)) => Ok(Value::Boolean(left >= right)),
-                (Value::Integer(left), Value::Real(right)) => {
-                    Ok(Value::Boolean(left as f64 >= right))
-                }
-                (Value::Real(left), Value::Integer(right)) => {
-                    Ok(Value::Boolean(left >= right as f64))
-                }
-                _ => Err(Error::new("Expected number".to_string())),
-            },
-            TokenType::DotDot => match (left, right) {
-                (Value::Integer(left), Value::Integer(right)) => Ok(Value::Range(left, right)),
-                (Value::Real(left), Value::Real(right)) => Ok(Value::Range(left as i64, right as i64)),
-                (Value::Integer(left), Value::Real(right)) => {
-                    Ok(Value::Range(left, right as i64))
-                }
-                (Value::Real(left), Value::Integer(right)) => {
-                    Ok(Value::Range(left as i64, right))
+    fn struct_statement(&mut self, name: &Token, fields: &[Token]) -> Result<ControlFlow, Error> {
+        let struct_type = StructType {
+            name: name.lexeme.clone(),
+            fields: fields.iter().map(|field| field.lexeme.clone()).collect(),
+        };
+        self.environment
+            .declare(&name.lexeme, Value::Struct(Rc::new(struct_type)));
+        Ok(ControlFlow::Normal)
+    }
+
+    fn enum_statement(
+        &mut self,
+        name: &Token,
+        variants: &[VariantDeclaration],
+    ) -> Result<ControlFlow, Error> {
+        let enum_type = EnumType {
+            name: name.lexeme.clone(),
+            variants: variants
+                .iter()
+                .map(|variant| VariantType {
+                    name: variant.name.lexeme.clone(),
+                    fields: variant
+                        .fields
+                        .iter()
+                        .map(|field| field.lexeme.clone())
+                        .collect(),
+                })
+                .collect(),
+        };
+        self.environment
+            .declare(&name.lexeme, Value::Enum(Rc::new(enum_type)));
+        Ok(ControlFlow::Normal)
+    }
+
+    fn return_statement(&mut self, value: Option<&Expression>) -> Result<ControlFlow, Error> {
+        let value = match value {
+            Some(value) => self.evaluate(value)?,
+            None => Value::Nil,
+        };
+        Ok(ControlFlow::Return(value))
+    }
+
+    fn while_statement(
+        &mut self,
+        label: &Option<Token>,
+        condition: &Expression,
+        body: &Statement,
+    ) -> Result<ControlFlow, Error> {
+        loop {
+            let truthiness = match self.evaluate(condition)? {
+                Value::Boolean(bool) => bool,
+                _ => {
+                    return Err(
+                        Error::new("Expected boolean in a while condition".to_string())
+                            .with_span(condition.span()),
+                    )
                 }
-                _ => Err(Error::new("Expected number".to_string())),
-            },
-            _ => Err(Error::new("Expected binary operator".to_string())),
+            };
+            if !truthiness {
+                return Ok(ControlFlow::Normal);
+            }
+            if let Some(flow) = self.execute(body)?.exit_loop(label) {
+                return Ok(flow);
+            }
         }
     }
 
-    fn evaluate_logical(
+    fn loop_statement(
         &mut self,
-        operator: &'a Token,
-        left: &'a Expression,
-        right: &'a Expression,
-    ) -> Result<Value, Error> {
-        let left = self.evaluate(left)?;
+        label: &Option<Token>,
+        body: &Statement,
+    ) -> Result<ControlFlow, Error> {
+        loop {
+            if let Some(flow) = self.execute(body)?.exit_loop(label) {
+                return Ok(flow);
+            }
+        }
+    }
 
-        match operator.token_type {
-            TokenType::And => {
-                if let Value::Boolean(bool) = left {
-                    if bool {
-                        let right = self.evaluate(right)?;
-                        if let Value::Boolean(bool) = right {
-                            return Ok(Value::Boolean(bool));
-                        }
+    fn for_statement(
+        &mut self,
+        label: &Option<Token>,
+        name: &Token,
+        range: &Expression,
+        body: &Statement,
+    ) -> Result<ControlFlow, Error> {
+        match self.evaluate(range)? {
+            Value::Range(range) => {
+                for i in 0..range.len() {
+                    self.environment.push_scope();
+                    self.environment
+                        .declare(&name.lexeme, range.get(i).unwrap_or(Value::Nil));
+                    let result = self.execute(body);
+                    self.environment.pop_scope();
+                    if let Some(flow) = result?.exit_loop(label) {
+                        return Ok(flow);
                     }
                 }
-                Ok(Value::Boolean(false))
             }
-            TokenType::Or => {
-                if let Value::Boolean(bool) = left {
-                    if bool {
-                        return Ok(Value::Boolean(true));
+            Value::List(list) => {
+                // Iterates over a snapshot, so the body may modify the list
+                let elements = list.borrow().clone();
+                for element in elements {
+                    self.environment.push_scope();
+                    self.environment.declare(&name.lexeme, element);
+                    let result = self.execute(body);
+                    self.environment.pop_scope();
+                    if let Some(flow) = result?.exit_loop(label) {
+                        return Ok(flow);
                     }
                 }
-                let right = self.evaluate(right)?;
-                if let Value::Boolean(bool) = right {
-                    return Ok(Value::Boolean(bool));
-                }
-                Ok(Value::Boolean(false))
             }
-            _ => Err(Error::new("Expected logical operator".to_string())),
+            _ => {
+                return Err(Error::new("Expected range or list".to_string()).with_span(range.span()))
+            }
         }
+
+        Ok(ControlFlow::Normal)
     }
 
-    fn evaluate_assign(
+    fn match_statement(
         &mut self,
-        assignee: &'a Expression,
-        value: &'a Expression,
-    ) -> Result<Value, Error> {
+        value: &Expression,
+        arms: &[MatchArm<Statement>],
+    ) -> Result<ControlFlow, Error> {
         let value = self.evaluate(value)?;
+        // Values no arm matches are ignored, the parser warns about that
+        let Some(body) = self.match_arm(&value, arms)? else {
+            return Ok(ControlFlow::Normal);
+        };
+
+        let result = self.execute(body);
+        self.environment.pop_scope();
+        result
+    }
 
-        let Expression::Variable { name, member } = assignee else {
-            return Err(Error::new("Expected variable".to_string()));
+    fn evaluate_match(
+        &mut self,
+        value: &Expression,
+        arms: &[MatchArm<Expression>],
+    ) -> Result<Value, Error> {
+        let subject = self.evaluate(value)?;
+        let Some(body) = self.match_arm(&subject, arms)? else {
+            return Err(Error::new(format!("No match arm for {}", subject)).with_span(value.span()));
         };
 
-        // TODO: Handle members
+        let result = self.evaluate(body);
+        self.environment.pop_scope();
+        result
+    }
 
-        self.environment
-            .variables
-            .insert(&name.lexeme, value);
+    /// Finds the first arm whose pattern matches and whose guard holds. Its bindings are declared
+    /// in a new scope, which the caller pops after running the body.
+    fn match_arm<'b, B>(
+        &mut self,
+        value: &Value,
+        arms: &'b [MatchArm<B>],
+    ) -> Result<Option<&'b B>, Error> {
+        for arm in arms {
+            let mut bindings = Vec::new();
+            let lookup = &mut |name: &Token| self.evaluate_variable(name);
+            if !match_pattern(&arm.pattern, value, &mut bindings, lookup)? {
+                continue;
+            }
 
-        Ok(value)
+            self.environment.push_scope();
+            for (name, value) in bindings {
+                self.environment.declare(&name, value);
+            }
+            let matched = match &arm.guard {
+                Some(guard) => match self.evaluate(guard) {
+                    Ok(Value::Boolean(bool)) => Ok(bool),
+                    Ok(_) => Err(Error::new("Expected boolean in a match guard".to_string())
+                        .with_span(guard.span())),
+                    Err(err) => Err(err),
+                },
+                None => Ok(true),
+            };
+            if let Ok(true) = matched {
+                return Ok(Some(&arm.body));
+            }
+            self.environment.pop_scope();
+            matched?;
+        }
+        Ok(None)
+    }
+
+    fn make_closure(&mut self, declaration: &Rc<FunctionDeclaration>) -> Value {
+        Value::Function(Rc::new(Closure {
+            declaration: declaration.clone(),
+            scopes: self.environment.scopes.clone(),
+        }))
     }
 
-    fn evaluate_variable(
+    fn call(&mut self, callee: Value, arguments: Vec<Value>, span: Span) -> Result<Value, Error> {
+        match callee {
+            Value::Function(closure) => self.call_closure(&closure, arguments, span),
+            Value::NativeFunction(native) if arguments.len() != native.arity => {
+                Err(arity_error(native.arity, arguments.len()).with_span(span))
+            }
+            Value::NativeFunction(native) => {
+                (native.function)(arguments).map_err(|err| err.or_span(span))
+            }
+            callee => Err(not_callable_error(&callee).with_span(span)),
+        }
+    }
+
+    fn call_closure(
         &mut self,
-        name: &Token,
-        member: &Option<Box<Expression>>,
+        closure: &Closure,
+        arguments: Vec<Value>,
+        span: Span,
     ) -> Result<Value, Error> {
-        let name = name.lexeme.as_str();
-        let value = self
-            .environment
-            .variables
-            .get(name)
-            .ok_or_else(|| Error::new(format!("Variable {} not found", name)))?;
+        let declaration = &closure.declaration;
+
+        if arguments.len() != declaration.params.len() {
+            return Err(arity_error(declaration.params.len(), arguments.len()).with_span(span));
+        }
+        if self.call_depth >= self.max_call_depth {
+            return Err(call_depth_error(self.max_call_depth).with_span(span));
+        }
+
+        let scopes = std::mem::replace(&mut self.environment.scopes, closure.scopes.clone());
+        self.environment.push_scope();
+        for (param, argument) in declaration.params.iter().zip(arguments) {
+            let argument = match &param.annotation {
+                Some(annotation) => annotation.convert(argument),
+                None => argument,
+            };
+            self.environment.declare(&param.name.lexeme, argument);
+        }
 
-        Ok(*value)
+        self.call_depth += 1;
+        let result = self.execute_statements(&declaration.body);
+        self.call_depth -= 1;
+        self.environment.scopes = scopes;
+
+        match result? {
+            ControlFlow::Return(value) => Ok(match &declaration.return_type {
+                Some(annotation) => annotation.convert(value),
+                None => value,
+            }),
+            // The parser rejects break and continue outside of loops
+            _ => Ok(Value::Nil),
+        }
     }
-}
 
-pub fn interpret(statements: Vec<Statement>) -> Result<(), Error> {
-    let mut environment = Environment::new();
-    let mut interpreter = Interpreter::new(&mut environment);
-    for statement in &statements {
-        interpreter.execute(statement)?;
+    fn evaluate(&mut self, expression: &Expression) -> Result<Value, Error> {
+        self.evaluate_expression(expression)
+            .map_err(|err| err.or_span(expression.span()))
+    }
+
+    fn evaluate_expression(&mut self, expression: &Expression) -> Result<Value, Error> {
+        match expression {
+            Expression::Value { value, .. } => self.evaluate_value(value),
+            Expression::Unary { operator, right } => self.evaluate_unary(operator, right),
+            Expression::BinaryExpr {
+                operator,
+                left,
+                right,
+            } => self.evaluate_binary(operator, left, right),
+            Expression::LogicalExpr {
+                operator,
+                left,
+                right,
+            } => self.evaluate_logical(operator, left, right),
+            Expression::Grouping { expr } => self.evaluate(expr),
+            Expression::Interpolation { parts, .. } => self.evaluate_interpolation(parts),
+            Expression::Assign { assignee, value } => self.evaluate_assign(assignee, value),
+            Expression::CompoundAssign {
+                operator,
+                assignee,
+                value,
+            } => self.evaluate_compound_assign(operator, assignee, value),
+            Expression::Variable { name } => self.evaluate_variable(name),
+            Expression::Member { object, name } => self.evaluate_member(object, name),
+            Expression::Construct { name, fields, span } => {
+                self.evaluate_construct(name, fields, *span)
+            }
+            Expression::Call {
+                callee,
+                arguments,
+                span,
+            } => self.evaluate_call(callee, arguments, *span),
+            Expression::Function { declaration } => Ok(self.make_closure(declaration)),
+            Expression::List { elements, .. } => self.evaluate_list_literal(elements),
+            Expression::Tuple { elements, .. } => self.evaluate_tuple(elements),
+            Expression::Index { object, index, .. } => self.evaluate_index(object, index),
+            Expression::Match { value, arms, .. } => self.evaluate_match(value, arms),
+        }
+    }
+
+    fn evaluate_value(&mut self, value: &Value) -> Result<Value, Error> {
+        Ok(value.clone())
+    }
+
+    fn evaluate_interpolation(&mut self, parts: &[Expression]) -> Result<Value, Error> {
+        let mut string = String::new();
+        for part in parts {
+            let value = self.evaluate(part)?;
+            write!(string, "{}", value).unwrap();
+        }
+        Ok(Value::String(string))
+    }
+
+    fn evaluate_unary(&mut self, operator: &Token, right: &Expression) -> Result<Value, Error> {
+        let right = self.evaluate(right)?;
+        unary_operation(&operator.token_type, right)
+    }
+
+    fn evaluate_binary(
+        &mut self,
+        operator: &Token,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Value, Error> {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+
+        binary_operation(&operator.token_type, left, right)
+    }
+
+    fn evaluate_logical(
+        &mut self,
+        operator: &Token,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Value, Error> {
+        let left = match self.evaluate(left)? {
+            Value::Boolean(bool) => bool,
+            value => {
+                return Err(Error::new(format!(
+                    "Expected boolean operands for {} but got {}",
+                    operator.lexeme, value
+                ))
+                .with_span(left.span()))
+            }
+        };
+
+        // The right operand is only evaluated when it decides the result
+        match (operator.token_type, left) {
+            (TokenType::And, false) => return Ok(Value::Boolean(false)),
+            (TokenType::Or, true) => return Ok(Value::Boolean(true)),
+            (TokenType::And | TokenType::Or, _) => {}
+            _ => return Err(Error::new("Expected logical operator".to_string())),
+        }
+
+        match self.evaluate(right)? {
+            Value::Boolean(bool) => Ok(Value::Boolean(bool)),
+            value => Err(Error::new(format!(
+                "Expected boolean operands for {} but got {}",
+                operator.lexeme, value
+            ))
+            .with_span(right.span())),
+        }
+    }
+
+    fn evaluate_assign(
+        &mut self,
+        assignee: &Expression,
+        value: &Expression,
+    ) -> Result<Value, Error> {
+        let value = self.evaluate(value)?;
+        let place = self.evaluate_place(assignee)?;
+        self.write_place(&place, value.clone())?;
+        Ok(value)
+    }
+
+    fn evaluate_compound_assign(
+        &mut self,
+        operator: &Token,
+        assignee: &Expression,
+        value: &Expression,
+    ) -> Result<Value, Error> {
+        let Some(binary_operator) = operator.token_type.compound_operator() else {
+            return Err(Error::new(
+                "Expected compound assignment operator".to_string(),
+            ));
+        };
+
+        // The target is only evaluated once, so `a[next()] += 1` calls next once
+        let place = self.evaluate_place(assignee)?;
+        let current = self.read_place(&place)?;
+        let value = self.evaluate(value)?;
+        let value = binary_operation(&binary_operator, current, value)
+            .map_err(|err| err.with_span(operator.span))?;
+        self.write_place(&place, value.clone())?;
+        Ok(value)
+    }
+
+    fn evaluate_place(&mut self, assignee: &Expression) -> Result<Place, Error> {
+        match assignee {
+            Expression::Variable { name } => Ok(Place::Variable(name.clone())),
+            Expression::Member { object, name } => {
+                // Vectors are values, so writing a component writes the whole vector back
+                let (object_place, object) = match **object {
+                    Expression::Variable { .. }
+                    | Expression::Member { .. }
+                    | Expression::Index { .. } => {
+                        let place = self.evaluate_place(object)?;
+                        let value = self.read_place(&place)?;
+                        (Some(place), value)
+                    }
+                    _ => (None, self.evaluate(object)?),
+                };
+
+                let dimensions = vector::components(&object).map(|components| components.len());
+                if let Some(swizzle) = dimensions.and_then(|n| vector::swizzle(&name.lexeme, n)) {
+                    return match object_place {
+                        Some(place) => Ok(Place::Swizzle(Box::new(place), swizzle)),
+                        None => Err(Error::new(
+                            "Can't assign to a component of a temporary vector".to_string(),
+                        )
+                        .with_span(name.span)),
+                    };
+                }
+
+                let Value::Instance(instance) = &object else {
+                    return Err(Error::new(format!(
+                        "Value {} has no member {}",
+                        object, name.lexeme
+                    ))
+                    .with_span(name.span));
+                };
+                let struct_type = instance.borrow().struct_type.clone();
+                let index = struct_type.field_index(&name.lexeme).ok_or_else(|| {
+                    Error::new(format!(
+                        "Struct {} has no field {}",
+                        struct_type.name, name.lexeme
+                    ))
+                    .with_span(name.span)
+                })?;
+                Ok(Place::Field(instance.clone(), index))
+            }
+            Expression::Index { object, index, .. } => {
+                let list = self.evaluate_list(object)?;
+                let index = self.evaluate(index)?;
+                let index = list_index(&index, list.borrow().len())?;
+                Ok(Place::Element(list, index))
+            }
+            Expression::Tuple { elements, .. } => Ok(Place::Tuple(
+                elements
+                    .iter()
+                    .map(|element| self.evaluate_place(element))
+                    .collect::<Result<_, _>>()?,
+            )),
+            _ => Err(Error::new("Expected variable".to_string())),
+        }
+    }
+
+    fn read_place(&mut self, place: &Place) -> Result<Value, Error> {
+        match place {
+            Place::Variable(name) => self.evaluate_variable(name),
+            Place::Field(instance, index) => Ok(instance.borrow().fields[*index].clone()),
+            Place::Element(list, index) => {
+                let list = list.borrow();
+                Ok(list[list_index(&Value::Integer(*index as i64), list.len())?].clone())
+            }
+            Place::Swizzle(place, swizzle) => {
+                let vector = self.read_place(place)?;
+                Ok(vector::read_swizzle(&vector, swizzle))
+            }
+            Place::Tuple(places) => {
+                let values = places
+                    .iter()
+                    .map(|place| self.read_place(place))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::Tuple(values.into()))
+            }
+        }
+    }
+
+    fn write_place(&mut self, place: &Place, value: Value) -> Result<(), Error> {
+        match place {
+            Place::Variable(name) => {
+                if !self.environment.assign(&name.lexeme, value) {
+                    return Err(
+                        Error::new(format!("Variable {} is not declared", name.lexeme))
+                            .with_span(name.span),
+                    );
+                }
+            }
+            Place::Field(instance, index) => instance.borrow_mut().fields[*index] = value,
+            Place::Element(list, index) => {
+                // The list may have shrunk while the assigned value was evaluated
+                let mut list = list.borrow_mut();
+                let index = list_index(&Value::Integer(*index as i64), list.len())?;
+                list[index] = value;
+            }
+            Place::Swizzle(place, swizzle) => {
+                let vector = self.read_place(place)?;
+                let vector = vector::write_swizzle(&vector, swizzle, value)?;
+                self.write_place(place, vector)?;
+            }
+            Place::Tuple(places) => {
+                let values = tuple_elements(value, places.len())?;
+                for (place, value) in places.iter().zip(values.iter()) {
+                    self.write_place(place, value.clone())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate_variable(&mut self, name: &Token) -> Result<Value, Error> {
+        self.environment.get(&name.lexeme).ok_or_else(|| {
+            Error::new(format!("Variable {} not found", name.lexeme)).with_span(name.span)
+        })
+    }
+
+    fn evaluate_construct(
+        &mut self,
+        name: &Token,
+        fields: &[(Token, Expression)],
+        span: Span,
+    ) -> Result<Value, Error> {
+        let value = self.evaluate_variable(name)?;
+        let fields = fields
+            .iter()
+            .map(|(field, value)| Ok((field, self.evaluate(value)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        construct(value, name, fields, span)
+    }
+
+    fn evaluate_member(&mut self, object: &Expression, name: &Token) -> Result<Value, Error> {
+        let object = self.evaluate(object)?;
+        member(object, name)
+    }
+
+    fn evaluate_call(
+        &mut self,
+        callee: &Expression,
+        arguments: &[Expression],
+        span: Span,
+    ) -> Result<Value, Error> {
+        let callee = self.evaluate(callee)?;
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.evaluate(argument))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.call(callee, arguments, span)
+    }
+
+    fn evaluate_tuple(&mut self, elements: &[Expression]) -> Result<Value, Error> {
+        let elements = elements
+            .iter()
+            .map(|element| self.evaluate(element))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::Tuple(elements.into()))
+    }
+
+    fn evaluate_list_literal(&mut self, elements: &[Expression]) -> Result<Value, Error> {
+        let elements = elements
+            .iter()
+            .map(|element| self.evaluate(element))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::list(elements))
+    }
+
+    fn evaluate_index(&mut self, object: &Expression, index: &Expression) -> Result<Value, Error> {
+        match self.evaluate(object)? {
+            Value::List(list) => {
+                let index = self.evaluate(index)?;
+                let list = list.borrow();
+                Ok(list[list_index(&index, list.len())?].clone())
+            }
+            Value::Tuple(elements) => {
+                let index = self.evaluate(index)?;
+                Ok(elements[list_index(&index, elements.len())?].clone())
+            }
+            value => {
+                Err(Error::new(format!("Value {} can't be indexed", value))
+                    .with_span(object.span()))
+            }
+        }
+    }
+
+    fn evaluate_list(&mut self, object: &Expression) -> Result<Rc<RefCell<Vec<Value>>>, Error> {
+        match self.evaluate(object)? {
+            Value::List(list) => Ok(list),
+            Value::Tuple(_) => Err(Error::new(
+                "Tuples can't be modified, assign a new tuple instead".to_string(),
+            )
+            .with_span(object.span())),
+            value => {
+                Err(Error::new(format!("Value {} can't be indexed", value))
+                    .with_span(object.span()))
+            }
+        }
+    }
+}
+
+/// Reads a member of an already evaluated value, like a field, a swizzle or an enum variant.
+pub fn member(value: Value, name: &Token) -> Result<Value, Error> {
+    match (value, name.lexeme.as_str()) {
+        (Value::String(string), "len") => Ok(Value::Integer(string.chars().count() as i64)),
+        (Value::List(list), "len") => Ok(Value::Integer(list.borrow().len() as i64)),
+        (Value::Tuple(elements), "len") => Ok(Value::Integer(elements.len() as i64)),
+        (Value::Range(range), "len") => Ok(Value::Integer(range.checked_len()?)),
+        (Value::Range(range), "start") => Ok(range.get(0).unwrap_or(Value::Nil)),
+        (Value::Range(range), "last") => Ok(range.last().unwrap_or(Value::Nil)),
+        (vector @ (Value::Vec2(_) | Value::Vec3(_)), member) => {
+            let dimensions = vector::components(&vector).unwrap_or_default().len();
+            match vector::swizzle(member, dimensions) {
+                Some(swizzle) => Ok(vector::read_swizzle(&vector, &swizzle)),
+                None => Err(
+                    Error::new(format!("Value {} has no member {}", vector, member))
+                        .with_span(name.span),
+                ),
+            }
+        }
+        (Value::Enum(enum_type), member) => match enum_type.variant_index(member) {
+            Some(index) => Ok(variant_constructor(&enum_type, index)),
+            None => Err(
+                Error::new(format!("Enum {} has no variant {}", enum_type.name, member))
+                    .with_span(name.span),
+            ),
+        },
+        (Value::Instance(instance), member) => {
+            let instance = instance.borrow();
+            match instance.struct_type.field_index(member) {
+                Some(index) => Ok(instance.fields[index].clone()),
+                None => Err(Error::new(format!(
+                    "Struct {} has no field {}",
+                    instance.struct_type.name, member
+                ))
+                .with_span(name.span)),
+            }
+        }
+        (value, member) => Err(
+            Error::new(format!("Value {} has no member {}", value, member)).with_span(name.span),
+        ),
+    }
+}
+
+/// Checks `value` against `pattern`, collecting the variables it binds. Enums named by variant
+/// patterns are looked up with `lookup` once the pattern is reached.
+pub fn match_pattern(
+    pattern: &Pattern,
+    value: &Value,
+    bindings: &mut Vec<(String, Value)>,
+    lookup: &mut dyn FnMut(&Token) -> Result<Value, Error>,
+) -> Result<bool, Error> {
+    match pattern {
+        Pattern::Wildcard { .. } => Ok(true),
+        Pattern::Binding { name } => {
+            bindings.push((name.lexeme.clone(), value.clone()));
+            Ok(true)
+        }
+        Pattern::Literal { value: literal, .. } => Ok(value == literal),
+        Pattern::Range { range, .. } => Ok(range.contains(value)),
+        Pattern::Or { alternatives } => {
+            for alternative in alternatives {
+                if match_pattern(alternative, value, bindings, lookup)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        Pattern::Tuple { elements, .. } => {
+            let Value::Tuple(values) = value else {
+                return Ok(false);
+            };
+            if values.len() != elements.len() {
+                return Ok(false);
+            }
+            for (element, value) in elements.iter().zip(values.iter()) {
+                if !match_pattern(element, value, bindings, lookup)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        Pattern::Variant {
+            enum_name,
+            variant,
+            fields,
+            span,
+        } => {
+            let enum_type = match lookup(enum_name)? {
+                Value::Enum(enum_type) => enum_type,
+                _ => {
+                    return Err(Error::new(format!("{} is not an enum", enum_name.lexeme))
+                        .with_span(enum_name.span))
+                }
+            };
+            let Some(index) = enum_type.variant_index(&variant.lexeme) else {
+                return Err(Error::new(format!(
+                    "Enum {} has no variant {}",
+                    enum_type.name, variant.lexeme
+                ))
+                .with_span(variant.span));
+            };
+            let expected = enum_type.variants[index].fields.len();
+            if fields.len() != expected {
+                return Err(Error::new(format!(
+                    "Variant {}.{} has {} fields but the pattern has {}",
+                    enum_type.name,
+                    variant.lexeme,
+                    expected,
+                    fields.len()
+                ))
+                .with_span(*span));
+            }
+
+            let Value::Variant(value) = value else {
+                return Ok(false);
+            };
+            if value.enum_type != enum_type || value.index != index {
+                return Ok(false);
+            }
+            for (field, value) in fields.iter().zip(&value.payload) {
+                if !match_pattern(field, value, bindings, lookup)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+    }
+}
+
+/// Creates an instance of the struct `value` from its already evaluated fields
+pub fn construct(
+    value: Value,
+    name: &Token,
+    fields: Vec<(&Token, Value)>,
+    span: Span,
+) -> Result<Value, Error> {
+    let struct_type = match value {
+        Value::Struct(struct_type) => struct_type,
+        value => {
+            return Err(Error::new(format!("Value {} is not a struct", value)).with_span(name.span))
+        }
+    };
+
+    let mut values = vec![None; struct_type.fields.len()];
+    for (field, value) in fields {
+        let index = struct_type.field_index(&field.lexeme).ok_or_else(|| {
+            Error::new(format!(
+                "Struct {} has no field {}",
+                struct_type.name, field.lexeme
+            ))
+            .with_span(field.span)
+        })?;
+        values[index] = Some(value);
+    }
+
+    let values = values
+        .into_iter()
+        .zip(&struct_type.fields)
+        .map(|(value, field)| {
+            value.ok_or_else(|| {
+                Error::new(format!(
+                    "Missing field {} when constructing {}",
+                    field, struct_type.name
+                ))
+                .with_span(span)
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Value::Instance(Rc::new(RefCell::new(Instance {
+        struct_type,
+        fields: values,
+    }))))
+}
+
+/// Binds the variables of a let pattern, which the parser checked to always match
+pub fn destructure(
+    pattern: &Pattern,
+    value: Value,
+    bindings: &mut Vec<(String, Value)>,
+) -> Result<(), Error> {
+    match pattern {
+        Pattern::Binding { name } => bindings.push((name.lexeme.clone(), value)),
+        Pattern::Tuple { elements, span } => {
+            let values = tuple_elements(value, elements.len()).map_err(|err| err.or_span(*span))?;
+            for (element, value) in elements.iter().zip(values.iter()) {
+                destructure(element, value.clone(), bindings)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+pub fn tuple_elements(value: Value, len: usize) -> Result<Rc<[Value]>, Error> {
+    match value {
+        Value::Tuple(elements) if elements.len() == len => Ok(elements),
+        value => Err(Error::new(format!(
+            "Expected a tuple of {} values but got {}",
+            len, value
+        ))),
+    }
+}
+
+/// Variants without fields are values, the others are functions taking the fields
+pub fn variant_constructor(enum_type: &Rc<EnumType>, index: usize) -> Value {
+    let variant = &enum_type.variants[index];
+    if variant.fields.is_empty() {
+        return Value::Variant(Rc::new(Variant {
+            enum_type: enum_type.clone(),
+            index,
+            payload: Vec::new(),
+        }));
+    }
+
+    let name = format!("{}.{}", enum_type.name, variant.name);
+    let arity = variant.fields.len();
+    let enum_type = enum_type.clone();
+    Value::NativeFunction(Rc::new(NativeFunction {
+        name,
+        arity,
+        function: Box::new(move |payload| {
+            Ok(Value::Variant(Rc::new(Variant {
+                enum_type: enum_type.clone(),
+                index,
+                payload,
+            })))
+        }),
+    }))
+}
+
+/// Applies a unary operator to an already evaluated operand.
+pub fn unary_operation(operator: &TokenType, right: Value) -> Result<Value, Error> {
+    match operator {
+        TokenType::Minus => match right {
+            Value::Integer(int) => int
+                .checked_neg()
+                .map(Value::Integer)
+                .ok_or_else(|| Error::new("Integer overflow".to_string())),
+            Value::Real(real) => Ok(Value::Real(-real)),
+            Value::Vec2(vector) => Ok(Value::Vec2(-vector)),
+            Value::Vec3(vector) => Ok(Value::Vec3(-vector)),
+            _ => Err(Error::new("Expected number".to_string())),
+        },
+        TokenType::Bang => match right {
+            Value::Boolean(bool) => Ok(Value::Boolean(!bool)),
+            // Bitwise not, like in Rust
+            Value::Integer(int) => Ok(Value::Integer(!int)),
+            _ => Err(Error::new("Expected boolean or integer".to_string())),
+        },
+        _ => Err(Error::new("Expected unary operator".to_string())),
+    }
+}
+
+fn label_name(label: &Option<Token>) -> Option<String> {
+    label.as_ref().map(|label| label.lexeme.clone())
+}
+
+// Errors of calls are built out of line, so the formatting doesn't grow the stack frame of
+// every script call
+fn arity_error(expected: usize, got: usize) -> Error {
+    Error::new(format!("Expected {} arguments but got {}", expected, got))
+}
+
+fn not_callable_error(callee: &Value) -> Error {
+    Error::new(format!("Value {} is not callable", callee))
+}
+
+fn call_depth_error(max_call_depth: usize) -> Error {
+    Error::new(format!("Maximum call depth of {} exceeded", max_call_depth))
+}
+
+/// Applies a binary operator to two already evaluated operands.
+pub fn binary_operation(operator: &TokenType, left: Value, right: Value) -> Result<Value, Error> {
+    if let Some(result) = vector::binary_operation(operator, &left, &right) {
+        return result;
+    }
+
+    match operator {
+        TokenType::Star => match (left, right) {
+            (Value::Integer(left), Value::Integer(right)) => left
+                .checked_mul(right)
+                .map(Value::Integer)
+                .ok_or_else(|| Error::new("Integer overflow".to_string())),
+            (Value::Real(left), Value::Real(right)) => Ok(Value::Real(left * right)),
+            (Value::Integer(left), Value::Real(right)) => Ok(Value::Real(left as f64 * right)),
+            (Value::Real(left), Value::Integer(right)) => Ok(Value::Real(left * right as f64)),
+            _ => Err(Error::new("Expected number".to_string())),
+        },
+        TokenType::Slash => match (left, right) {
+            (Value::Integer(_), Value::Integer(0)) => {
+                Err(Error::new("Division by zero".to_string()))
+            }
+            (Value::Integer(left), Value::Integer(right)) => left
+                .checked_div(right)
+                .map(Value::Integer)
+                .ok_or_else(|| Error::new("Integer overflow".to_string())),
+            (Value::Real(left), Value::Real(right)) => Ok(Value::Real(left / right)),
+            (Value::Integer(left), Value::Real(right)) => Ok(Value::Real(left as f64 / right)),
+            (Value::Real(left), Value::Integer(right)) => Ok(Value::Real(left / right as f64)),
+            _ => Err(Error::new("Expected number".to_string())),
+        },
+        // Euclidean remainder, so that wrapping around with negative numbers works
+        TokenType::Percent => match (left, right) {
+            (Value::Integer(_), Value::Integer(0)) => {
+                Err(Error::new("Division by zero".to_string()))
+            }
+            (Value::Integer(left), Value::Integer(right)) => left
+                .checked_rem_euclid(right)
+                .map(Value::Integer)
+                .ok_or_else(|| Error::new("Integer overflow".to_string())),
+            (Value::Real(left), Value::Real(right)) => Ok(Value::Real(left.rem_euclid(right))),
+            (Value::Integer(left), Value::Real(right)) => {
+                Ok(Value::Real((left as f64).rem_euclid(right)))
+            }
+            (Value::Real(left), Value::Integer(right)) => {
+                Ok(Value::Real(left.rem_euclid(right as f64)))
+            }
+            _ => Err(Error::new("Expected number".to_string())),
+        },
+        TokenType::StarStar => match (left, right) {
+            (Value::Integer(_), Value::Integer(right)) if right < 0 => Err(Error::new(format!(
+                "Integer can't be raised to the negative power {}",
+                right
+            ))),
+            (Value::Integer(left), Value::Integer(right)) => u32::try_from(right)
+                .ok()
+                .and_then(|right| left.checked_pow(right))
+                .map(Value::Integer)
+                .ok_or_else(|| Error::new("Integer overflow".to_string())),
+            (Value::Real(left), Value::Real(right)) => Ok(Value::Real(left.powf(right))),
+            (Value::Integer(left), Value::Real(right)) => {
+                Ok(Value::Real((left as f64).powf(right)))
+            }
+            (Value::Real(left), Value::Integer(right)) => Ok(Value::Real(left.powf(right as f64))),
+            _ => Err(Error::new("Expected number".to_string())),
+        },
+        TokenType::Ampersand => match (left, right) {
+            (Value::Integer(left), Value::Integer(right)) => Ok(Value::Integer(left & right)),
+            _ => Err(Error::new("Expected integers".to_string())),
+        },
+        TokenType::Pipe => match (left, right) {
+            (Value::Integer(left), Value::Integer(right)) => Ok(Value::Integer(left | right)),
+            _ => Err(Error::new("Expected integers".to_string())),
+        },
+        TokenType::Caret => match (left, right) {
+            (Value::Integer(left), Value::Integer(right)) => Ok(Value::Integer(left ^ right)),
+            _ => Err(Error::new("Expected integers".to_string())),
+        },
+        TokenType::LessLess | TokenType::GreaterGreater => match (left, right) {
+            (Value::Integer(left), Value::Integer(right)) => {
+                let Some(amount) = u32::try_from(right).ok().filter(|amount| *amount < 64) else {
+                    return Err(Error::new(format!(
+                        "Shift amount {} is out of range",
+                        right
+                    )));
+                };
+                match operator {
+                    TokenType::LessLess => Ok(Value::Integer(left << amount)),
+                    _ => Ok(Value::Integer(left >> amount)),
+                }
+            }
+            _ => Err(Error::new("Expected integers".to_string())),
+        },
+        TokenType::Plus => match (left, right) {
+            (Value::Integer(left), Value::Integer(right)) => left
+                .checked_add(right)
+                .map(Value::Integer)
+                .ok_or_else(|| Error::new("Integer overflow".to_string())),
+            (Value::Real(left), Value::Real(right)) => Ok(Value::Real(left + right)),
+            (Value::Integer(left), Value::Real(right)) => Ok(Value::Real(left as f64 + right)),
+            (Value::Real(left), Value::Integer(right)) => Ok(Value::Real(left + right as f64)),
+            (Value::String(left), Value::String(right)) => Ok(Value::String(left + &right)),
+            _ => Err(Error::new("Expected number or string".to_string())),
+        },
+        TokenType::Minus => match (left, right) {
+            (Value::Integer(left), Value::Integer(right)) => left
+                .checked_sub(right)
+                .map(Value::Integer)
+                .ok_or_else(|| Error::new("Integer overflow".to_string())),
+            (Value::Real(left), Value::Real(right)) => Ok(Value::Real(left - right)),
+            (Value::Integer(left), Value::Real(right)) => Ok(Value::Real(left as f64 - right)),
+            (Value::Real(left), Value::Integer(right)) => Ok(Value::Real(left - right as f64)),
+            _ => Err(Error::new("Expected number".to_string())),
+        },
+        TokenType::EqualsEquals => Ok(Value::Boolean(left == right)),
+        TokenType::BangEquals => Ok(Value::Boolean(left != right)),
+        TokenType::Less => match (left, right) {
+            (Value::Integer(left), Value::Integer(right)) => Ok(Value::Boolean(left < right)),
+            (Value::Real(left), Value::Real(right)) => Ok(Value::Boolean(left < right)),
+            (Value::Integer(left), Value::Real(right)) => Ok(Value::Boolean((left as f64) < right)),
+            (Value::Real(left), Value::Integer(right)) => Ok(Value::Boolean(left < right as f64)),
+            (Value::String(left), Value::String(right)) => Ok(Value::Boolean(left < right)),
+            _ => Err(Error::new("Expected numbers or strings".to_string())),
+        },
+        TokenType::LessEquals => match (left, right) {
+            (Value::Integer(left), Value::Integer(right)) => Ok(Value::Boolean(left <= right)),
+            (Value::Real(left), Value::Real(right)) => Ok(Value::Boolean(left <= right)),
+            (Value::Integer(left), Value::Real(right)) => Ok(Value::Boolean(left as f64 <= right)),
+            (Value::Real(left), Value::Integer(right)) => Ok(Value::Boolean(left <= right as f64)),
+            (Value::String(left), Value::String(right)) => Ok(Value::Boolean(left <= right)),
+            _ => Err(Error::new("Expected numbers or strings".to_string())),
+        },
+        TokenType::Greater => match (left, right) {
+            (Value::Integer(left), Value::Integer(right)) => Ok(Value::Boolean(left > right)),
+            (Value::Real(left), Value::Real(right)) => Ok(Value::Boolean(left > right)),
+            (Value::Integer(left), Value::Real(right)) => Ok(Value::Boolean(left as f64 > right)),
+            (Value::Real(left), Value::Integer(right)) => Ok(Value::Boolean(left > right as f64)),
+            (Value::String(left), Value::String(right)) => Ok(Value::Boolean(left > right)),
+            _ => Err(Error::new("Expected numbers or strings".to_string())),
+        },
+        TokenType::GreaterEquals => match (left, right) {
+            (Value::Integer(left), Value::Integer(right)) => Ok(Value::Boolean(left >= right)),
+            (Value::Real(left), Value::Real(right)) => Ok(Value::Boolean(left >= right)),
+            (Value::Integer(left), Value::Real(right)) => Ok(Value::Boolean(left as f64 >= right)),
+            (Value::Real(left), Value::Integer(right)) => Ok(Value::Boolean(left >= right as f64)),
+            (Value::String(left), Value::String(right)) => Ok(Value::Boolean(left >= right)),
+            _ => Err(Error::new("Expected numbers or strings".to_string())),
+        },
+        TokenType::DotDot => Ok(Value::Range(Range::new(left, right, false)?)),
+        TokenType::DotDotEquals => Ok(Value::Range(Range::new(left, right, true)?)),
+        TokenType::Step => match left {
+            Value::Range(range) => Ok(Value::Range(range.with_step(right)?)),
+            _ => Err(Error::new("Expected range".to_string())),
+        },
+        _ => Err(Error::new("Expected binary operator".to_string())),
+    }
+}
+
+pub fn interpret(statements: Vec<Statement>) -> Result<(), Error> {
+    interpret_with_output(statements, &mut std::io::stdout())
+}
+
+pub fn interpret_with_output(
+    statements: Vec<Statement>,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    let mut environment = Environment::new();
+    Interpreter::new(&mut environment, output).interpret(&statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{lexer::Lexer, parser::parse, resolver::resolve};
+
+    fn run(code: &str) -> Result<String, Error> {
+        let code = code.chars().collect::<Vec<char>>();
+        let tokens = Lexer::new(&code).collect::<Result<Vec<_>, _>>()?;
+        let statements = parse(&tokens).map_err(|mut errors| errors.remove(0))?;
+        resolve(&statements, &Environment::new()).map_err(|mut errors| errors.remove(0))?;
+        let mut output = Vec::new();
+        interpret_with_output(statements, &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_runtime_error_span() {
+        let error = run("let x = 1\nprint x + \"a\"\n").unwrap_err();
+        let span = error.span.unwrap();
+        assert_eq!(
+            (span.line, span.column, span.start, span.end),
+            (2, 7, 16, 23)
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let output = run(
+            "/// Starting value\nlet x = 4 // four\n/* x = 5\n/* nested */ */\nprint x / 2 /* half */\n",
+        )
+        .unwrap();
+        assert_eq!(output, "2\n");
+    }
+
+    #[test]
+    fn test_print_string() {
+        let output = run("print \"Hello\\tworld\\n\\\"quoted\\\"\"\n").unwrap();
+        assert_eq!(output, "Hello\tworld\n\"quoted\"\n");
+    }
+
+    #[test]
+    fn test_string_concatenation() {
+        let output = run("let x = \"foo\"\nprint x + \"bar\"\n").unwrap();
+        assert_eq!(output, "foobar\n");
+    }
+
+    #[test]
+    fn test_string_comparison() {
+        let output =
+            run("print \"abc\" == \"abc\"\nprint \"abc\" < \"abd\"\nprint \"b\" >= \"c\"\n")
+                .unwrap();
+        assert_eq!(output, "true\ntrue\nfalse\n");
+    }
+
+    #[test]
+    fn test_string_length() {
+        let output = run("let x = \"zażółć\"\nprint x.len\n").unwrap();
+        assert_eq!(output, "6\n");
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let output =
+            run("let x = 5\nprint \"x = {x}, x * 2 = {x * 2}, {\"nested {x}\"} \\{x\\}\"\n")
+                .unwrap();
+        assert_eq!(output, "x = 5, x * 2 = 10, nested 5 {x}\n");
+    }
+
+    #[test]
+    fn test_string_type_errors() {
+        assert!(run("print \"a\" + 1\n").is_err());
+        assert!(run("print \"a\" < 1\n").is_err());
+    }
+
+    #[test]
+    fn test_annotated_real() {
+        let output = run("let x: real = 2\nprint x / 4\n").unwrap();
+        assert_eq!(output, "0.5\n");
+    }
+
+    #[test]
+    fn test_let() {
+        let output = run("let mut x = 1\nlet y = x + 1\nx = y * 10\nprint x\n").unwrap();
+        assert_eq!(output, "20\n");
+    }
+
+    #[test]
+    fn test_assign_undeclared() {
+        let error = run("x = 1\n").unwrap_err();
+        assert_eq!(error.message, "Variable x is not declared");
+    }
+
+    #[test]
+    fn test_block_scopes() {
+        let output = run(
+            "let x = 1\n{\n    let mut x = 2\n    {\n        x = 3\n        let y = x\n        print y\n    }\n    print x\n}\nprint x\n",
+        )
+        .unwrap();
+        assert_eq!(output, "3\n3\n1\n");
+
+        assert!(run("{\n    let y = 1\n}\nprint y\n").is_err());
+    }
+
+    #[test]
+    fn test_for_scopes() {
+        let output = run(
+            "let i = 100\nlet mut sum = 0\nfor i in 0..4 {\n    let doubled = i * 2\n    sum = sum + doubled\n}\nprint i\nprint sum\n",
+        )
+        .unwrap();
+        assert_eq!(output, "100\n12\n");
+    }
+
+    #[test]
+    fn test_loop_variable_does_not_leak() {
+        let code = "for i in 0..3 {\n    let x = i\n}\n"
+            .chars()
+            .collect::<Vec<char>>();
+        let tokens = Lexer::new(&code).collect::<Result<Vec<_>, _>>().unwrap();
+        let statements = parse(&tokens).unwrap();
+
+        let mut environment = Environment::new();
+        let mut output = Vec::new();
+        let mut interpreter = Interpreter::new(&mut environment, &mut output);
+        for statement in &statements {
+            interpreter.execute(statement).unwrap();
+        }
+
+        assert!(interpreter.environment.variables.values.is_empty());
+    }
+
+    #[test]
+    fn test_function_call() {
+        let output = run("fn add(a, b) {\n    return a + b\n}\nprint add(2, 3)\n").unwrap();
+        assert_eq!(output, "5\n");
+    }
+
+    #[test]
+    fn test_recursion() {
+        let output = run(
+            "fn fib(n) {\n    if n < 2 {\n        return n\n    }\n    return fib(n - 1) + fib(n - 2)\n}\nprint fib(15)\n",
+        )
+        .unwrap();
+        assert_eq!(output, "610\n");
+    }
+
+    #[test]
+    fn test_implicit_nil_return() {
+        let output =
+            run("fn a() {\n    return\n}\nfn b() {\n    let x = 1\n}\nprint a()\nprint b()\n")
+                .unwrap();
+        assert_eq!(output, "nil\nnil\n");
+    }
+
+    #[test]
+    fn test_return_from_loop() {
+        let output = run(
+            "fn first_over(limit) {\n    for i in 0..100 {\n        if i * i > limit {\n            return i\n        }\n    }\n    return -1\n}\nprint first_over(50)\n",
+        )
+        .unwrap();
+        assert_eq!(output, "8\n");
+    }
+
+    #[test]
+    fn test_arity_error() {
+        let error = run("fn f(a) {\n    return a\n}\nf(1, 2)\n").unwrap_err();
+        assert_eq!(error.message, "Expected 1 arguments but got 2");
+        assert!(run("let x = 5\nx()\n").is_err());
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        let code =
+            "fn down(n) {\n    if n == 0 {\n        return 0\n    }\n    return down(n - 1)\n}\n";
+
+        assert!(run(&format!("{}down({})\n", code, DEFAULT_MAX_CALL_DEPTH - 1)).is_ok());
+        let error = run(&format!("{}down({})\n", code, DEFAULT_MAX_CALL_DEPTH)).unwrap_err();
+        assert_eq!(
+            error.message,
+            format!("Maximum call depth of {} exceeded", DEFAULT_MAX_CALL_DEPTH)
+        );
+
+        let code = format!("{}down(10)\n", code).chars().collect::<Vec<char>>();
+        let tokens = Lexer::new(&code).collect::<Result<Vec<_>, _>>().unwrap();
+        let statements = parse(&tokens).unwrap();
+        let mut environment = Environment::new();
+        let mut output = Vec::new();
+        let result = Interpreter::new(&mut environment, &mut output)
+            .with_max_call_depth(5)
+            .interpret(&statements);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_call_depth_in_nested_blocks() {
+        // Recursing from inside blocks, loops and expressions uses more native stack per call
+        let code = "fn sum(n) {\n    if n > 0 {\n        while true {\n            let rest = n + sum(n - 1)\n            return rest\n        }\n    }\n    return 0\n}\n";
+
+        let depth = DEFAULT_MAX_CALL_DEPTH as i64 - 1;
+        assert_eq!(
+            run(&format!("{}print sum({})\n", code, depth)),
+            Ok(format!("{}\n", depth * (depth + 1) / 2))
+        );
+        let error = run(&format!("{}sum({})\n", code, DEFAULT_MAX_CALL_DEPTH)).unwrap_err();
+        assert_eq!(
+            error.message,
+            format!("Maximum call depth of {} exceeded", DEFAULT_MAX_CALL_DEPTH)
+        );
+    }
+
+    #[test]
+    fn test_local_functions_are_freed() {
+        let closure = |code: &str, name: &str| {
+            let code = code.chars().collect::<Vec<char>>();
+            let tokens = Lexer::new(&code).collect::<Result<Vec<_>, _>>().unwrap();
+            let statements = parse(&tokens).unwrap();
+            let mut environment = Environment::new();
+            Interpreter::new(&mut environment, &mut Vec::new())
+                .interpret(&statements)
+                .unwrap();
+            match environment.get(name) {
+                Some(Value::Function(closure)) => Rc::downgrade(&closure),
+                value => panic!("Expected a function but got {:?}", value),
+            }
+        };
+
+        let global = closure("fn f(n) {\n    return f(n)\n}\n", "f");
+        assert!(global.upgrade().is_none());
+        let local = closure(
+            "fn outer() {\n    fn inner(n) {\n        return inner(n)\n    }\n    return inner\n}\nlet f = outer()\n",
+            "f",
+        );
+        assert!(local.upgrade().is_none());
+        let literal = closure(
+            "fn outer() {\n    let inner = fn() {\n        return inner\n    }\n    return inner\n}\nlet f = outer()\n",
+            "f",
+        );
+        assert!(literal.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_closure_counter() {
+        let output = run(
+            "fn make_counter() {\n    let mut count = 0\n    fn increment() {\n        count = count + 1\n        return count\n    }\n    return increment\n}\nlet a = make_counter()\nlet b = make_counter()\na()\na()\nprint a()\nprint b()\n",
+        )
+        .unwrap();
+        assert_eq!(output, "3\n1\n");
+    }
+
+    #[test]
+    fn test_closures_capture_loop_variable() {
+        let output = run(
+            "let mut middle = 0\nlet mut last = 0\nfor i in 0..3 {\n    let captured = fn() {\n        return i * 10\n    }\n    if i == 1 {\n        middle = captured\n    }\n    last = captured\n}\nprint middle()\nprint last()\n",
+        )
+        .unwrap();
+        assert_eq!(output, "10\n20\n");
+    }
+
+    #[test]
+    fn test_anonymous_function_argument() {
+        let output = run(
+            "fn twice(f, x) {\n    return f(f(x))\n}\nprint twice(fn(x) {\n    return x * 3\n}, 2)\nprint fn() {\n    return \"iife\"\n}()\n",
+        )
+        .unwrap();
+        assert_eq!(output, "18\niife\n");
+    }
+
+    #[test]
+    fn test_while() {
+        let output = run("let mut i = 0\nwhile i < 3 {\n    print i\n    i = i + 1\n}\n").unwrap();
+        assert_eq!(output, "0\n1\n2\n");
+        let error = run("while 1 {\n}\n").unwrap_err();
+        assert_eq!(error.message, "Expected boolean in a while condition");
+    }
+
+    #[test]
+    fn test_break_continue() {
+        let output = run(
+            "let mut i = 0\nloop {\n    i = i + 1\n    if i == 2 {\n        continue\n    }\n    if i > 4 {\n        break\n    }\n    print i\n}\nfor j in 0..10 {\n    if j == 2 {\n        break\n    }\n    print j * 10\n}\n",
+        )
+        .unwrap();
+        assert_eq!(output, "1\n3\n4\n0\n10\n");
+    }
+
+    #[test]
+    fn test_labeled_loops() {
+        let output = run(
+            "'outer: for i in 0..3 {\n    let mut j = 0\n    'inner: while true {\n        j = j + 1\n        if j == 2 {\n            continue 'outer\n        }\n        if i == 2 {\n            break 'outer\n        }\n        print \"{i} {j}\"\n    }\n}\n",
+        )
+        .unwrap();
+        assert_eq!(output, "0 1\n1 1\n");
+    }
+
+    #[test]
+    fn test_return_from_while() {
+        let output = run(
+            "fn find() {\n    let i = 0\n    loop {\n        while true {\n            return i\n        }\n    }\n}\nprint find()\n",
+        )
+        .unwrap();
+        assert_eq!(output, "0\n");
+    }
+
+    #[test]
+    fn test_lists() {
+        let output = run(
+            "let a = [1, 2, 3]\nprint a\nprint a[0] + a[2]\na[1] = \"two\"\nprint a\nprint [[1], []]\nprint a.len\n",
+        )
+        .unwrap();
+        assert_eq!(output, "[1, 2, 3]\n4\n[1, two, 3]\n[[1], []]\n3\n");
+    }
+
+    #[test]
+    fn test_list_functions() {
+        let output = run(
+            "let a = []\npush(a, 1)\npush(a, 2)\ninsert(a, 0, 0)\ninsert(a, 3, 3)\nprint a\nprint pop(a)\nprint remove(a, 0)\nprint a\nprint len(a)\nprint len(\"abc\")\n",
+        )
+        .unwrap();
+        assert_eq!(output, "[0, 1, 2, 3]\n3\n0\n[1, 2]\n2\n3\n");
+    }
+
+    #[test]
+    fn test_list_shared() {
+        let output = run(
+            "let enemies = [1, 2]\nlet alias = enemies\nfn kill(list) {\n    pop(list)\n}\npush(alias, 3)\nkill(enemies)\nkill(enemies)\nprint alias\nprint enemies == [1]\n",
+        )
+        .unwrap();
+        assert_eq!(output, "[1]\ntrue\n");
+    }
+
+    #[test]
+    fn test_cyclic_values() {
+        let output = run(
+            "let l = [1]\npush(l, l)\nprint l\nprint l == l\nlet m = [1]\npush(m, m)\nprint l == m\nprint l == [1, [1]]\nstruct Node { next }\nlet n = Node { next: 0 }\nn.next = n\nprint n\nprint [n] == [n]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "[1, [...]]\ntrue\ntrue\nfalse\nNode { next: Node { ... } }\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn test_for_list() {
+        let output = run(
+            "let mut total = 0\nlet items = [1, 2, 3]\nfor x in items {\n    push(items, x)\n    total = total + x\n}\nprint total\nprint items.len\n",
+        )
+        .unwrap();
+        assert_eq!(output, "6\n6\n");
+    }
+
+    #[test]
+    fn test_list_errors() {
+        let error = run("let a = [1, 2]\nprint a[2]\n").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Index 2 is out of bounds for a list of length 2"
+        );
+        assert_eq!(error.span.map(|span| span.line), Some(2));
+        assert!(run("let a = [1]\na[-1] = 0\n").is_err());
+        assert!(run("let a = [1]\nprint a[true]\n").is_err());
+        assert!(run("print pop([])\n").is_err());
+        assert!(run("insert([], 1, 0)\n").is_err());
+        assert!(run("push(1, 2)\n").is_err());
+        assert!(run("push([])\n").is_err());
+        assert!(run("let x = 5\nprint x[0]\n").is_err());
+    }
+
+    #[test]
+    fn test_shadow_native() {
+        let output = run("let len = 5\nprint len\n").unwrap();
+        assert_eq!(output, "5\n");
+    }
+
+    #[test]
+    fn test_structs() {
+        let output = run(
+            "struct Player {\n    x,\n    y,\n    hp,\n}\nlet hp = 100\nlet p = Player { y: 2, x: 1, hp }\nprint p\np.hp = p.hp - 10\nprint p.hp\nprint Player\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "Player { x: 1, y: 2, hp: 100 }\n90\n<struct Player>\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_members() {
+        let output = run(
+            "struct Vec { x, y }\nstruct Entity { position, tags }\nlet enemies = [Entity { position: Vec { x: 0, y: 0 }, tags: [] }]\nfn first() {\n    return enemies[0]\n}\nenemies[0].position.x = 5\npush(first().tags, \"boss\")\nprint first().position.x\nprint enemies[0].tags.len\n",
+        )
+        .unwrap();
+        assert_eq!(output, "5\n1\n");
+    }
+
+    #[test]
+    fn test_struct_shared() {
+        let output = run(
+            "struct Counter { value }\nfn bump(counter) {\n    counter.value = counter.value + 1\n}\nlet a = Counter { value: 0 }\nlet b = a\nbump(a)\nbump(b)\nprint a.value\nprint Counter { value: 2 } == a\n",
+        )
+        .unwrap();
+        assert_eq!(output, "2\ntrue\n");
+    }
+
+    #[test]
+    fn test_struct_literal_in_condition() {
+        let output =
+            run("struct P { x }\nlet x = true\nif x {\n    print (P { x: 1 }).x\n}\n").unwrap();
+        assert_eq!(output, "1\n");
+    }
+
+    #[test]
+    fn test_struct_errors() {
+        let code = "struct Player { x, y }\n";
+        let error = run(&format!("{}let p = Player {{ x: 1 }}\n", code)).unwrap_err();
+        assert_eq!(error.message, "Missing field y when constructing Player");
+        let error = run(&format!("{}let p = Player {{ x: 1, y: 2, z: 3 }}\n", code)).unwrap_err();
+        assert_eq!(error.message, "Struct Player has no field z");
+        let error = run(&format!(
+            "{}let p = Player {{ x: 1, y: 2 }}\nprint p.z\n",
+            code
+        ))
+        .unwrap_err();
+        assert_eq!(error.message, "Struct Player has no field z");
+        assert_eq!(
+            error.span.map(|span| (span.line, span.column)),
+            Some((3, 9))
+        );
+        assert!(run(&format!(
+            "{}let p = Player {{ x: 1, y: 2 }}\np.z = 3\n",
+            code
+        ))
+        .is_err());
+        assert!(run("let x = 5\nprint x.y\n").is_err());
+        assert!(run("let x = 5\nlet y = x { a: 1 }\n").is_err());
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let output = run(
+            "print true and false\nprint true and true\nprint false or true\nprint false or false\nprint 1 < 2 and 3 < 4 or false\nprint !(false or false) and true\n",
+        )
+        .unwrap();
+        assert_eq!(output, "false\ntrue\ntrue\nfalse\ntrue\ntrue\n");
+    }
+
+    #[test]
+    fn test_logical_short_circuit() {
+        let output = run(
+            "let mut calls = 0\nfn touch(result) {\n    calls = calls + 1\n    return result\n}\nprint false and touch(true)\nprint true or touch(false)\nprint true and touch(true)\nprint false or touch(false)\nprint calls\n",
+        )
+        .unwrap();
+        assert_eq!(output, "false\ntrue\ntrue\nfalse\n2\n");
+
+        // The right side is not evaluated at runtime, but its type is still checked beforehand
+        assert!(run("print false and 5\n").is_err());
+        let code = "print false and 5\n".chars().collect::<Vec<char>>();
+        let tokens = Lexer::new(&code).collect::<Result<Vec<_>, _>>().unwrap();
+        let mut output = Vec::new();
+        interpret_with_output(parse(&tokens).unwrap(), &mut output).unwrap();
+        assert_eq!(output, b"false\n");
+    }
+
+    #[test]
+    fn test_logical_type_errors() {
+        let error = run("print 1 and true\n").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Expected boolean operands for and but got int"
+        );
+        assert_eq!(error.span.map(|span| span.column), Some(7));
+        let error = run("print false or \"yes\"\n").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Expected boolean operands for or but got string"
+        );
+        assert_eq!(error.span.map(|span| span.column), Some(16));
+    }
+
+    #[test]
+    fn test_arithmetic_operators() {
+        let output = run(
+            "print 7 % 3\nprint -1 % 5\nprint 5.5 % 2\nprint 2 ** 10\nprint -2 ** 2\nprint 2 ** 0.5 == 2.0 ** 0.5\nprint 4 ** 0.5\nprint 1 + 2 * 3 % 4\n",
+        )
+        .unwrap();
+        assert_eq!(output, "1\n4\n1.5\n1024\n-4\ntrue\n2\n3\n");
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let output = run(
+            "let PLAYER = 1 << 0\nlet ENEMY = 1 << 1\nlet WALL = 1 << 2\nlet mask = PLAYER | WALL\nprint mask\nprint mask & ENEMY == 0\nprint mask ^ WALL\nprint mask & !PLAYER\nprint -16 >> 2\n",
+        )
+        .unwrap();
+        assert_eq!(output, "5\ntrue\n1\n4\n-4\n");
+    }
+
+    #[test]
+    fn test_operator_errors() {
+        assert_eq!(
+            run("print 1 / 0\n").unwrap_err().message,
+            "Division by zero"
+        );
+        assert_eq!(
+            run("print 1 % 0\n").unwrap_err().message,
+            "Division by zero"
+        );
+        assert_eq!(run("print 1.0 / 0 > 1000\n").unwrap(), "true\n");
+        assert!(run("print 2 ** -1\n").is_err());
+        assert!(run("print 10 ** 100\n").is_err());
+        assert!(run("print 1 << 64\n").is_err());
+        assert!(run("print 1.0 & 1\n").is_err());
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        let max = "let max = 9223372036854775807\nlet min = -max - 1\n";
+        for code in [
+            "print max + 1\n",
+            "print min - 1\n",
+            "print max * 2\n",
+            "print -min\n",
+            "let mut x = max\nx += 1\n",
+            "struct Score { value }\nlet s = Score { value: max }\ns.value += 1\n",
+            "let scores = [0, min]\nscores[1] -= 1\n",
+            "let scores = [max]\nscores[0] *= 2\n",
+        ] {
+            let error = run(&format!("{}{}", max, code)).unwrap_err();
+            assert_eq!(error.message, "Integer overflow", "{}", code);
+        }
+        assert_eq!(
+            run(&format!("{}print max + min\nprint -max\n", max)).unwrap(),
+            "-1\n-9223372036854775807\n"
+        );
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let output = run(
+            "struct Enemy { hp }\nlet enemies = [Enemy { hp: 10 }, Enemy { hp: 20 }]\nlet mut score = 0\nscore += 5\nscore *= 3\nscore -= 1\nscore /= 2\nprint score\nenemies[1].hp -= 5\nlet counts = [1, 2]\ncounts[0] += 10\nlet mut speed = 2\nspeed *= 1.5\nprint enemies[1].hp\nprint counts\nprint speed\nlet mut name = \"a\"\nname += \"b\"\nprint name\n",
+        )
+        .unwrap();
+        assert_eq!(output, "7\n15\n[11, 2]\n3\nab\n");
+    }
+
+    #[test]
+    fn test_compound_assignment_evaluates_target_once() {
+        let output = run(
+            "let mut calls = 0\nlet values = [0, 0]\nfn next() {\n    calls += 1\n    return calls\n}\nvalues[next()] += 5\nprint values\nprint calls\n",
+        )
+        .unwrap();
+        assert_eq!(output, "[0, 5]\n1\n");
+
+        let error = run("let mut x = true\nx += 1\n").unwrap_err();
+        assert_eq!(error.message, "Expected number or string");
+        assert_eq!(error.span.map(|span| span.column), Some(3));
+        assert!(run("y += 1\n").is_err());
+    }
+
+    #[test]
+    fn test_ranges() {
+        let output = run(
+            "for i in 0..=3 {\n    print i\n}\nfor i in 10..0 step -4 {\n    print i\n}\nfor t in 0.0..=1.0 step 0.25 {\n    print t\n}\nlet n = 3\nfor i in 0..n - 1 {\n    print i * 100\n}\nfor i in 5..0 {\n    print \"never\"\n}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "0\n1\n2\n3\n10\n6\n2\n0\n0.25\n0.5\n0.75\n1\n0\n100\n"
+        );
+    }
+
+    #[test]
+    fn test_range_values() {
+        let output = run(
+            "let r = 0..10 step 3\nprint r\nprint r.len\nprint len(0.0..=1.0 step 0.1)\nprint r.start\nprint r.last\nprint contains(r, 9)\nprint contains(r, 10)\nprint contains(0.5..1.5, 1)\nprint 1..=2 == 1..=2\nlet m = -9223372036854775807 - 1\nfor i in m..=9223372036854775807 step 9223372036854775807 {\n    print i\n}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "0..10 step 3\n4\n11\n0\n9\ntrue\nfalse\ntrue\ntrue\n-9223372036854775808\n-1\n9223372036854775806\n"
+        );
+    }
+
+    #[test]
+    fn test_range_errors() {
+        let error = run("for i in 0..10 step 0 {\n}\n").unwrap_err();
+        assert_eq!(error.message, "Range step must be finite and not zero");
+        for code in [
+            "len(0..=9223372036854775807)",
+            "(0..=9223372036854775807).len",
+        ] {
+            let error = run(&format!("print {}\n", code)).unwrap_err();
+            assert_eq!(error.message, "Range is too long", "{}", code);
+        }
+        assert!(run("let r = 0..true\n").is_err());
+        assert!(run("let r = 0..1 step \"a\"\n").is_err());
+    }
+
+    #[test]
+    fn test_vectors() {
+        let output = run(
+            "let a = vec2(1, 2)\nlet b = vec2(0.5, -1)\nprint a + b\nprint a - b\nprint a * b\nprint a / vec2(2, 4)\nprint a * 2\nprint 3 * a\nprint a / 2\nprint -a\nprint vec3(1, 2, 3) == vec3(1.0, 2.0, 3.0)\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "vec2(1.5, 1)\nvec2(0.5, 3)\nvec2(0.5, -2)\nvec2(0.5, 0.5)\nvec2(2, 4)\nvec2(3, 6)\nvec2(0.5, 1)\nvec2(-1, -2)\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn test_vector_functions() {
+        let output = run(
+            "print length(vec2(3, 4))\nprint normalize(vec3(0, 0, 5))\nprint normalize(vec2(0, 0))\nprint dot(vec3(1, 2, 3), vec3(4, 5, 6))\nprint cross(vec3(1, 0, 0), vec3(0, 1, 0))\n",
+        )
+        .unwrap();
+        assert_eq!(output, "5\nvec3(0, 0, 1)\nvec2(0, 0)\n32\nvec3(0, 0, 1)\n");
+    }
+
+    #[test]
+    fn test_swizzles() {
+        let output = run(
+            "struct Body { position }\nlet v = vec3(1, 2, 3)\nprint v.x\nprint v.zy\nprint v.xzy\nprint v.xx\nlet body = Body { position: v }\nbody.position.y = 10\nbody.position.xz += vec2(1, 1)\nprint body.position\nprint v\nlet points = [vec2(0, 0)]\npoints[0].yx = vec2(1, 2)\nprint points[0]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "1\nvec2(3, 2)\nvec3(1, 3, 2)\nvec2(1, 1)\nvec3(2, 10, 4)\nvec3(1, 2, 3)\nvec2(2, 1)\n"
+        );
+    }
+
+    #[test]
+    fn test_vector_errors() {
+        assert!(run("print vec2(1, 2) + vec3(1, 2, 3)\n").is_err());
+        assert!(run("print vec2(1, 2) + 1\n").is_err());
+        assert!(run("print 1 / vec2(1, 2)\n").is_err());
+        assert!(run("print vec2(1, 2).z\n").is_err());
+        assert!(run("print cross(vec2(1, 2), vec2(1, 2))\n").is_err());
+        assert!(run("print vec2(1, true)\n").is_err());
+        assert!(run("let v = vec2(1, 2)\nv.xx = vec2(1, 2)\n").is_err());
+        let error = run("fn f() {\n    return vec2(1, 2)\n}\nf().x = 5\n").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Can't assign to a component of a temporary vector"
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        let output = run(
+            "fn describe(n) {\n    return match n {\n        0 => \"none\",\n        1 | 2 => \"few\",\n        3..=5 => \"some\",\n        -1 => \"negative\",\n        x if x > 100 => \"{x} is a lot\",\n        _ => \"many\",\n    }\n}\nfor n in [0, 2, 5, -1, 500, 50] {\n    print describe(n)\n}\nprint match \"b\" { \"a\" => 1, \"b\" => 2, _ => 3 }\nprint match 0.5 { 0.0..1.0 => \"unit\", _ => \"other\" }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "none\nfew\nsome\nnegative\n500 is a lot\nmany\n2\nunit\n"
+        );
+    }
+
+    #[test]
+    fn test_match_statement() {
+        let output = run(
+            "fn step(start) {\n    let mut state = start\n    for i in 0..10 {\n        match state {\n            0 => state = 1\n            1 => {\n                print \"walking\"\n                state = 2\n            }\n            s if s > 1 => return i\n        }\n    }\n}\nprint step(0)\nmatch 7 {\n    1 => print \"never\"\n}\n",
+        )
+        .unwrap();
+        assert_eq!(output, "walking\n2\n");
+    }
+
+    #[test]
+    fn test_match_bindings_are_scoped() {
+        let output =
+            run("let x = 1\nlet y = match 5 { x if x < 3 => 0, x => x * 2 }\nprint x\nprint y\n")
+                .unwrap();
+        assert_eq!(output, "1\n10\n");
+    }
+
+    #[test]
+    fn test_enums() {
+        let output = run(
+            "enum State { Idle, Walking(speed), Attacking(target, damage) }\nlet a = State.Walking(2.5)\nprint a\nprint State.Idle\nprint State.Attacking(\"orc\", 3)\nprint State\nprint a == State.Walking(2.5)\nprint a == State.Walking(1)\nprint State.Idle == State.Idle\nenum Other { Idle }\nprint Other.Idle == State.Idle\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "State.Walking(2.5)\nState.Idle\nState.Attacking(orc, 3)\n<enum State>\ntrue\nfalse\ntrue\nfalse\n"
+        );
+    }
+
+    #[test]
+    fn test_enum_match() {
+        let output = run(
+            "enum Shape { Circle(radius), Rect(size), Empty }\nfn area(shape) {\n    return match shape {\n        Shape.Circle(0) | Shape.Empty => 0,\n        Shape.Circle(r) => 3 * r * r,\n        Shape.Rect(Shape.Circle(r)) => r,\n        Shape.Rect(s) => s.x * s.y,\n    }\n}\nprint area(Shape.Circle(2))\nprint area(Shape.Circle(0))\nprint area(Shape.Empty)\nprint area(Shape.Rect(vec2(2, 3)))\nprint area(Shape.Rect(Shape.Circle(7)))\n",
+        )
+        .unwrap();
+        assert_eq!(output, "12\n0\n0\n6\n7\n");
+    }
+
+    #[test]
+    fn test_enum_errors() {
+        let enums = "enum State { Idle, Walking(speed) }\n";
+        let error = run(&format!("{}print State.Running\n", enums)).unwrap_err();
+        assert_eq!(error.message, "Enum State has no variant Running");
+        let error = run(&format!("{}print State.Walking()\n", enums)).unwrap_err();
+        assert_eq!(error.message, "Expected 1 arguments but got 0");
+        let error = run(&format!(
+            "{}match State.Idle {{\n    State.Walking(a, b) => print a\n    _ => print 0\n}}\n",
+            enums
+        ))
+        .unwrap_err();
+        assert_eq!(
+            error.message,
+            "Variant State.Walking has 1 fields but the pattern has 2"
+        );
+        let error = run("let x = 1\nprint match 1 { x.Y => 1, _ => 2 }\n").unwrap_err();
+        assert_eq!(error.message, "x is not an enum");
+    }
+
+    #[test]
+    fn test_tuples() {
+        let output = run(
+            "let t = (1, \"a\", (true, 2.5))\nprint t\nprint (1,)\nprint t[1]\nprint t[2][1]\nprint t.len\nprint len(t)\nprint (1, 2) == (1, 2)\nprint (1, 2) == (2, 1)\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "(1, a, (true, 2.5))\n(1,)\na\n2.5\n3\n3\ntrue\nfalse\n"
+        );
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let output = run(
+            "fn step(position) {\n    let (x, y) = position\n    return (x + 1, y), x > 0\n}\nlet (position, moved) = step((0, 5))\nprint position\nprint moved\nlet mut (_, (a, b)) = 1, (2, 3)\na, b = b, a\nprint a\nprint b\nlet list = [0, 0]\nlist[0], list[1] = position\nprint list\nprint match position {\n    (0, _) => \"left\",\n    (x, 5) if x > 0 => \"moved\",\n    _ => \"other\",\n}\n",
+        )
+        .unwrap();
+        assert_eq!(output, "(1, 5)\nfalse\n3\n2\n[1, 5]\nmoved\n");
+    }
+
+    #[test]
+    fn test_tuple_errors() {
+        let error = run("let (a, b) = (1, 2, 3)\n").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Expected a tuple of 2 values but got (int, int, int)"
+        );
+        let error = run("let mut a = 1\nlet mut b = 2\na, b = 3\n").unwrap_err();
+        assert_eq!(error.message, "Expected a tuple of 2 values but got int");
+        let error = run("let t = (1, 2)\nt[0] = 5\n").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Tuples can't be modified, assign a new tuple instead"
+        );
+        assert!(run("print (1, 2)[2]\n").is_err());
+    }
+
+    #[test]
+    fn test_match_errors() {
+        let error = run("let a = 1\nprint match a { 2 => 0 }\n").unwrap_err();
+        assert_eq!(error.message, "No match arm for 1");
+        let error = run("print match 1 { x if x => 0, _ => 1 }\n").unwrap_err();
+        assert_eq!(error.message, "Expected boolean in a match guard");
     }
-    Ok(())
 }
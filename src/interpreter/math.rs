@@ -0,0 +1,285 @@
+use std::f64::consts::{PI, TAU};
+
+use crate::parser::{error::Error, parser::Value};
+
+use super::{interpreter::Environment, vector::number};
+
+/// Defines the math functions and constants. Like the arithmetic operators, functions of
+/// integers return integers, and an integer is promoted to a real when mixed with a real.
+pub fn define(environment: &mut Environment) {
+    environment.define_constant("PI", Value::Real(PI));
+    environment.define_constant("TAU", Value::Real(TAU));
+
+    real_function(environment, "sin", f64::sin);
+    real_function(environment, "cos", f64::cos);
+    real_function(environment, "tan", f64::tan);
+    real_function(environment, "sqrt", f64::sqrt);
+    environment.define_native("atan2", 2, |arguments| {
+        Ok(Value::Real(
+            number(&arguments[0])?.atan2(number(&arguments[1])?),
+        ))
+    });
+
+    environment.define_native("abs", 1, |arguments| match &arguments[0] {
+        Value::Integer(int) => int.checked_abs().map(Value::Integer).ok_or_else(overflow),
+        value => Ok(Value::Real(number(value)?.abs())),
+    });
+    // Zero has no sign, unlike f64::signum which gives 1 for it
+    environment.define_native("sign", 1, |arguments| match &arguments[0] {
+        Value::Integer(int) => Ok(Value::Integer(int.signum())),
+        value => match number(value)? {
+            0.0 => Ok(Value::Real(0.0)),
+            real => Ok(Value::Real(real.signum())),
+        },
+    });
+    rounding_function(environment, "floor", f64::floor);
+    rounding_function(environment, "ceil", f64::ceil);
+    rounding_function(environment, "round", f64::round);
+
+    environment.define_native("min", 2, |arguments| match (&arguments[0], &arguments[1]) {
+        (Value::Integer(left), Value::Integer(right)) => Ok(Value::Integer(*left.min(right))),
+        (left, right) => Ok(Value::Real(number(left)?.min(number(right)?))),
+    });
+    environment.define_native("max", 2, |arguments| match (&arguments[0], &arguments[1]) {
+        (Value::Integer(left), Value::Integer(right)) => Ok(Value::Integer(*left.max(right))),
+        (left, right) => Ok(Value::Real(number(left)?.max(number(right)?))),
+    });
+    environment.define_native("clamp", 3, |arguments| {
+        match (&arguments[0], &arguments[1], &arguments[2]) {
+            (Value::Integer(value), Value::Integer(min), Value::Integer(max)) => {
+                check_bounds(min, max)?;
+                Ok(Value::Integer(*value.clamp(min, max)))
+            }
+            (value, min, max) => {
+                let (min, max) = (number(min)?, number(max)?);
+                check_bounds(&min, &max)?;
+                Ok(Value::Real(number(value)?.clamp(min, max)))
+            }
+        }
+    });
+
+    environment.define_native("lerp", 3, |arguments| {
+        let t = number(&arguments[2])?;
+        match (&arguments[0], &arguments[1]) {
+            (Value::Vec2(from), Value::Vec2(to)) => Ok(Value::Vec2(from.lerp(*to, t))),
+            (Value::Vec3(from), Value::Vec3(to)) => Ok(Value::Vec3(from.lerp(*to, t))),
+            (from, to) => Ok(Value::Real(lerp(number(from)?, number(to)?, t))),
+        }
+    });
+    environment.define_native("smoothstep", 3, |arguments| {
+        let (edge0, edge1) = (number(&arguments[0])?, number(&arguments[1])?);
+        let x = number(&arguments[2])?;
+        Ok(Value::Real(smoothstep(edge0, edge1, x)))
+    });
+
+    // Reals are truncated towards zero
+    environment.define_native("int", 1, |arguments| match &arguments[0] {
+        Value::Integer(int) => Ok(Value::Integer(*int)),
+        Value::Real(real) if real.is_finite() && real.abs() < i64::MAX as f64 => {
+            Ok(Value::Integer(*real as i64))
+        }
+        Value::Real(real) => Err(Error::new(format!("Can't convert {} to an integer", real))),
+        Value::String(string) => string
+            .trim()
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| Error::new(format!("Can't convert \"{}\" to an integer", string))),
+        value => Err(Error::new(format!(
+            "Expected number or string but got {}",
+            value
+        ))),
+    });
+    environment.define_native("real", 1, |arguments| match &arguments[0] {
+        Value::String(string) => string
+            .trim()
+            .parse()
+            .map(Value::Real)
+            .map_err(|_| Error::new(format!("Can't convert \"{}\" to a real", string))),
+        Value::Integer(_) | Value::Real(_) => Ok(Value::Real(number(&arguments[0])?)),
+        value => Err(Error::new(format!(
+            "Expected number or string but got {}",
+            value
+        ))),
+    });
+}
+
+fn real_function(environment: &mut Environment, name: &str, function: fn(f64) -> f64) {
+    environment.define_native(name, 1, move |arguments| {
+        Ok(Value::Real(function(number(&arguments[0])?)))
+    });
+}
+
+// Integers are already whole, so they are returned as they are
+fn rounding_function(environment: &mut Environment, name: &str, function: fn(f64) -> f64) {
+    environment.define_native(name, 1, move |arguments| match &arguments[0] {
+        Value::Integer(int) => Ok(Value::Integer(*int)),
+        value => Ok(Value::Real(function(number(value)?))),
+    });
+}
+
+pub fn check_bounds<T: PartialOrd + std::fmt::Display>(min: &T, max: &T) -> Result<(), Error> {
+    // Also rejects NaN bounds
+    if min <= max {
+        return Ok(());
+    }
+    Err(Error::new(format!(
+        "Expected min to be at most max but got {} and {}",
+        min, max
+    )))
+}
+
+fn overflow() -> Error {
+    Error::new("Integer overflow".to_string())
+}
+
+pub fn lerp(from: f64, to: f64, t: f64) -> f64 {
+    from + (to - from) * t
+}
+
+/// Goes smoothly from 0 at `edge0` to 1 at `edge1`
+pub fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::DVec2;
+
+    fn call(name: &str, arguments: &[Value]) -> Result<Value, Error> {
+        match Environment::new().get(name) {
+            Some(Value::NativeFunction(native)) => {
+                assert_eq!(native.arity, arguments.len());
+                (native.function)(arguments.to_vec())
+            }
+            _ => panic!("{} is not a native function", name),
+        }
+    }
+
+    fn real(name: &str, arguments: &[f64]) -> f64 {
+        let arguments = arguments
+            .iter()
+            .map(|x| Value::Real(*x))
+            .collect::<Vec<_>>();
+        match call(name, &arguments) {
+            Ok(Value::Real(real)) => real,
+            result => panic!("{} returned {:?}", name, result),
+        }
+    }
+
+    const INPUTS: [f64; 9] = [-7.5, -2.5, -1.0, -0.3, 0.0, 0.5, 1.0, 2.5, 100.25];
+
+    #[test]
+    fn test_real_functions() {
+        let unary = [
+            ("sin", f64::sin as fn(f64) -> f64),
+            ("cos", f64::cos),
+            ("tan", f64::tan),
+            ("sqrt", f64::sqrt),
+            ("abs", f64::abs),
+            ("floor", f64::floor),
+            ("ceil", f64::ceil),
+            ("round", f64::round),
+        ];
+        for (name, function) in unary {
+            for x in INPUTS {
+                let (result, expected) = (real(name, &[x]), function(x));
+                assert!(
+                    result == expected || (result.is_nan() && expected.is_nan()),
+                    "{}({}) = {} instead of {}",
+                    name,
+                    x,
+                    result,
+                    expected
+                );
+            }
+        }
+
+        for x in INPUTS {
+            let expected = if x == 0.0 { 0.0 } else { x.signum() };
+            assert_eq!(real("sign", &[x]), expected);
+            for y in INPUTS {
+                assert_eq!(real("atan2", &[y, x]), y.atan2(x));
+                assert_eq!(real("min", &[x, y]), x.min(y));
+                assert_eq!(real("max", &[x, y]), x.max(y));
+                assert_eq!(real("lerp", &[x, y, 0.25]), x + (y - x) * 0.25);
+                if x <= y {
+                    for z in INPUTS {
+                        assert_eq!(real("clamp", &[z, x, y]), z.clamp(x, y));
+                    }
+                }
+            }
+        }
+
+        assert_eq!(real("smoothstep", &[0.0, 1.0, -1.0]), 0.0);
+        assert_eq!(real("smoothstep", &[0.0, 1.0, 0.5]), 0.5);
+        assert_eq!(real("smoothstep", &[0.0, 1.0, 2.0]), 1.0);
+        assert_eq!(real("smoothstep", &[0.0, 4.0, 1.0]), 0.25 * 0.25 * 2.5);
+        assert_eq!(
+            call(
+                "lerp",
+                &[
+                    Value::Vec2(DVec2::ZERO),
+                    Value::Vec2(DVec2::ONE),
+                    Value::Real(0.5)
+                ]
+            ),
+            Ok(Value::Vec2(DVec2::splat(0.5)))
+        );
+    }
+
+    #[test]
+    fn test_integer_promotion() {
+        let int = Value::Integer;
+        assert_eq!(call("abs", &[int(-3)]), Ok(int(3)));
+        assert!(call("abs", &[int(i64::MIN)]).is_err());
+        assert_eq!(call("sign", &[int(-3)]), Ok(int(-1)));
+        assert_eq!(call("sign", &[int(0)]), Ok(int(0)));
+        assert_eq!(call("floor", &[int(3)]), Ok(int(3)));
+        assert_eq!(call("min", &[int(2), int(-1)]), Ok(int(-1)));
+        assert_eq!(
+            call("max", &[int(2), Value::Real(1.5)]),
+            Ok(Value::Real(2.0))
+        );
+        assert_eq!(call("clamp", &[int(7), int(0), int(5)]), Ok(int(5)));
+        assert_eq!(
+            call("clamp", &[int(7), int(0), Value::Real(5.5)]),
+            Ok(Value::Real(5.5))
+        );
+        assert!(call("clamp", &[int(1), int(5), int(0)]).is_err());
+        assert_eq!(call("sqrt", &[int(9)]), Ok(Value::Real(3.0)));
+        assert_eq!(
+            call("lerp", &[int(0), int(10), int(1)]),
+            Ok(Value::Real(10.0))
+        );
+        assert_eq!(
+            call("sin", &[Value::Boolean(true)]).unwrap_err().message,
+            "Expected number but got true"
+        );
+    }
+
+    #[test]
+    fn test_conversions() {
+        let int = Value::Integer;
+        assert_eq!(call("int", &[Value::Real(2.9)]), Ok(int(2)));
+        assert_eq!(call("int", &[Value::Real(-2.9)]), Ok(int(-2)));
+        assert_eq!(
+            call("int", &[Value::String(" 42 ".to_string())]),
+            Ok(int(42))
+        );
+        assert!(call("int", &[Value::Real(f64::NAN)]).is_err());
+        assert!(call("int", &[Value::Real(1e300)]).is_err());
+        assert!(call("int", &[Value::String("4.2".to_string())]).is_err());
+        assert_eq!(call("real", &[int(3)]), Ok(Value::Real(3.0)));
+        assert_eq!(
+            call("real", &[Value::String("4.5".to_string())]),
+            Ok(Value::Real(4.5))
+        );
+        assert!(call("real", &[Value::Boolean(false)]).is_err());
+
+        let environment = Environment::new();
+        assert_eq!(environment.get("PI"), Some(Value::Real(PI)));
+        assert_eq!(environment.get("TAU"), Some(Value::Real(TAU)));
+    }
+}
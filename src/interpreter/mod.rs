@@ -1 +1,11 @@
+pub mod builtins;
+pub mod chunk;
+pub mod compiler;
+pub mod host;
 pub mod interpreter;
+pub mod math;
+pub mod random;
+pub mod range;
+pub mod runtime;
+pub mod vector;
+pub mod vm;
@@ -0,0 +1,253 @@
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::parser::{error::Error, parser::Value};
+
+use super::{builtins::expect_list, interpreter::Environment, math::check_bounds, vector::number};
+
+/// The random number generator of scripts, a SplitMix64 generator. Its whole state is one
+/// integer, so saving it and setting it again later replays the same numbers. Clones share
+/// the state.
+#[derive(Clone, Debug)]
+pub struct Random(Rc<Cell<u64>>);
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self(Rc::new(Cell::new(seed)))
+    }
+
+    /// Seeded from the clock, so every run is different until a script calls `seed`
+    pub fn from_time() -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self::new(time.as_nanos() as u64)
+    }
+
+    pub fn seed(&self, seed: u64) {
+        self.0.set(seed);
+    }
+
+    pub fn state(&self) -> u64 {
+        self.0.get()
+    }
+
+    pub fn set_state(&self, state: u64) {
+        self.0.set(state);
+    }
+
+    pub fn next_u64(&self) -> u64 {
+        let state = self.0.get().wrapping_add(0x9e3779b97f4a7c15);
+        self.0.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A real in `0..1`
+    pub fn next_real(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// An integer in `0..len`, `len` must not be zero
+    pub fn next_index(&self, len: u64) -> u64 {
+        ((self.next_u64() as u128 * len as u128) >> 64) as u64
+    }
+}
+
+/// Defines the random functions, which all use `random`
+pub fn define(environment: &mut Environment, random: &Random) {
+    let rng = random.clone();
+    environment.define_native("seed", 1, move |arguments| {
+        rng.seed(integer(&arguments[0])? as u64);
+        Ok(Value::Nil)
+    });
+    let rng = random.clone();
+    environment.define_native("rand", 0, move |_| Ok(Value::Real(rng.next_real())));
+    let rng = random.clone();
+    environment.define_native("rand_range", 2, move |arguments| {
+        let (min, max) = (number(&arguments[0])?, number(&arguments[1])?);
+        check_bounds(&min, &max)?;
+        Ok(Value::Real(min + (max - min) * rng.next_real()))
+    });
+    // Both bounds are included, like a die roll with `rand_int(1, 6)`
+    let rng = random.clone();
+    environment.define_native("rand_int", 2, move |arguments| {
+        let (min, max) = (integer(&arguments[0])?, integer(&arguments[1])?);
+        check_bounds(&min, &max)?;
+        let len = max.abs_diff(min).wrapping_add(1);
+        // The full range of integers has 2^64 values, which doesn't fit in len
+        let offset = match len {
+            0 => rng.next_u64(),
+            len => rng.next_index(len),
+        };
+        Ok(Value::Integer(min.wrapping_add_unsigned(offset)))
+    });
+    let rng = random.clone();
+    environment.define_native("choose", 1, move |arguments| {
+        let list = expect_list(&arguments[0])?.borrow();
+        if list.is_empty() {
+            return Err(Error::new("Can't choose from an empty list".to_string()));
+        }
+        Ok(list[rng.next_index(list.len() as u64) as usize].clone())
+    });
+    // Shuffles in place, like push changes the list
+    let rng = random.clone();
+    environment.define_native("shuffle", 1, move |arguments| {
+        let mut list = expect_list(&arguments[0])?.borrow_mut();
+        for i in (1..list.len()).rev() {
+            let j = rng.next_index(i as u64 + 1) as usize;
+            list.swap(i, j);
+        }
+        Ok(Value::Nil)
+    });
+}
+
+fn integer(value: &Value) -> Result<i64, Error> {
+    match value {
+        Value::Integer(int) => Ok(*int),
+        value => Err(Error::new(format!("Expected integer but got {}", value))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(environment: &Environment, name: &str, arguments: &[Value]) -> Result<Value, Error> {
+        match environment.get(name) {
+            Some(Value::NativeFunction(native)) => (native.function)(arguments.to_vec()),
+            _ => panic!("{} is not a native function", name),
+        }
+    }
+
+    fn sequence(environment: &Environment) -> Vec<Value> {
+        let list = Value::list((0..10).map(Value::Integer).collect());
+        call(environment, "shuffle", std::slice::from_ref(&list)).unwrap();
+        let mut values = vec![list.clone()];
+        for _ in 0..20 {
+            values.push(call(environment, "rand", &[]).unwrap());
+            values.push(
+                call(
+                    environment,
+                    "rand_range",
+                    &[Value::Integer(-5), Value::Real(5.0)],
+                )
+                .unwrap(),
+            );
+            values.push(
+                call(
+                    environment,
+                    "rand_int",
+                    &[Value::Integer(1), Value::Integer(6)],
+                )
+                .unwrap(),
+            );
+            values.push(call(environment, "choose", std::slice::from_ref(&list)).unwrap());
+        }
+        values
+    }
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let (first, second) = (Environment::new(), Environment::new());
+        call(&first, "seed", &[Value::Integer(42)]).unwrap();
+        call(&second, "seed", &[Value::Integer(42)]).unwrap();
+        let values = sequence(&first);
+        assert_eq!(values, sequence(&second));
+
+        call(&second, "seed", &[Value::Integer(42)]).unwrap();
+        assert_eq!(values, sequence(&second));
+        call(&second, "seed", &[Value::Integer(43)]).unwrap();
+        assert_ne!(values, sequence(&second));
+
+        // Restoring a saved state replays the numbers that came after it
+        let state = first.random().state();
+        let next = sequence(&first);
+        first.random().set_state(state);
+        assert_eq!(next, sequence(&first));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let environment = Environment::new();
+        call(&environment, "seed", &[Value::Integer(7)]).unwrap();
+        let mut rolls = [0; 6];
+        for _ in 0..600 {
+            match call(
+                &environment,
+                "rand_int",
+                &[Value::Integer(1), Value::Integer(6)],
+            ) {
+                Ok(Value::Integer(roll)) if (1..=6).contains(&roll) => {
+                    rolls[roll as usize - 1] += 1
+                }
+                result => panic!("Rolled {:?}", result),
+            }
+            match call(&environment, "rand", &[]) {
+                Ok(Value::Real(real)) => assert!((0.0..1.0).contains(&real)),
+                result => panic!("Got {:?}", result),
+            }
+            match call(
+                &environment,
+                "rand_range",
+                &[Value::Real(2.0), Value::Integer(3)],
+            ) {
+                Ok(Value::Real(real)) => assert!((2.0..3.0).contains(&real)),
+                result => panic!("Got {:?}", result),
+            }
+        }
+        assert!(rolls.iter().all(|count| *count > 50));
+
+        let full = [Value::Integer(i64::MIN), Value::Integer(i64::MAX)];
+        assert!(call(&environment, "rand_int", &full).is_ok());
+        assert_eq!(
+            call(
+                &environment,
+                "rand_int",
+                &[Value::Integer(3), Value::Integer(3)]
+            ),
+            Ok(Value::Integer(3))
+        );
+        assert!(call(
+            &environment,
+            "rand_int",
+            &[Value::Integer(3), Value::Integer(2)]
+        )
+        .is_err());
+        assert!(call(
+            &environment,
+            "rand_int",
+            &[Value::Real(1.0), Value::Integer(2)]
+        )
+        .is_err());
+        assert_eq!(
+            call(&environment, "choose", &[Value::list(Vec::new())])
+                .unwrap_err()
+                .message,
+            "Can't choose from an empty list"
+        );
+    }
+
+    #[test]
+    fn test_shuffle_keeps_elements() {
+        let environment = Environment::new();
+        call(&environment, "seed", &[Value::Integer(1)]).unwrap();
+        let list = Value::list((0..50).map(Value::Integer).collect());
+        call(&environment, "shuffle", std::slice::from_ref(&list)).unwrap();
+        let Value::List(elements) = &list else {
+            unreachable!()
+        };
+        let mut elements = elements.borrow().clone();
+        assert_ne!(elements, (0..50).map(Value::Integer).collect::<Vec<_>>());
+        elements.sort_by_key(|value| match value {
+            Value::Integer(int) => *int,
+            _ => unreachable!(),
+        });
+        assert_eq!(elements, (0..50).map(Value::Integer).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,382 @@
+use std::fmt::Display;
+
+use crate::parser::{error::Error, parser::Value};
+
+// Tolerance for deciding whether the end of a real range is hit exactly
+const REAL_EPSILON: f64 = 1e-9;
+
+/// A range of numbers with a step. Descending ranges have a negative step, a range whose
+/// step points away from its end is empty.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Range {
+    Integer {
+        start: i64,
+        end: i64,
+        step: i64,
+        inclusive: bool,
+    },
+    Real {
+        start: f64,
+        end: f64,
+        step: f64,
+        inclusive: bool,
+    },
+}
+
+impl Range {
+    /// Creates a range with a step of one. Any real bound makes it a real range.
+    pub fn new(start: Value, end: Value, inclusive: bool) -> Result<Range, Error> {
+        match (start, end) {
+            (Value::Integer(start), Value::Integer(end)) => Ok(Range::Integer {
+                start,
+                end,
+                step: 1,
+                inclusive,
+            }),
+            (Value::Real(start), Value::Real(end)) => Ok(Range::real(start, end, inclusive)),
+            (Value::Integer(start), Value::Real(end)) => {
+                Ok(Range::real(start as f64, end, inclusive))
+            }
+            (Value::Real(start), Value::Integer(end)) => {
+                Ok(Range::real(start, end as f64, inclusive))
+            }
+            _ => Err(Error::new("Expected number".to_string())),
+        }
+    }
+
+    fn real(start: f64, end: f64, inclusive: bool) -> Range {
+        Range::Real {
+            start,
+            end,
+            step: 1.0,
+            inclusive,
+        }
+    }
+
+    pub fn with_step(self, step: Value) -> Result<Range, Error> {
+        match (self, step) {
+            (
+                Range::Integer {
+                    start,
+                    end,
+                    inclusive,
+                    ..
+                },
+                Value::Integer(step),
+            ) if step != 0 => Ok(Range::Integer {
+                start,
+                end,
+                step,
+                inclusive,
+            }),
+            (range, Value::Integer(step)) => range.with_real_step(step as f64),
+            (range, Value::Real(step)) => range.with_real_step(step),
+            (_, step) => Err(Error::new(format!(
+                "Expected number as step but got {}",
+                step
+            ))),
+        }
+    }
+
+    fn with_real_step(self, step: f64) -> Result<Range, Error> {
+        if step == 0.0 || !step.is_finite() {
+            return Err(Error::new(
+                "Range step must be finite and not zero".to_string(),
+            ));
+        }
+
+        let (start, end, inclusive) = match self {
+            Range::Integer {
+                start,
+                end,
+                inclusive,
+                ..
+            } => (start as f64, end as f64, inclusive),
+            Range::Real {
+                start,
+                end,
+                inclusive,
+                ..
+            } => (start, end, inclusive),
+        };
+        Ok(Range::Real {
+            start,
+            end,
+            step,
+            inclusive,
+        })
+    }
+
+    /// How many values the range has. Only the longest integer ranges have more than fit into
+    /// a usize, they are cut short at usize::MAX values.
+    pub fn len(&self) -> usize {
+        usize::try_from(self.count()).unwrap_or(usize::MAX)
+    }
+
+    /// The length as a script integer, which the longest integer ranges don't fit into
+    pub fn checked_len(&self) -> Result<i64, Error> {
+        i64::try_from(self.count()).map_err(|_| Error::new("Range is too long".to_string()))
+    }
+
+    fn count(&self) -> i128 {
+        match *self {
+            Range::Integer {
+                start,
+                end,
+                step,
+                inclusive,
+            } => {
+                let distance = end as i128 - start as i128;
+                let step = step as i128;
+                if distance != 0 && (distance < 0) != (step < 0) {
+                    return 0;
+                }
+                let steps = distance / step;
+                match inclusive {
+                    true => steps + 1,
+                    false => steps + (distance % step != 0) as i128,
+                }
+            }
+            Range::Real {
+                start,
+                end,
+                step,
+                inclusive,
+            } => {
+                let steps = (end - start) / step;
+                if steps.is_nan() || steps < -REAL_EPSILON {
+                    return 0;
+                }
+                let len = match inclusive {
+                    true => (steps + REAL_EPSILON).floor() + 1.0,
+                    false => (steps - REAL_EPSILON).ceil(),
+                };
+                len.max(0.0) as i128
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The element at `index`, computed from the start so reals don't accumulate errors
+    pub fn get(&self, index: usize) -> Option<Value> {
+        let index = index as i128;
+        (index < self.count()).then(|| self.element(index))
+    }
+
+    pub fn last(&self) -> Option<Value> {
+        let count = self.count();
+        (count > 0).then(|| self.element(count - 1))
+    }
+
+    fn element(&self, index: i128) -> Value {
+        match *self {
+            // Elements are between the start and the end, only the steps to them can overflow
+            Range::Integer { start, step, .. } => {
+                Value::Integer((start as i128 + index * step as i128) as i64)
+            }
+            Range::Real {
+                start,
+                end,
+                step,
+                inclusive,
+            } => {
+                let value = start + index as f64 * step;
+                // Hit the end exactly, so sampling 0.0..=1.0 ends on 1.0
+                match inclusive && (value - end).abs() <= REAL_EPSILON * step.abs() {
+                    true => Value::Real(end),
+                    false => Value::Real(value),
+                }
+            }
+        }
+    }
+
+    /// Integer ranges contain exactly the values they iterate over. Real ranges are
+    /// treated as intervals between their bounds.
+    pub fn contains(&self, value: &Value) -> bool {
+        let (low, high, inclusive) = match *self {
+            Range::Integer {
+                start,
+                end,
+                inclusive,
+                ..
+            } => (start as f64, end as f64, inclusive),
+            Range::Real {
+                start,
+                end,
+                inclusive,
+                ..
+            } => (start, end, inclusive),
+        };
+        let value = match value {
+            Value::Integer(int) => *int as f64,
+            Value::Real(real) => *real,
+            _ => return false,
+        };
+
+        let (ascending, step_integer) = match *self {
+            Range::Integer { step, .. } => (step > 0, Some(step)),
+            Range::Real { step, .. } => (step > 0.0, None),
+        };
+        let in_bounds = match (ascending, inclusive) {
+            (true, true) => low <= value && value <= high,
+            (true, false) => low <= value && value < high,
+            (false, true) => high <= value && value <= low,
+            (false, false) => high < value && value <= low,
+        };
+
+        match step_integer {
+            Some(step) => in_bounds && value.fract() == 0.0 && (value - low) % step as f64 == 0.0,
+            None => in_bounds,
+        }
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dots = |inclusive| if inclusive { "..=" } else { ".." };
+        match *self {
+            Range::Integer {
+                start,
+                end,
+                step,
+                inclusive,
+            } => {
+                write!(f, "{}{}{}", start, dots(inclusive), end)?;
+                if step != 1 {
+                    write!(f, " step {}", step)?;
+                }
+            }
+            Range::Real {
+                start,
+                end,
+                step,
+                inclusive,
+            } => {
+                write!(f, "{}{}{}", start, dots(inclusive), end)?;
+                if step != 1.0 {
+                    write!(f, " step {}", step)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integers(start: i64, end: i64, step: i64, inclusive: bool) -> Vec<i64> {
+        let range = Range::new(Value::Integer(start), Value::Integer(end), inclusive)
+            .unwrap()
+            .with_step(Value::Integer(step))
+            .unwrap();
+        (0..range.len())
+            .map(|i| match range.get(i) {
+                Some(Value::Integer(int)) => int,
+                value => panic!("Expected integer but got {:?}", value),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_integer_len() {
+        assert_eq!(integers(0, 5, 1, false), vec![0, 1, 2, 3, 4]);
+        assert_eq!(integers(0, 5, 1, true), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(integers(0, 10, 3, false), vec![0, 3, 6, 9]);
+        assert_eq!(integers(0, 9, 3, false), vec![0, 3, 6]);
+        assert_eq!(integers(0, 9, 3, true), vec![0, 3, 6, 9]);
+        assert_eq!(integers(5, 0, -1, false), vec![5, 4, 3, 2, 1]);
+        assert_eq!(integers(5, 0, -2, true), vec![5, 3, 1]);
+        assert_eq!(integers(5, 0, 1, false), Vec::<i64>::new());
+        assert_eq!(integers(0, 5, -1, true), Vec::<i64>::new());
+        assert_eq!(integers(3, 3, 1, false), Vec::<i64>::new());
+        assert_eq!(integers(3, 3, -1, true), vec![3]);
+    }
+
+    #[test]
+    fn test_extreme_integers() {
+        assert_eq!(
+            integers(i64::MIN, i64::MAX, i64::MAX, true),
+            vec![i64::MIN, -1, i64::MAX - 1]
+        );
+        assert_eq!(
+            integers(i64::MAX, i64::MIN, i64::MIN, false),
+            vec![i64::MAX, -1]
+        );
+
+        let range = Range::new(Value::Integer(0), Value::Integer(i64::MAX), false).unwrap();
+        assert_eq!(range.checked_len(), Ok(i64::MAX));
+        assert_eq!(range.last(), Some(Value::Integer(i64::MAX - 1)));
+        let range = Range::new(Value::Integer(0), Value::Integer(i64::MAX), true).unwrap();
+        assert_eq!(range.len(), i64::MAX as usize + 1);
+        assert_eq!(
+            range.checked_len().unwrap_err().message,
+            "Range is too long"
+        );
+        let range = Range::new(Value::Integer(i64::MIN), Value::Integer(i64::MAX), true).unwrap();
+        assert_eq!(range.len(), usize::MAX);
+        assert_eq!(range.last(), Some(Value::Integer(i64::MAX)));
+    }
+
+    #[test]
+    fn test_real_len() {
+        let range = Range::new(Value::Real(0.0), Value::Real(1.0), true)
+            .unwrap()
+            .with_step(Value::Real(0.1))
+            .unwrap();
+        assert_eq!(range.len(), 11);
+        assert_eq!(range.get(10), Some(Value::Real(1.0)));
+        assert_eq!(range.get(11), None);
+
+        let range = Range::new(Value::Real(0.0), Value::Real(1.0), false)
+            .unwrap()
+            .with_step(Value::Real(0.1))
+            .unwrap();
+        assert_eq!(range.len(), 10);
+
+        let range = Range::new(Value::Integer(1), Value::Real(0.0), false)
+            .unwrap()
+            .with_step(Value::Real(-0.25))
+            .unwrap();
+        assert_eq!(range.len(), 4);
+        assert_eq!(range.get(3), Some(Value::Real(0.25)));
+    }
+
+    #[test]
+    fn test_contains() {
+        let range = Range::new(Value::Integer(0), Value::Integer(10), false)
+            .unwrap()
+            .with_step(Value::Integer(2))
+            .unwrap();
+        assert!(range.contains(&Value::Integer(4)));
+        assert!(range.contains(&Value::Real(4.0)));
+        assert!(!range.contains(&Value::Integer(5)));
+        assert!(!range.contains(&Value::Integer(10)));
+        assert!(!range.contains(&Value::Boolean(true)));
+
+        let range = Range::new(Value::Integer(10), Value::Integer(0), true)
+            .unwrap()
+            .with_step(Value::Integer(-5))
+            .unwrap();
+        assert!(range.contains(&Value::Integer(0)));
+        assert!(range.contains(&Value::Integer(5)));
+        assert!(!range.contains(&Value::Integer(3)));
+
+        let range = Range::new(Value::Real(0.0), Value::Real(1.0), false).unwrap();
+        assert!(range.contains(&Value::Real(0.5)));
+        assert!(!range.contains(&Value::Real(1.0)));
+    }
+
+    #[test]
+    fn test_step_errors() {
+        let range = Range::new(Value::Integer(0), Value::Integer(10), false).unwrap();
+        assert!(range.with_step(Value::Integer(0)).is_err());
+        assert!(range.with_step(Value::Real(0.0)).is_err());
+        assert!(range.with_step(Value::Boolean(true)).is_err());
+        assert!(Range::new(Value::Boolean(true), Value::Integer(1), false).is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use std::{io::Write, rc::Rc};
+
+use super::{
+    host::{return_value, FromValue, IntoArguments, IntoNative},
+    interpreter::Environment,
+    random::Random,
+    vm::Vm,
+};
+use crate::parser::{
+    error::Error,
+    lexer::Lexer,
+    optimizer::optimize,
+    parser::{parse_with_warnings, Value},
+    resolver::resolve,
+    span::SourceMap,
+};
+
+/// Keeps scripts alive between frames. Globals declared by loaded code stay around, so code
+/// can be loaded in several chunks, reloaded, and called into from the engine loop.
+pub struct ScriptRuntime {
+    vm: Vm,
+    // Every chunk of code that was loaded, for rendering errors
+    sources: SourceMap,
+    // Warnings about the chunk loaded last
+    warnings: Vec<Error>,
+    random: Random,
+}
+
+impl ScriptRuntime {
+    pub fn new() -> Self {
+        let environment = Environment::new();
+        Self {
+            vm: Vm::new(&environment),
+            sources: SourceMap::new(),
+            warnings: Vec::new(),
+            random: environment.random().clone(),
+        }
+    }
+
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.vm = self.vm.with_output(output);
+        self
+    }
+
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.vm = self.vm.with_max_call_depth(max_call_depth);
+        self
+    }
+
+    /// Checks, compiles and runs a chunk of code. It can use the globals of code loaded before,
+    /// and declaring a global again replaces it.
+    pub fn load(&mut self, name: &str, source: &str) -> Result<(), Vec<Error>> {
+        self.warnings.clear();
+        let file = self.sources.add(name.to_string(), source.to_string());
+        let code = source.chars().collect::<Vec<char>>();
+        let tokens = Lexer::with_file(&code, file)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| vec![err])?;
+        let (statements, errors, warnings) = parse_with_warnings(&tokens);
+        self.warnings = warnings;
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        resolve(&statements, self.vm.globals())?;
+        let statements = optimize(statements);
+        self.vm.interpret(&statements).map_err(|err| vec![err])
+    }
+
+    /// Calls the global function `name`, like `update` once per frame. The arguments are a
+    /// tuple of Rust values, and the result is converted to `R`.
+    pub fn call<R: FromValue>(
+        &mut self,
+        name: &str,
+        arguments: impl IntoArguments,
+    ) -> Result<R, Error> {
+        let function = self
+            .get(name)
+            .ok_or_else(|| Error::new(format!("Variable {} not found", name)))?;
+        let value = self.vm.call(function, arguments.into_arguments())?;
+        return_value(value)
+    }
+
+    /// Makes a Rust closure callable from scripts as the global `name`. Arguments are converted
+    /// to the closure's parameter types, and it can return a value or a `Result`.
+    pub fn register_fn<Args>(&mut self, name: &str, function: impl IntoNative<Args>) {
+        let function = function.into_native(name);
+        self.set(name, Value::NativeFunction(Rc::new(function)));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.vm.globals().get(name).cloned()
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.vm.globals_mut().define(name, value);
+    }
+
+    /// The generator behind `rand` and friends. Saving its state and restoring it later
+    /// makes scripts draw the same numbers again, for replays.
+    pub fn random(&self) -> &Random {
+        &self.random
+    }
+
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// Warnings about the code loaded last, like a match that doesn't handle every value. They
+    /// don't stop the code from running, `Error::render_warning` shows them with `sources`.
+    pub fn warnings(&self) -> &[Error] {
+        &self.warnings
+    }
+}
+
+impl Default for ScriptRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::vm::OutputBuffer;
+    use glam::DVec2;
+
+    fn runtime() -> (ScriptRuntime, OutputBuffer) {
+        let output = OutputBuffer::default();
+        (ScriptRuntime::new().with_output(output.clone()), output)
+    }
+
+    #[test]
+    fn test_calls_across_frames() {
+        let (mut runtime, output) = runtime();
+        runtime
+            .load(
+                "game",
+                "let mut time = 0.0\nlet mut frames = 0\nfn update(dt) {\n    time += dt\n    frames += 1\n    return frames\n}\n",
+            )
+            .unwrap();
+        for _ in 0..3 {
+            runtime.call::<()>("update", (0.5,)).unwrap();
+        }
+        assert_eq!(runtime.get("time"), Some(Value::Real(1.5)));
+        assert_eq!(runtime.call("update", (0.5,)), Ok(4));
+        assert_eq!(output.contents(), "");
+    }
+
+    #[test]
+    fn test_chunks_share_globals() {
+        let (mut runtime, output) = runtime();
+        runtime
+            .load("first", "struct Enemy { health }\nlet enemies = []\n")
+            .unwrap();
+        runtime
+            .load(
+                "second",
+                "fn spawn(health) {\n    push(enemies, Enemy { health: health })\n}\nspawn(3)\n",
+            )
+            .unwrap();
+        runtime.call::<()>("spawn", (5,)).unwrap();
+        runtime.load("third", "print enemies\n").unwrap();
+        assert_eq!(
+            output.contents(),
+            "[Enemy { health: 3 }, Enemy { health: 5 }]\n"
+        );
+
+        let errors = runtime.load("fourth", "print missing\n").unwrap_err();
+        assert_eq!(errors[0].message, "Variable missing not found");
+    }
+
+    #[test]
+    fn test_reloading_keeps_state() {
+        let (mut runtime, output) = runtime();
+        runtime
+            .load(
+                "v1",
+                "let mut score = 0\nfn hit() {\n    score += 1\n    print \"v1 {score}\"\n}\n",
+            )
+            .unwrap();
+        runtime.call::<()>("hit", ()).unwrap();
+        runtime
+            .load(
+                "v2",
+                "fn hit() {\n    score += 10\n    print \"v2 {score}\"\n}\n",
+            )
+            .unwrap();
+        runtime.call::<()>("hit", ()).unwrap();
+        assert_eq!(output.contents(), "v1 1\nv2 11\n");
+    }
+
+    #[test]
+    fn test_errors_leave_runtime_usable() {
+        let (mut runtime, output) = runtime();
+        runtime
+            .load(
+                "game",
+                "fn divide(a, b) {\n    return a / b\n}\nfn depth(n) {\n    return depth(n + 1)\n}\n",
+            )
+            .unwrap();
+        let error = runtime.call::<Value>("divide", (1, 0)).unwrap_err();
+        let span = error.span.unwrap();
+        assert_eq!(runtime.sources().get(span.file).unwrap().name, "game");
+        assert_eq!(span.line, 2);
+
+        let error = runtime.call::<Value>("depth", (0,)).unwrap_err();
+        assert!(error.message.starts_with("Maximum call depth"));
+        let error = runtime.call::<Value>("divide", ()).unwrap_err();
+        assert_eq!(error.message, "Expected 2 arguments but got 0");
+        let error = runtime.call::<Value>("update", ()).unwrap_err();
+        assert_eq!(error.message, "Variable update not found");
+
+        let errors = runtime.load("broken", "let x = 1 +\n").unwrap_err();
+        assert!(!errors.is_empty());
+        runtime.load("runtime error", "print [1][5]\n").unwrap_err();
+
+        assert_eq!(runtime.call("divide", (6, 3)), Ok(2));
+        runtime.load("after", "print divide(9, 3)\n").unwrap();
+        assert_eq!(output.contents(), "3\n");
+    }
+
+    #[test]
+    fn test_host_globals() {
+        let (mut runtime, output) = runtime();
+        runtime.set("gravity", Value::Real(9.81));
+        runtime
+            .load(
+                "physics",
+                "let mut speed = 0.0\nspeed -= gravity\nprint speed\n",
+            )
+            .unwrap();
+        runtime.set("gravity", Value::Real(1.0));
+        runtime
+            .load("step", "speed -= gravity\nprint speed\n")
+            .unwrap();
+        assert_eq!(output.contents(), "-9.81\n-10.81\n");
+    }
+
+    #[test]
+    fn test_warnings() {
+        let (mut runtime, output) = runtime();
+        runtime
+            .load(
+                "state",
+                "let state = 2\nmatch state {\n    0 => print \"idle\"\n}\nprint state\n",
+            )
+            .unwrap();
+        assert_eq!(output.contents(), "2\n");
+        let warnings = runtime
+            .warnings()
+            .iter()
+            .map(|warning| warning.render_warning(runtime.sources()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec!["warning: Match is not exhaustive, add a '_' arm for other values\n --> state:2:1\n  |\n2 | match state {\n  | ^^^^^\n"]
+        );
+
+        runtime.load("clean", "print state\n").unwrap();
+        assert!(runtime.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_globals_keep_their_bindings() {
+        let (mut runtime, output) = runtime();
+        runtime
+            .load("a", "const G = 1\nlet x = 1\nfn f() {\n}\nlet mut y = 1\n")
+            .unwrap();
+        let error = |runtime: &mut ScriptRuntime, code: &str| {
+            runtime.load("b", code).unwrap_err().remove(0).message
+        };
+        assert_eq!(error(&mut runtime, "G = 5\n"), "Can't assign to constant G");
+        assert_eq!(
+            error(&mut runtime, "x = 5\n"),
+            "Can't assign to immutable variable x, declare it with 'let mut'"
+        );
+        assert_eq!(error(&mut runtime, "f = 5\n"), "Can't assign to function f");
+        runtime.load("b", "y = 5\n").unwrap();
+
+        // Declaring them again replaces them, like when a script is reloaded
+        runtime
+            .load("c", "const G = 2\nlet mut x = 2\nx += G\nprint x\n")
+            .unwrap();
+        assert_eq!(error(&mut runtime, "G = 5\n"), "Can't assign to constant G");
+        runtime.load("d", "x = 5\nprint x + y\n").unwrap();
+        assert_eq!(output.contents(), "4\n10\n");
+    }
+
+    #[test]
+    fn test_annotated_reals() {
+        let (mut runtime, output) = runtime();
+        runtime
+            .load("types", "let a: real = 2\nlet mut b: real = 0\n")
+            .unwrap();
+        runtime.load("later", "b = 3\nprint b / 2\n").unwrap();
+        assert_eq!(runtime.get("a"), Some(Value::Real(2.0)));
+        assert_eq!(runtime.get("b"), Some(Value::Real(3.0)));
+        assert_eq!(output.contents(), "1.5\n");
+    }
+
+    #[test]
+    fn test_registered_functions() {
+        let (mut runtime, output) = runtime();
+        let spawned = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let log = spawned.clone();
+        runtime.register_fn("spawn", move |x: f64, y: f64| {
+            log.borrow_mut().push((x, y));
+            log.borrow().len() as i64
+        });
+        runtime.register_fn("distance", |a: DVec2, b: DVec2| a.distance(b));
+        runtime
+            .load(
+                "level",
+                "print spawn(1, 2.5)\nprint spawn(-1.0, 0)\nprint distance(vec2(0, 0), vec2(3, 4))\n",
+            )
+            .unwrap();
+        assert_eq!(*spawned.borrow(), vec![(1.0, 2.5), (-1.0, 0.0)]);
+        assert_eq!(output.contents(), "1\n2\n5\n");
+
+        let errors = runtime.load("arity", "spawn(1)\n").unwrap_err();
+        assert_eq!(errors[0].message, "Expected 2 arguments but got 1");
+        let errors = runtime.load("types", "spawn(1, \"two\")\n").unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "Expected real for argument 2 but got string"
+        );
+        assert_eq!(errors[0].span.unwrap().line, 1);
+    }
+
+    #[test]
+    fn test_typed_calls() {
+        let (mut runtime, _) = runtime();
+        runtime
+            .load(
+                "player",
+                "let mut health = 10\nfn on_hit(damage) {\n    health -= damage\n    return (health, health <= 0)\n}\nfn name() {\n    return \"player\"\n}\n",
+            )
+            .unwrap();
+        assert_eq!(runtime.call("on_hit", (4,)), Ok((6, false)));
+        assert_eq!(runtime.call("on_hit", (6,)), Ok((0, true)));
+        assert_eq!(runtime.call("name", ()), Ok("player".to_string()));
+
+        let error = runtime.call::<f64>("name", ()).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Expected real for the return value but got string"
+        );
+        let error = runtime.call::<()>("on_hit", (1, 2)).unwrap_err();
+        assert_eq!(error.message, "Expected 1 arguments but got 2");
+    }
+
+    #[test]
+    fn test_random_state() {
+        let (mut other, other_output) = runtime();
+        let (mut runtime, output) = runtime();
+        runtime
+            .load(
+                "dice",
+                "seed(2024)\nfn roll() {\n    return (rand_int(1, 6), rand(), choose([\"a\", \"b\", \"c\"]))\n}\n",
+            )
+            .unwrap();
+        let saved = runtime.random().state();
+        let rolls = (0..5)
+            .map(|_| runtime.call("roll", ()).unwrap())
+            .collect::<Vec<(i64, f64, String)>>();
+
+        runtime.random().set_state(saved);
+        for roll in &rolls {
+            assert_eq!(runtime.call("roll", ()).as_ref(), Ok(roll));
+        }
+
+        // Seeding from the host and from a script give the same numbers
+        other.random().seed(7);
+        other
+            .load("dice", "print rand_int(1, 100)\nprint rand()\n")
+            .unwrap();
+        runtime
+            .load("again", "seed(7)\nprint rand_int(1, 100)\nprint rand()\n")
+            .unwrap();
+        assert_eq!(output.contents(), other_output.contents());
+        assert_ne!(output.contents(), "");
+    }
+}
@@ -0,0 +1,217 @@
+use glam::{DVec2, DVec3};
+
+use crate::parser::{error::Error, parser::Value, token::TokenType};
+
+use super::interpreter::Environment;
+
+/// Defines the constructors and functions working on vectors.
+pub fn define(environment: &mut Environment) {
+    environment.define_native("vec2", 2, |arguments| {
+        Ok(Value::Vec2(DVec2::new(
+            number(&arguments[0])?,
+            number(&arguments[1])?,
+        )))
+    });
+    environment.define_native("vec3", 3, |arguments| {
+        Ok(Value::Vec3(DVec3::new(
+            number(&arguments[0])?,
+            number(&arguments[1])?,
+            number(&arguments[2])?,
+        )))
+    });
+    environment.define_native("length", 1, |arguments| match &arguments[0] {
+        Value::Vec2(vector) => Ok(Value::Real(vector.length())),
+        Value::Vec3(vector) => Ok(Value::Real(vector.length())),
+        value => Err(Error::new(format!("Expected vector but got {}", value))),
+    });
+    // A zero vector stays zero instead of turning into NaNs
+    environment.define_native("normalize", 1, |arguments| match &arguments[0] {
+        Value::Vec2(vector) => Ok(Value::Vec2(vector.normalize_or_zero())),
+        Value::Vec3(vector) => Ok(Value::Vec3(vector.normalize_or_zero())),
+        value => Err(Error::new(format!("Expected vector but got {}", value))),
+    });
+    environment.define_native("dot", 2, |arguments| match (&arguments[0], &arguments[1]) {
+        (Value::Vec2(left), Value::Vec2(right)) => Ok(Value::Real(left.dot(*right))),
+        (Value::Vec3(left), Value::Vec3(right)) => Ok(Value::Real(left.dot(*right))),
+        (left, right) => Err(Error::new(format!(
+            "Expected two vectors of the same size but got {} and {}",
+            left, right
+        ))),
+    });
+    environment.define_native("cross", 2, |arguments| {
+        match (&arguments[0], &arguments[1]) {
+            (Value::Vec3(left), Value::Vec3(right)) => Ok(Value::Vec3(left.cross(*right))),
+            (left, right) => Err(Error::new(format!(
+                "Expected two vec3 but got {} and {}",
+                left, right
+            ))),
+        }
+    });
+}
+
+pub fn number(value: &Value) -> Result<f64, Error> {
+    match value {
+        Value::Integer(int) => Ok(*int as f64),
+        Value::Real(real) => Ok(*real),
+        value => Err(Error::new(format!("Expected number but got {}", value))),
+    }
+}
+
+/// Applies a binary operator if one of the operands is a vector. Vectors combine
+/// component-wise and can be scaled by a number.
+pub fn binary_operation(
+    operator: &TokenType,
+    left: &Value,
+    right: &Value,
+) -> Option<Result<Value, Error>> {
+    let is_vector = |value: &Value| matches!(value, Value::Vec2(_) | Value::Vec3(_));
+    if !is_vector(left) && !is_vector(right) {
+        return None;
+    }
+    // Equality compares like any other value
+    if let TokenType::EqualsEquals | TokenType::BangEquals = operator {
+        return None;
+    }
+
+    let result = match (operator, left, right) {
+        (TokenType::Plus, Value::Vec2(left), Value::Vec2(right)) => Value::Vec2(*left + *right),
+        (TokenType::Plus, Value::Vec3(left), Value::Vec3(right)) => Value::Vec3(*left + *right),
+        (TokenType::Minus, Value::Vec2(left), Value::Vec2(right)) => Value::Vec2(*left - *right),
+        (TokenType::Minus, Value::Vec3(left), Value::Vec3(right)) => Value::Vec3(*left - *right),
+        (TokenType::Star, Value::Vec2(left), Value::Vec2(right)) => Value::Vec2(*left * *right),
+        (TokenType::Star, Value::Vec3(left), Value::Vec3(right)) => Value::Vec3(*left * *right),
+        (TokenType::Slash, Value::Vec2(left), Value::Vec2(right)) => Value::Vec2(*left / *right),
+        (TokenType::Slash, Value::Vec3(left), Value::Vec3(right)) => Value::Vec3(*left / *right),
+        (TokenType::Star | TokenType::Slash, Value::Vec2(vector), scalar) => {
+            let scalar = match number(scalar) {
+                Ok(scalar) => scalar,
+                Err(err) => return Some(Err(err)),
+            };
+            match operator {
+                TokenType::Star => Value::Vec2(*vector * scalar),
+                _ => Value::Vec2(*vector / scalar),
+            }
+        }
+        (TokenType::Star | TokenType::Slash, Value::Vec3(vector), scalar) => {
+            let scalar = match number(scalar) {
+                Ok(scalar) => scalar,
+                Err(err) => return Some(Err(err)),
+            };
+            match operator {
+                TokenType::Star => Value::Vec3(*vector * scalar),
+                _ => Value::Vec3(*vector / scalar),
+            }
+        }
+        (TokenType::Star, scalar, Value::Vec2(vector)) => match number(scalar) {
+            Ok(scalar) => Value::Vec2(scalar * *vector),
+            Err(err) => return Some(Err(err)),
+        },
+        (TokenType::Star, scalar, Value::Vec3(vector)) => match number(scalar) {
+            Ok(scalar) => Value::Vec3(scalar * *vector),
+            Err(err) => return Some(Err(err)),
+        },
+        (_, left, right) => {
+            return Some(Err(Error::new(format!(
+                "Can't combine {} and {}",
+                left, right
+            ))))
+        }
+    };
+    Some(Ok(result))
+}
+
+/// Indices of the components named by a swizzle like `xzy`, if `name` is one.
+pub fn swizzle(name: &str, dimensions: usize) -> Option<Vec<usize>> {
+    if name.is_empty() || name.len() > 3 {
+        return None;
+    }
+    name.chars()
+        .map(|component| match component {
+            'x' => Some(0),
+            'y' => Some(1),
+            'z' => Some(2),
+            _ => None,
+        })
+        .map(|index| index.filter(|index| *index < dimensions))
+        .collect()
+}
+
+pub fn components(value: &Value) -> Option<Vec<f64>> {
+    match value {
+        Value::Vec2(vector) => Some(vector.to_array().to_vec()),
+        Value::Vec3(vector) => Some(vector.to_array().to_vec()),
+        _ => None,
+    }
+}
+
+/// Reads the swizzled components of a vector as a real, vec2 or vec3.
+pub fn read_swizzle(vector: &Value, swizzle: &[usize]) -> Value {
+    let components = components(vector).unwrap_or_default();
+    let component = |i: usize| components[swizzle[i]];
+    match swizzle.len() {
+        1 => Value::Real(component(0)),
+        2 => Value::Vec2(DVec2::new(component(0), component(1))),
+        _ => Value::Vec3(DVec3::new(component(0), component(1), component(2))),
+    }
+}
+
+/// Returns `vector` with the swizzled components replaced by `value`.
+pub fn write_swizzle(vector: &Value, swizzle: &[usize], value: Value) -> Result<Value, Error> {
+    let Some(mut target) = components(vector) else {
+        return Err(Error::new(format!("Expected vector but got {}", vector)));
+    };
+    let written = match (swizzle.len(), &value) {
+        (1, Value::Integer(_) | Value::Real(_)) => vec![number(&value)?],
+        (2, Value::Vec2(_)) | (3, Value::Vec3(_)) => components(&value).unwrap_or_default(),
+        _ => {
+            return Err(Error::new(format!(
+                "Can't assign {} to {} components",
+                value,
+                swizzle.len()
+            )))
+        }
+    };
+    for (i, index) in swizzle.iter().enumerate() {
+        if swizzle[..i].contains(index) {
+            return Err(Error::new(
+                "Can't assign to a swizzle with repeated components".to_string(),
+            ));
+        }
+        target[*index] = written[i];
+    }
+
+    Ok(match vector {
+        Value::Vec2(_) => Value::Vec2(DVec2::from_slice(&target)),
+        _ => Value::Vec3(DVec3::from_slice(&target)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swizzle() {
+        assert_eq!(swizzle("x", 2), Some(vec![0]));
+        assert_eq!(swizzle("xzy", 3), Some(vec![0, 2, 1]));
+        assert_eq!(swizzle("z", 2), None);
+        assert_eq!(swizzle("xyzx", 3), None);
+        assert_eq!(swizzle("len", 3), None);
+        assert_eq!(swizzle("", 3), None);
+    }
+
+    #[test]
+    fn test_write_swizzle() {
+        let vector = Value::Vec3(DVec3::new(1.0, 2.0, 3.0));
+        assert_eq!(
+            write_swizzle(&vector, &[2, 0], Value::Vec2(DVec2::new(5.0, 6.0))),
+            Ok(Value::Vec3(DVec3::new(6.0, 2.0, 5.0)))
+        );
+        assert_eq!(
+            write_swizzle(&vector, &[1], Value::Integer(0)),
+            Ok(Value::Vec3(DVec3::new(1.0, 0.0, 3.0)))
+        );
+        assert!(write_swizzle(&vector, &[0, 0], Value::Vec2(DVec2::ZERO)).is_err());
+        assert!(write_swizzle(&vector, &[0, 1], Value::Real(1.0)).is_err());
+    }
+}
@@ -0,0 +1,1069 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Debug, fmt::Write as _, io::Write, rc::Rc};
+
+use super::{
+    builtins::list_index,
+    chunk::{Capture, Function, Op, Variable},
+    compiler::compile,
+    interpreter::{
+        binary_operation, construct, destructure, match_pattern, member, tuple_elements,
+        unary_operation, Environment, Instance,
+    },
+    vector,
+};
+use crate::parser::{
+    error::Error,
+    parser::{Statement, TypeAnnotation, Value},
+    resolver::{Binding, GlobalScope},
+    span::Span,
+    token::{Token, TokenType},
+};
+
+/// A function compiled to bytecode with the variables it captured
+pub struct CompiledFunction {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Value>>>,
+}
+
+impl Debug for CompiledFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CompiledFunction({:?})", self.function.name)
+    }
+}
+
+impl PartialEq for CompiledFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Global variables, which compiled code refers to by index
+#[derive(Debug, Default)]
+pub struct Globals {
+    indices: HashMap<String, u32>,
+    names: Vec<String>,
+    // Globals the code refers to are only set once their declaration ran
+    values: Vec<Option<Value>>,
+    // How the globals were declared, so code loaded later can't assign to a constant.
+    // Globals the host defines have no binding and can be assigned to.
+    bindings: HashMap<String, Binding>,
+    // Annotations of globals that convert what is assigned to them, recorded by the compiler
+    annotations: HashMap<String, TypeAnnotation>,
+}
+
+impl Globals {
+    pub fn new(environment: &Environment) -> Self {
+        let mut globals = Self::default();
+        for (name, value) in environment.globals() {
+            globals.define(name, value.clone());
+        }
+        globals.bindings = environment
+            .globals()
+            .map(|(name, _)| name)
+            .filter(|name| environment.is_constant(name))
+            .map(|name| (name.clone(), Binding::Constant))
+            .collect();
+        globals
+    }
+
+    /// Index of a global, which is reserved when it doesn't exist yet
+    pub fn index(&mut self, name: &str) -> u32 {
+        if let Some(index) = self.indices.get(name) {
+            return *index;
+        }
+        let index = self.names.len() as u32;
+        self.indices.insert(name.to_string(), index);
+        self.names.push(name.to_string());
+        self.values.push(None);
+        index
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        let index = *self.indices.get(name)?;
+        self.values[index as usize].as_ref()
+    }
+
+    pub fn define(&mut self, name: &str, value: Value) {
+        let index = self.index(name);
+        self.values[index as usize] = Some(value);
+        self.bindings.remove(name);
+        self.annotations.remove(name);
+    }
+
+    /// Records how code declared a global, which replaces how it was declared before
+    pub fn bind(&mut self, name: &str, binding: Binding) {
+        self.bindings.insert(name.to_string(), binding);
+    }
+
+    pub fn annotation(&self, name: &str) -> Option<&TypeAnnotation> {
+        self.annotations.get(name)
+    }
+
+    pub fn annotate(&mut self, name: &str, annotation: Option<TypeAnnotation>) {
+        match annotation {
+            Some(annotation) => self.annotations.insert(name.to_string(), annotation),
+            None => self.annotations.remove(name),
+        };
+    }
+}
+
+impl GlobalScope for Globals {
+    fn global(&self, name: &str) -> Option<Value> {
+        self.get(name).cloned()
+    }
+
+    fn binding(&self, name: &str) -> Option<Binding> {
+        self.bindings.get(name).copied()
+    }
+}
+
+/// A slot of a call frame. Variables captured by closures live in a shared cell.
+enum Slot {
+    Value(Value),
+    Cell(Rc<RefCell<Value>>),
+}
+
+impl Slot {
+    fn get(&self) -> Value {
+        match self {
+            Slot::Value(value) => value.clone(),
+            Slot::Cell(cell) => cell.borrow().clone(),
+        }
+    }
+}
+
+struct Frame {
+    closure: Rc<CompiledFunction>,
+    ip: usize,
+    // Index of the first slot of the frame
+    slots: usize,
+    // Height of the operand stack below the called function
+    stack: usize,
+}
+
+/// Target of an assignment, like the places of the tree walking interpreter
+enum Place {
+    Slot(usize),
+    Cell(Rc<RefCell<Value>>),
+    Global(u32),
+    Field(Rc<RefCell<Instance>>, usize),
+    Element(Rc<RefCell<Vec<Value>>>, usize),
+    Swizzle(Box<Place>, Vec<usize>),
+    Tuple(Vec<Place>),
+    // A variable whose annotation converts the values written to it
+    Converted(Box<Place>, TypeAnnotation),
+}
+
+/// Collects printed output, every clone writes to the same buffer
+#[derive(Debug, Clone, Default)]
+pub struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Call frames of the VM live on the heap, so it can go deeper than the tree walker
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 256;
+
+/// Runs compiled code on a stack of operands, with the variables of every call in slots
+pub struct Vm {
+    globals: Globals,
+    output: Box<dyn Write>,
+    frames: Vec<Frame>,
+    stack: Vec<Value>,
+    slots: Vec<Slot>,
+    places: Vec<Place>,
+    max_call_depth: usize,
+}
+
+impl Vm {
+    pub fn new(environment: &Environment) -> Self {
+        Self {
+            globals: Globals::new(environment),
+            output: Box::new(std::io::stdout()),
+            frames: Vec::new(),
+            stack: Vec::new(),
+            slots: Vec::new(),
+            places: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+        }
+    }
+
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    pub fn globals(&self) -> &Globals {
+        &self.globals
+    }
+
+    pub fn globals_mut(&mut self) -> &mut Globals {
+        &mut self.globals
+    }
+
+    pub fn interpret(&mut self, statements: &[Statement]) -> Result<(), Error> {
+        let function = compile(statements, &mut self.globals)?;
+        self.run(function)
+    }
+
+    pub fn run(&mut self, function: Rc<Function>) -> Result<(), Error> {
+        let closure = Rc::new(CompiledFunction {
+            function,
+            upvalues: Vec::new(),
+        });
+        self.call(Value::CompiledFunction(closure), Vec::new())
+            .map(|_| ())
+    }
+
+    /// Calls a function from outside of the running code, like once per frame from the engine
+    pub fn call(&mut self, callee: Value, arguments: Vec<Value>) -> Result<Value, Error> {
+        let (depth, stack, slots) = (self.frames.len(), self.stack.len(), self.slots.len());
+        let count = arguments.len();
+        self.stack.push(callee);
+        self.stack.extend(arguments);
+        let result = match self.call_value(count, Span::default()) {
+            // Native functions return right away
+            Ok(()) if self.frames.len() == depth => Ok(self.pop()),
+            Ok(()) => self.execute(depth),
+            Err(err) => Err(err),
+        };
+        if result.is_err() {
+            // Leaves the VM like before the call, so it can run more code
+            self.stack.truncate(stack);
+            self.slots.truncate(slots);
+            self.frames.truncate(depth);
+            self.places.clear();
+        }
+        result
+    }
+
+    fn push_frame(&mut self, closure: Rc<CompiledFunction>, arity: usize) {
+        let stack = self.stack.len() - arity - 1;
+        let slots = self.slots.len();
+        self.slots
+            .extend(self.stack.drain(stack + 1..).map(Slot::Value));
+        let size = slots + closure.function.slots;
+        self.slots.resize_with(size, || Slot::Value(Value::Nil));
+        self.frames.push(Frame {
+            closure,
+            ip: 0,
+            slots,
+            stack,
+        });
+    }
+
+    /// Runs until the frame at `depth` returns
+    fn execute(&mut self, depth: usize) -> Result<Value, Error> {
+        let mut function = self.frame().closure.function.clone();
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let ip = frame.ip;
+            frame.ip += 1;
+
+            let (op, span) = (function.chunk.code[ip], function.chunk.spans[ip]);
+            let result = self
+                .step(&function, op, span, depth)
+                .map_err(|err| err.or_span(span))?;
+            if let Some(value) = result {
+                return Ok(value);
+            }
+            // Only calls and returns change the running function
+            if let Op::Call(_) | Op::Return = op {
+                function = self.frame().closure.function.clone();
+            }
+        }
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().unwrap()
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    fn pop_values(&mut self, count: u32) -> Vec<Value> {
+        let start = self.stack.len() - count as usize;
+        self.stack.drain(start..).collect()
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.frames.last_mut().unwrap().ip = target as usize;
+    }
+
+    fn get(&self, variable: Variable) -> Result<Value, Error> {
+        let frame = self.frame();
+        match variable {
+            Variable::Local(slot) | Variable::Cell(slot) => {
+                Ok(self.slots[frame.slots + slot as usize].get())
+            }
+            Variable::Upvalue(index) => Ok(frame.closure.upvalues[index as usize].borrow().clone()),
+            Variable::Global(index) => {
+                self.globals.values[index as usize].clone().ok_or_else(|| {
+                    let name = &self.globals.names[index as usize];
+                    Error::new(format!("Variable {} not found", name))
+                })
+            }
+        }
+    }
+
+    fn cell(&self, slot: usize) -> Rc<RefCell<Value>> {
+        match &self.slots[slot] {
+            Slot::Cell(cell) => cell.clone(),
+            Slot::Value(_) => unreachable!("slot {} holds no cell", slot),
+        }
+    }
+
+    fn set(&mut self, variable: Variable, value: Value, define: bool) -> Result<(), Error> {
+        let frame = self.frames.last().unwrap();
+        match variable {
+            // Replaces the slot, it may still hold the cell of a variable whose scope ended
+            Variable::Local(slot) => self.slots[frame.slots + slot as usize] = Slot::Value(value),
+            Variable::Cell(slot) => *self.cell(frame.slots + slot as usize).borrow_mut() = value,
+            Variable::Upvalue(index) => {
+                *frame.closure.upvalues[index as usize].borrow_mut() = value
+            }
+            Variable::Global(index) => {
+                let global = &mut self.globals.values[index as usize];
+                if global.is_none() && !define {
+                    let name = &self.globals.names[index as usize];
+                    return Err(Error::new(format!("Variable {} is not declared", name)));
+                }
+                *global = Some(value);
+            }
+        }
+        Ok(())
+    }
+
+    fn step(
+        &mut self,
+        function: &Function,
+        op: Op,
+        span: Span,
+        depth: usize,
+    ) -> Result<Option<Value>, Error> {
+        let chunk = &function.chunk;
+        match op {
+            Op::Constant(index) => self.stack.push(chunk.constants[index as usize].clone()),
+            Op::Pop => {
+                self.pop();
+            }
+            Op::Dup => self.stack.push(self.stack.last().unwrap().clone()),
+            Op::Get(variable) => {
+                let value = self.get(variable)?;
+                self.stack.push(value);
+            }
+            Op::Define(variable) => {
+                let value = self.pop();
+                self.set(variable, value, true)?;
+            }
+            Op::Store(variable) => {
+                let value = self.pop();
+                self.set(variable, value, false)?;
+            }
+            Op::Convert(index) => {
+                let value = self.pop();
+                let annotation = &chunk.annotations[index as usize];
+                self.stack.push(annotation.convert(value));
+            }
+            Op::NewCell(slot) => {
+                let slot = self.frame().slots + slot as usize;
+                self.slots[slot] = Slot::Cell(Rc::new(RefCell::new(Value::Nil)));
+            }
+            Op::Unary(operator) => {
+                let right = self.pop();
+                self.stack.push(unary_operation(&operator, right)?);
+            }
+            Op::Binary(operator) => {
+                let right = self.pop();
+                let left = self.pop();
+                self.stack.push(binary_operation(&operator, left, right)?);
+            }
+            Op::Add => self.number_operation(
+                TokenType::Plus,
+                |left, right| left.checked_add(right).map(Value::Integer),
+                |left, right| Value::Real(left + right),
+            )?,
+            Op::Subtract => self.number_operation(
+                TokenType::Minus,
+                |left, right| left.checked_sub(right).map(Value::Integer),
+                |left, right| Value::Real(left - right),
+            )?,
+            Op::Multiply => self.number_operation(
+                TokenType::Star,
+                |left, right| left.checked_mul(right).map(Value::Integer),
+                |left, right| Value::Real(left * right),
+            )?,
+            Op::Less => self.number_operation(
+                TokenType::Less,
+                |left, right| Some(Value::Boolean(left < right)),
+                |left, right| Value::Boolean(left < right),
+            )?,
+            Op::LessEquals => self.number_operation(
+                TokenType::LessEquals,
+                |left, right| Some(Value::Boolean(left <= right)),
+                |left, right| Value::Boolean(left <= right),
+            )?,
+            Op::Greater => self.number_operation(
+                TokenType::Greater,
+                |left, right| Some(Value::Boolean(left > right)),
+                |left, right| Value::Boolean(left > right),
+            )?,
+            Op::GreaterEquals => self.number_operation(
+                TokenType::GreaterEquals,
+                |left, right| Some(Value::Boolean(left >= right)),
+                |left, right| Value::Boolean(left >= right),
+            )?,
+            Op::ShortCircuit(operator, target) => {
+                let left = logical_operand(&operator, self.stack.last().unwrap())?;
+                match (operator, left) {
+                    (TokenType::And, false) | (TokenType::Or, true) => self.jump(target),
+                    _ => {
+                        self.pop();
+                    }
+                }
+            }
+            Op::Logical(operator) => {
+                logical_operand(&operator, self.stack.last().unwrap())?;
+            }
+            Op::Jump(target) => self.jump(target),
+            Op::JumpIfFalse(target, condition) => match self.pop() {
+                Value::Boolean(true) => {}
+                Value::Boolean(false) => self.jump(target),
+                _ => return Err(Error::new(condition.error_message().to_string())),
+            },
+            Op::Print => {
+                let value = self.pop();
+                writeln!(self.output, "{}", value)
+                    .map_err(|err| Error::new(format!("Failed to print: {}", err)))?;
+            }
+            Op::Call(count) => self.call_value(count as usize, span)?,
+            Op::Return => {
+                let value = self.pop();
+                let frame = self.frames.pop().unwrap();
+                self.stack.truncate(frame.stack);
+                self.slots.truncate(frame.slots);
+                if self.frames.len() == depth {
+                    return Ok(Some(value));
+                }
+                self.stack.push(value);
+            }
+            Op::Closure(index) => {
+                let function = chunk.functions[index as usize].clone();
+                let frame = self.frames.last().unwrap();
+                let upvalues = function
+                    .captures
+                    .iter()
+                    .map(|capture| match capture {
+                        Capture::Local(slot) => self.cell(frame.slots + *slot as usize),
+                        Capture::Upvalue(index) => frame.closure.upvalues[*index as usize].clone(),
+                    })
+                    .collect();
+                self.stack
+                    .push(Value::CompiledFunction(Rc::new(CompiledFunction {
+                        function,
+                        upvalues,
+                    })));
+            }
+            Op::Construct(index) => {
+                let construct_op = &chunk.constructs[index as usize];
+                let values = self.pop_values(construct_op.fields.len() as u32);
+                let value = self.pop();
+                let fields = construct_op.fields.iter().zip(values).collect();
+                let instance = construct(value, &construct_op.name, fields, construct_op.span)?;
+                self.stack.push(instance);
+            }
+            Op::Member(index) => {
+                let value = self.pop();
+                self.stack
+                    .push(member(value, &chunk.names[index as usize])?);
+            }
+            Op::Index => {
+                let index = self.pop();
+                let value = match self.pop() {
+                    Value::List(list) => {
+                        let list = list.borrow();
+                        list[list_index(&index, list.len())?].clone()
+                    }
+                    Value::Tuple(elements) => elements[list_index(&index, elements.len())?].clone(),
+                    value => return Err(Error::new(format!("Value {} can't be indexed", value))),
+                };
+                self.stack.push(value);
+            }
+            Op::List(count) => {
+                let elements = self.pop_values(count);
+                self.stack.push(Value::list(elements));
+            }
+            Op::Tuple(count) => {
+                let elements = self.pop_values(count);
+                self.stack.push(Value::Tuple(elements.into()));
+            }
+            Op::Interpolate(count) => {
+                let mut string = String::new();
+                for part in self.pop_values(count) {
+                    write!(string, "{}", part).unwrap();
+                }
+                self.stack.push(Value::String(string));
+            }
+            Op::ForStart(slot) => {
+                let collection = match self.pop() {
+                    Value::Range(range) => Value::Range(range),
+                    // Iterates over a snapshot, so the body may modify the list
+                    Value::List(list) => Value::list(list.borrow().clone()),
+                    _ => return Err(Error::new("Expected range or list".to_string())),
+                };
+                let slot = self.frame().slots + slot as usize;
+                self.slots[slot] = Slot::Value(collection);
+                self.slots[slot + 1] = Slot::Value(Value::Integer(0));
+            }
+            Op::ForNext(slot, target) => {
+                let slot = self.frame().slots + slot as usize;
+                let Slot::Value(Value::Integer(index)) = self.slots[slot + 1] else {
+                    unreachable!("for loop without index")
+                };
+                let element = match &self.slots[slot] {
+                    Slot::Value(Value::Range(range)) => range.get(index as usize),
+                    Slot::Value(Value::List(list)) => list.borrow().get(index as usize).cloned(),
+                    _ => None,
+                };
+                let Some(element) = element else {
+                    self.jump(target);
+                    return Ok(None);
+                };
+                self.slots[slot + 1] = Slot::Value(Value::Integer(index + 1));
+                self.stack.push(element);
+            }
+            Op::Match(index) => {
+                let pattern = &chunk.patterns[index as usize];
+                let enums = self.pop_values(pattern.enums.len() as u32);
+                let subject = self.pop();
+                let mut bindings = Vec::new();
+                let lookup = &mut |name: &Token| {
+                    let index = pattern.enums.iter().position(|other| other == name);
+                    Ok(enums[index.unwrap()].clone())
+                };
+                let matched = match_pattern(&pattern.pattern, &subject, &mut bindings, lookup)?;
+                if matched {
+                    self.stack
+                        .extend(bindings.into_iter().map(|(_, value)| value));
+                }
+                self.stack.push(Value::Boolean(matched));
+            }
+            Op::NoMatch => {
+                let subject = self.pop();
+                return Err(Error::new(format!("No match arm for {}", subject)));
+            }
+            Op::Destructure(index) => {
+                let value = self.pop();
+                let mut bindings = Vec::new();
+                destructure(
+                    &chunk.patterns[index as usize].pattern,
+                    value,
+                    &mut bindings,
+                )?;
+                self.stack
+                    .extend(bindings.into_iter().map(|(_, value)| value));
+            }
+            Op::PlaceVariable(variable) => {
+                let frame = self.frame();
+                let place = match variable {
+                    Variable::Local(slot) => Place::Slot(frame.slots + slot as usize),
+                    Variable::Cell(slot) => Place::Cell(self.cell(frame.slots + slot as usize)),
+                    Variable::Upvalue(index) => {
+                        Place::Cell(frame.closure.upvalues[index as usize].clone())
+                    }
+                    Variable::Global(index) => Place::Global(index),
+                };
+                self.places.push(place);
+            }
+            Op::PlaceMember(index) => {
+                let parent = self.places.pop().unwrap();
+                let object = self.read_place(&parent)?;
+                let place = member_place(Some(parent), object, &chunk.names[index as usize])?;
+                self.places.push(place);
+            }
+            Op::PlaceTemporaryMember(index) => {
+                let object = self.pop();
+                let place = member_place(None, object, &chunk.names[index as usize])?;
+                self.places.push(place);
+            }
+            Op::PlaceIndex => {
+                let index = self.pop();
+                let list = match self.pop() {
+                    Value::List(list) => list,
+                    Value::Tuple(_) => {
+                        return Err(Error::new(
+                            "Tuples can't be modified, assign a new tuple instead".to_string(),
+                        ))
+                    }
+                    value => return Err(Error::new(format!("Value {} can't be indexed", value))),
+                };
+                let index = list_index(&index, list.borrow().len())?;
+                self.places.push(Place::Element(list, index));
+            }
+            Op::PlaceTuple(count) => {
+                let start = self.places.len() - count as usize;
+                let places = self.places.drain(start..).collect();
+                self.places.push(Place::Tuple(places));
+            }
+            Op::PlaceConvert(index) => {
+                let place = self.places.pop().unwrap();
+                let annotation = chunk.annotations[index as usize].clone();
+                self.places
+                    .push(Place::Converted(Box::new(place), annotation));
+            }
+            Op::ReadPlace => {
+                let value = self.read_place(self.places.last().unwrap())?;
+                self.stack.push(value);
+            }
+            Op::WritePlace => {
+                let place = self.places.pop().unwrap();
+                let value = self.stack.last().unwrap().clone();
+                self.write_place(&place, value)?;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Applies `integer` to two integers and `real` to two reals without going through
+    /// binary_operation. When `integer` overflows or the operands are of other types,
+    /// binary_operation gives the result or the error.
+    fn number_operation(
+        &mut self,
+        operator: TokenType,
+        integer: fn(i64, i64) -> Option<Value>,
+        real: fn(f64, f64) -> Value,
+    ) -> Result<(), Error> {
+        let right = self.pop();
+        let left = self.pop();
+        let result = match (&left, &right) {
+            (Value::Integer(left), Value::Integer(right)) => integer(*left, *right),
+            (Value::Real(left), Value::Real(right)) => Some(real(*left, *right)),
+            _ => None,
+        };
+        let result = match result {
+            Some(result) => result,
+            None => binary_operation(&operator, left, right)?,
+        };
+        self.stack.push(result);
+        Ok(())
+    }
+
+    fn call_value(&mut self, count: usize, span: Span) -> Result<(), Error> {
+        let callee = self.stack[self.stack.len() - count - 1].clone();
+        let closure = match callee {
+            Value::CompiledFunction(closure) => closure,
+            Value::NativeFunction(native) => {
+                if count != native.arity {
+                    return Err(Error::new(format!(
+                        "Expected {} arguments but got {}",
+                        native.arity, count
+                    ))
+                    .with_span(span));
+                }
+                let arguments = self.pop_values(count as u32);
+                self.pop();
+                let result = (native.function)(arguments).map_err(|err| err.or_span(span))?;
+                self.stack.push(result);
+                return Ok(());
+            }
+            _ => {
+                return Err(Error::new(format!("Value {} is not callable", callee)).with_span(span))
+            }
+        };
+
+        if count != closure.function.arity {
+            return Err(Error::new(format!(
+                "Expected {} arguments but got {}",
+                closure.function.arity, count
+            ))
+            .with_span(span));
+        }
+        // The frame of the script itself doesn't count as a call
+        if self.frames.len() > self.max_call_depth {
+            return Err(Error::new(format!(
+                "Maximum call depth of {} exceeded",
+                self.max_call_depth
+            ))
+            .with_span(span));
+        }
+        self.push_frame(closure, count);
+        Ok(())
+    }
+
+    fn read_place(&self, place: &Place) -> Result<Value, Error> {
+        match place {
+            Place::Slot(slot) => Ok(self.slots[*slot].get()),
+            Place::Cell(cell) => Ok(cell.borrow().clone()),
+            Place::Global(index) => self.globals.values[*index as usize].clone().ok_or_else(|| {
+                let name = &self.globals.names[*index as usize];
+                Error::new(format!("Variable {} not found", name))
+            }),
+            Place::Field(instance, index) => Ok(instance.borrow().fields[*index].clone()),
+            Place::Element(list, index) => {
+                let list = list.borrow();
+                Ok(list[list_index(&Value::Integer(*index as i64), list.len())?].clone())
+            }
+            Place::Swizzle(place, swizzle) => {
+                let vector = self.read_place(place)?;
+                Ok(vector::read_swizzle(&vector, swizzle))
+            }
+            Place::Tuple(places) => {
+                let values = places
+                    .iter()
+                    .map(|place| self.read_place(place))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::Tuple(values.into()))
+            }
+            Place::Converted(place, _) => self.read_place(place),
+        }
+    }
+
+    fn write_place(&mut self, place: &Place, value: Value) -> Result<(), Error> {
+        match place {
+            Place::Slot(slot) => self.slots[*slot] = Slot::Value(value),
+            Place::Cell(cell) => *cell.borrow_mut() = value,
+            Place::Global(index) => {
+                let global = &mut self.globals.values[*index as usize];
+                if global.is_none() {
+                    let name = &self.globals.names[*index as usize];
+                    return Err(Error::new(format!("Variable {} is not declared", name)));
+                }
+                *global = Some(value);
+            }
+            Place::Field(instance, index) => instance.borrow_mut().fields[*index] = value,
+            Place::Element(list, index) => {
+                // The list may have shrunk while the assigned value was evaluated
+                let mut list = list.borrow_mut();
+                let index = list_index(&Value::Integer(*index as i64), list.len())?;
+                list[index] = value;
+            }
+            Place::Swizzle(place, swizzle) => {
+                let vector = self.read_place(place)?;
+                let vector = vector::write_swizzle(&vector, swizzle, value)?;
+                self.write_place(place, vector)?;
+            }
+            Place::Tuple(places) => {
+                let values = tuple_elements(value, places.len())?;
+                for (place, value) in places.iter().zip(values.iter()) {
+                    self.write_place(place, value.clone())?;
+                }
+            }
+            Place::Converted(place, annotation) => {
+                self.write_place(place, annotation.convert(value))?
+            }
+        }
+        Ok(())
+    }
+}
+
+fn logical_operand(operator: &TokenType, value: &Value) -> Result<bool, Error> {
+    match value {
+        Value::Boolean(bool) => Ok(*bool),
+        value => {
+            let lexeme = match operator {
+                TokenType::And => "and",
+                _ => "or",
+            };
+            Err(Error::new(format!(
+                "Expected boolean operands for {} but got {}",
+                lexeme, value
+            )))
+        }
+    }
+}
+
+/// The place of a member of `object`, which is a swizzle of `parent` for vectors
+fn member_place(parent: Option<Place>, object: Value, name: &Token) -> Result<Place, Error> {
+    let dimensions = vector::components(&object).map(|components| components.len());
+    if let Some(swizzle) = dimensions.and_then(|n| vector::swizzle(&name.lexeme, n)) {
+        return match parent {
+            Some(place) => Ok(Place::Swizzle(Box::new(place), swizzle)),
+            None => Err(Error::new(
+                "Can't assign to a component of a temporary vector".to_string(),
+            )
+            .with_span(name.span)),
+        };
+    }
+
+    let Value::Instance(instance) = &object else {
+        return Err(
+            Error::new(format!("Value {} has no member {}", object, name.lexeme))
+                .with_span(name.span),
+        );
+    };
+    let struct_type = instance.borrow().struct_type.clone();
+    let index = struct_type.field_index(&name.lexeme).ok_or_else(|| {
+        Error::new(format!(
+            "Struct {} has no field {}",
+            struct_type.name, name.lexeme
+        ))
+        .with_span(name.span)
+    })?;
+    Ok(Place::Field(instance.clone(), index))
+}
+
+pub fn interpret(statements: Vec<Statement>) -> Result<(), Error> {
+    Vm::new(&Environment::new()).interpret(&statements)
+}
+
+pub fn interpret_with_output(
+    statements: Vec<Statement>,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    let buffer = OutputBuffer::default();
+    let result = Vm::new(&Environment::new())
+        .with_output(buffer.clone())
+        .interpret(&statements);
+    output
+        .write_all(&buffer.0.borrow())
+        .map_err(|err| Error::new(format!("Failed to print: {}", err)))?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        interpreter::interpreter,
+        parser::{lexer::Lexer, parser::parse},
+    };
+
+    /// Runs the code with the tree walking interpreter and the VM, which have to agree on the
+    /// output and the error message. The resolver isn't run, so runtime errors get compared too.
+    fn run(code: &str) -> Result<String, Error> {
+        let chars = code.chars().collect::<Vec<char>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>()?;
+        let statements = parse(&tokens).map_err(|mut errors| errors.remove(0))?;
+
+        let mut expected = Vec::new();
+        let expected_result = interpreter::interpret_with_output(statements.clone(), &mut expected);
+        let mut output = Vec::new();
+        let result = interpret_with_output(statements, &mut output);
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, String::from_utf8(expected).unwrap(), "{}", code);
+        // Errors about a part of an instruction may point at the whole instruction
+        let line = |result: &Result<(), Error>| {
+            result
+                .clone()
+                .map_err(|err| (err.message, err.span.map(|span| span.line)))
+        };
+        assert_eq!(line(&result), line(&expected_result), "{}", code);
+        result.map(|_| output)
+    }
+
+    #[test]
+    fn test_expressions() {
+        let output = run(
+            "let x = 5\nprint x * 2 + 1\nprint -x / 2.0\nprint !(x > 3)\nprint \"x = {x}, {x * 2}\"\nprint x > 3 and x < 10 or false\nprint [1, 2, 3][1]\nprint (1, (2, 3))[1][0]\nprint vec2(1, 2).yx\nprint 0..10 step 2\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "11\n-2.5\nfalse\nx = 5, 10\ntrue\n2\n2\nvec2(2, 1)\n0..10 step 2\n"
+        );
+    }
+
+    #[test]
+    fn test_number_operations() {
+        let output = run(
+            "let (a, b) = (7, 2.5)\nprint a + 1\nprint a - 10\nprint a * 3\nprint b + b\nprint b * 2 - a\nprint a + b\nprint \"a\" + \"b\"\nprint vec2(1, 2) * 2 + vec2(1, 1)\nprint (a < 8, a <= 7, a > b, b >= 2.5, 2.5 < 2, \"a\" < \"b\")\nlet mut x = 9223372036854775806\nx += 1\nprint x\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "8\n-3\n21\n5\n-2\n9.5\nab\nvec2(3, 5)\n(true, true, true, true, false, true)\n9223372036854775807\n"
+        );
+        for code in ["x + 1", "x * 2", "-x - 2"] {
+            let error = run(&format!("let x = 9223372036854775807\nprint {}\n", code)).unwrap_err();
+            assert_eq!(error.message, "Integer overflow", "{}", code);
+        }
+        assert!(run("print 1 < \"a\"\n").is_err());
+        assert!(run("print true - 1\n").is_err());
+    }
+
+    #[test]
+    fn test_scopes() {
+        let output = run(
+            "let x = 1\n{\n    let mut x = 2\n    {\n        x = 3\n        let y = x\n        print y\n    }\n    print x\n    let x = 4\n    print x\n}\nprint x\nlet i = 100\nlet mut sum = 0\nfor i in 0..4 {\n    let doubled = i * 2\n    sum += doubled\n}\nprint i\nprint sum\n",
+        )
+        .unwrap();
+        assert_eq!(output, "3\n3\n4\n1\n100\n12\n");
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let output = run(
+            "let mut n = 0\nwhile n < 5 {\n    n += 1\n    if n == 2 {\n        continue\n    }\n    if n == 4 {\n        break\n    }\n    print n\n}\n'outer: for i in 0..3 {\n    for j in [10, 20, 30] {\n        if j == 20 {\n            continue 'outer\n        }\n        if i == 2 {\n            break 'outer\n        }\n        print i + j\n    }\n}\nlet mut k = 0\nloop {\n    k += 1\n    if k > 2 {\n        break\n    }\n}\nprint k\n",
+        )
+        .unwrap();
+        assert_eq!(output, "1\n3\n10\n11\n3\n");
+    }
+
+    #[test]
+    fn test_functions() {
+        let output = run(
+            "fn fib(n) {\n    if n < 2 {\n        return n\n    }\n    return fib(n - 1) + fib(n - 2)\n}\nprint fib(15)\nfn nothing() {\n    let x = 1\n}\nprint nothing()\nprint fib\nprint fn(x) { return x }\nprint len\nlet twice = fn(f, x) { return f(f(x)) }\nprint twice(fn(x) { return x * 3 }, 2)\n",
+        )
+        .unwrap();
+        assert_eq!(output, "610\nnil\n<fn fib>\n<fn>\n<native fn len>\n18\n");
+    }
+
+    #[test]
+    fn test_closures() {
+        let output = run(
+            "fn counter() {\n    let mut count = 0\n    return fn() {\n        count += 1\n        return count\n    }\n}\nlet a = counter()\nlet b = counter()\nprint a()\nprint a()\nprint b()\nlet mut fns = []\nfor i in 0..3 {\n    push(fns, fn() { return i * 10 })\n}\nfor f in fns {\n    print f()\n}\nfn outer(x) {\n    fn middle() {\n        fn inner() {\n            x = x + 1\n            return x\n        }\n        return inner\n    }\n    let f = middle()\n    f()\n    return f() + x\n}\nprint outer(1)\n{\n    fn is_even(n) {\n        if n == 0 {\n            return true\n        }\n        return is_odd(n - 1)\n    }\n    fn is_odd(n) {\n        if n == 0 {\n            return false\n        }\n        return is_even(n - 1)\n    }\n    print is_even(10)\n    print (is_even == is_even, is_even == is_odd)\n}\n",
+        )
+        .unwrap();
+        assert_eq!(output, "1\n2\n1\n0\n10\n20\n6\ntrue\n(true, false)\n");
+    }
+
+    #[test]
+    fn test_structs_and_places() {
+        let output = run(
+            "struct Player { position, health }\nlet p = Player { position: vec2(1, 2), health: 10 }\np.health -= 3\np.position.x = 5\np.position.yx += vec2(1, 1)\nprint p\nlet players = [p, Player { health: 1, position: vec2(0, 0) }]\nplayers[1].position.y = 7\nprint players[1]\nlet mut a = 1\nlet mut b = 2\na, b = b, a\nprint (a, b)\nlet list = [0, 0]\nlist[0], list[1] = (3, 4)\nprint list\nlet mut v = vec3(1, 2, 3)\nv.zx = vec2(9, 8)\nprint v\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "Player { position: vec2(6, 3), health: 7 }\nPlayer { position: vec2(0, 7), health: 1 }\n(2, 1)\n[3, 4]\nvec3(8, 2, 9)\n"
+        );
+    }
+
+    #[test]
+    fn test_annotated_reals() {
+        let output = run(
+            "let x: real = 2\nprint x / 4\nconst HALF: real = 1\nprint HALF / 2\nfn f(a: real) -> real {\n    return a / 2\n}\nprint f(3)\nfn g() -> real {\n    return 1\n}\nprint g() / 2\nfn h(a: real) {\n    return fn() { return a / 2 }\n}\nprint h(1)()\nlet mut speed: real = 1\nspeed = 3\nprint speed / 2\nlet mut size: (real, real) = (1, 2)\nsize = (3, 4)\nprint size[0] / 2\nlet mut a: real = 0\nlet mut b = 0\na, b = 5, 5\nprint (a / 2, b / 2)\nfn bump() {\n    total = 3\n}\nlet mut total: real = 0\nbump()\nprint total / 2\n{\n    fn set() {\n        local = 7\n    }\n    let mut local: real = 0\n    set()\n    print local / 2\n    let mut local = 0\n    local = 7\n    print local / 2\n}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "0.5\n0.5\n1.5\n0.5\n0.5\n1.5\n1.5\n(2.5, 2)\n1.5\n3.5\n3\n"
+        );
+    }
+
+    #[test]
+    fn test_extreme_ranges() {
+        let output = run(
+            "let m = -9223372036854775807 - 1\nfor i in m..=9223372036854775807 step 9223372036854775807 {\n    print i\n}\nprint (m..0).last\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "-9223372036854775808\n-1\n9223372036854775806\n-1\n"
+        );
+        let error = run("print len(0..=9223372036854775807)\n").unwrap_err();
+        assert_eq!(error.message, "Range is too long");
+    }
+
+    #[test]
+    fn test_match() {
+        let output = run(
+            "enum Shape { Circle(radius), Rect(size), Empty }\nfn area(shape) {\n    return match shape {\n        Shape.Circle(0) | Shape.Empty => 0,\n        Shape.Circle(r) => 3 * r * r,\n        Shape.Rect(Shape.Circle(r)) => r,\n        Shape.Rect(s) => s.x * s.y,\n    }\n}\nprint area(Shape.Circle(2))\nprint area(Shape.Empty)\nprint area(Shape.Rect(vec2(2, 3)))\nprint area(Shape.Rect(Shape.Circle(7)))\nlet x = 1\nlet y = match 5 { x if x < 3 => 0, x => x * 2 }\nprint x\nprint y\nfor n in [0, 4, 500] {\n    match n {\n        0 => print \"none\"\n        1..=5 => {\n            let m = n * 2\n            print m\n        }\n        big if big > 100 => print \"{big} is a lot\"\n    }\n}\nlet (position, (a, b)) = (1, 5), (2, 3)\nprint position\nprint a + b\nlet handlers = match 1 { n => fn() { return n + 1 } }\nprint handlers()\n",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "12\n0\n6\n7\n1\n10\nnone\n8\n500 is a lot\n(1, 5)\n5\n2\n"
+        );
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let errors = [
+            "print 1 + \"a\"\n",
+            "print undefined\n",
+            "x = 1\n",
+            "let a = [1, 2]\nprint a[2]\n",
+            "if 1 {\n}\n",
+            "while \"a\" {\n}\n",
+            "print 1 and true\n",
+            "print false or 1\n",
+            "fn f(a) {\n    return a\n}\nf(1, 2)\n",
+            "len(1, 2)\n",
+            "let x = 5\nx()\n",
+            "fn f() {\n    return vec2(1, 2)\n}\nf().x = 5\n",
+            "let t = (1, 2)\nt[0] = 5\n",
+            "let mut a = 1\nlet mut b = 2\na, b = 3\n",
+            "print match 1 { 2 => 0 }\n",
+            "print match 1 { x if x => 0, _ => 1 }\n",
+            "for i in 5 {\n}\n",
+            "struct P { x }\nprint P { x: 1, y: 2 }\n",
+            "struct P { x }\nprint P { }\n",
+            "let mut x = 1\nx += \"a\"\n",
+            "print 1 / 0\n",
+            "enum State { Idle }\nprint State.Running\n",
+            "let x = 1\nprint match 1 { x.Y => 1, _ => 2 }\n",
+        ];
+        for code in errors {
+            assert!(run(code).is_err(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_call_depth() {
+        let code = "fn f(n) {\n    if n == 0 {\n        return 0\n    }\n    return f(n - 1) + 1\n}\nprint f(9)\nprint f(10)\n"
+            .chars()
+            .collect::<Vec<char>>();
+        let tokens = Lexer::new(&code).collect::<Result<Vec<_>, _>>().unwrap();
+        let statements = parse(&tokens).unwrap();
+
+        let output = OutputBuffer::default();
+        let error = Vm::new(&Environment::new())
+            .with_output(output.clone())
+            .with_max_call_depth(10)
+            .interpret(&statements)
+            .unwrap_err();
+        assert_eq!(error.message, "Maximum call depth of 10 exceeded");
+        assert_eq!(output.contents(), "9\n");
+    }
+
+    #[test]
+    fn test_resolved_slots() {
+        let code =
+            "let g = 1\n{\n    let a = 2\n    let f = fn() { return a + g }\n    print f()\n}\n"
+                .chars()
+                .collect::<Vec<char>>();
+        let tokens = Lexer::new(&code).collect::<Result<Vec<_>, _>>().unwrap();
+        let statements = parse(&tokens).unwrap();
+        let mut globals = Globals::new(&Environment::new());
+        let function = compile(&statements, &mut globals).unwrap();
+
+        let code = &function.chunk.code;
+        assert!(code.contains(&Op::Define(Variable::Global(globals.index("g")))));
+        assert!(code.contains(&Op::NewCell(0)));
+        assert!(code.contains(&Op::Define(Variable::Cell(0))));
+        assert!(code.contains(&Op::Define(Variable::Local(1))));
+        let closure = &function.chunk.functions[0];
+        assert_eq!(closure.captures, vec![Capture::Local(0)]);
+        assert_eq!(
+            closure.chunk.code[..2],
+            [
+                Op::Get(Variable::Upvalue(0)),
+                Op::Get(Variable::Global(globals.index("g")))
+            ]
+        );
+    }
+}
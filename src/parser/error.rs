@@ -1,13 +1,148 @@
-#[derive(Debug)]
+use std::fmt::Write;
+
+use super::span::{SourceMap, Span};
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Error {
     pub message: String,
+    pub span: Option<Span>,
 }
 
 impl Error {
     pub fn new(message: String) -> Self {
-        Self { message }
+        Self {
+            message,
+            span: None,
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
     }
+
+    /// Attaches `span` only if the error doesn't point anywhere yet, so the innermost location wins.
+    pub fn or_span(mut self, span: Span) -> Self {
+        self.span = self.span.or(Some(span));
+        self
+    }
+
     pub fn print_error(&self) {
         eprintln!("ERROR: {}", self.message);
     }
+
+    pub fn print_error_with_source(&self, sources: &SourceMap) {
+        eprint!("{}", self.render(sources));
+    }
+
+    /// Renders the error with a snippet of the offending line and a caret under the span.
+    pub fn render(&self, sources: &SourceMap) -> String {
+        self.render_as("error", sources)
+    }
+
+    /// Renders a diagnostic that doesn't stop the program, like an unreachable match arm.
+    pub fn render_warning(&self, sources: &SourceMap) -> String {
+        self.render_as("warning", sources)
+    }
+
+    fn render_as(&self, severity: &str, sources: &SourceMap) -> String {
+        let mut rendered = format!("{}: {}\n", severity, self.message);
+
+        let Some((span, file)) = self
+            .span
+            .and_then(|span| Some((span, sources.get(span.file)?)))
+        else {
+            return rendered;
+        };
+
+        let start = span.start.min(file.text.len());
+        let line_start = file.text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = file.text[start..]
+            .find('\n')
+            .map_or(file.text.len(), |i| start + i);
+        let line = &file.text[line_start..line_end];
+
+        let line_number = file.text[..line_start].matches('\n').count() + 1;
+        let column = file.text[line_start..start].chars().count();
+        let width = file.text[start..span.end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+        let gutter = " ".repeat(line_number.to_string().len());
+
+        writeln!(
+            rendered,
+            "{}--> {}:{}:{}",
+            gutter,
+            file.name,
+            line_number,
+            column + 1
+        )
+        .unwrap();
+        writeln!(rendered, "{} |", gutter).unwrap();
+        writeln!(rendered, "{} | {}", line_number, line).unwrap();
+        writeln!(
+            rendered,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(column),
+            "^".repeat(width)
+        )
+        .unwrap();
+
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::span::FileId;
+
+    #[test]
+    fn test_render() {
+        let mut sources = SourceMap::new();
+        let file = sources.add(
+            "game.bz".to_string(),
+            "x = 1\nprint x + \"a\"\n".to_string(),
+        );
+        let span = Span {
+            file,
+            line: 2,
+            column: 7,
+            start: 12,
+            end: 19,
+        };
+
+        assert_eq!(
+            Error::new("Expected number".to_string())
+                .with_span(span)
+                .render(&sources),
+            "error: Expected number\n --> game.bz:2:7\n  |\n2 | print x + \"a\"\n  |       ^^^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let mut sources = SourceMap::new();
+        sources.add("game.bz".to_string(), "x\n".to_string());
+
+        assert_eq!(
+            Error::new("Oops".to_string()).render(&sources),
+            "error: Oops\n"
+        );
+        assert_eq!(
+            Error::new("Oops".to_string()).render_warning(&sources),
+            "warning: Oops\n"
+        );
+        assert_eq!(
+            Error::new("Oops".to_string())
+                .with_span(Span {
+                    file: FileId(7),
+                    ..Span::default()
+                })
+                .render(&sources),
+            "error: Oops\n"
+        );
+    }
 }
@@ -2,6 +2,7 @@ use phf::phf_map;
 
 use super::{
     error::Error,
+    span::{FileId, Span},
     token::{Token, TokenType},
 };
 
@@ -16,23 +17,61 @@ static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "else" => TokenType::Else,
     "for" => TokenType::For,
     "in" => TokenType::In,
+    "fn" => TokenType::Fn,
+    "return" => TokenType::Return,
+    "while" => TokenType::While,
+    "loop" => TokenType::Loop,
+    "break" => TokenType::Break,
+    "continue" => TokenType::Continue,
+    "struct" => TokenType::Struct,
+    "match" => TokenType::Match,
+    "enum" => TokenType::Enum,
+    "const" => TokenType::Const,
+    "mut" => TokenType::Mut,
 };
 
 pub struct Lexer<'a> {
     content: &'a [char],
-    current_line: u64,
+    file: FileId,
+    current_line: usize,
+    current_column: usize,
+    current_offset: usize,
+    // Curly bracket depth inside of every string interpolation we are currently in
+    interpolations: Vec<usize>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(content: &'a [char]) -> Self {
+        Self::with_file(content, FileId::default())
+    }
+
+    pub fn with_file(content: &'a [char], file: FileId) -> Self {
         Self {
             content,
-            current_line: 0,
+            file,
+            current_line: 1,
+            current_column: 1,
+            current_offset: 0,
+            interpolations: Vec::new(),
         }
     }
+
+    fn advance(&mut self, len: usize) {
+        for c in &self.content[0..len] {
+            self.current_offset += c.len_utf8();
+            if *c == '\n' {
+                self.current_line += 1;
+                self.current_column = 1;
+            } else {
+                self.current_column += 1;
+            }
+        }
+        self.content = &self.content[len..];
+    }
+
     fn chop(&mut self, len: usize) -> String {
         let lexeme = self.content[0..len].iter().collect();
-        self.content = &self.content[len..];
+        self.advance(len);
         lexeme
     }
 
@@ -52,7 +91,7 @@ impl<'a> Lexer<'a> {
         P: FnMut(&char) -> bool,
     {
         while !self.content.is_empty() && predicate(&self.content[0]) {
-            self.content = &self.content[1..]
+            self.advance(1);
         }
     }
 
@@ -75,24 +114,252 @@ impl<'a> Lexer<'a> {
         Some(self.content[offset])
     }
 
-    fn parse_number(&mut self) -> (TokenType, String) {
-        (TokenType::Integer, self.chop_while(|c| c.is_ascii_digit()))
+    fn digits_len<P>(&self, mut offset: usize, predicate: P) -> usize
+    where
+        P: Fn(char) -> bool,
+    {
+        while self.peek(offset).is_some_and(|c| predicate(c) || c == '_') {
+            offset += 1;
+        }
+        offset
+    }
+
+    fn malformed_number(&mut self, len: usize) -> Error {
+        let len = self.digits_len(len, |c| c.is_alphanumeric());
+        Error::new(format!("Malformed number literal '{}'", self.chop(len)))
+    }
+
+    fn parse_number(&mut self) -> Result<(TokenType, String), Error> {
+        if self.content[0] == '0' {
+            let radix = match self.peek(1) {
+                Some('x' | 'X') => Some(16),
+                Some('b' | 'B') => Some(2),
+                _ => None,
+            };
+            if let Some(radix) = radix {
+                return self.parse_radix_number(radix);
+            }
+        }
+
+        let mut token_type = TokenType::Integer;
+        let mut len = self.digits_len(0, |c| c.is_ascii_digit());
+
+        // A dot only starts a fraction when followed by a digit, so `1..10` stays a range
+        if self.peek(len) == Some('.') && self.peek(len + 1).is_some_and(|c| c.is_ascii_digit()) {
+            token_type = TokenType::Real;
+            len = self.digits_len(len + 1, |c| c.is_ascii_digit());
+        }
+
+        if matches!(self.peek(len), Some('e' | 'E')) {
+            let mut exponent = len + 1;
+            if matches!(self.peek(exponent), Some('+' | '-')) {
+                exponent += 1;
+            }
+            if !self.peek(exponent).is_some_and(|c| c.is_ascii_digit()) {
+                return Err(self.malformed_number(exponent));
+            }
+            token_type = TokenType::Real;
+            len = self.digits_len(exponent, |c| c.is_ascii_digit());
+        }
+
+        let separated = separators_between_digits(&self.content[..len], 10);
+        if !separated || self.peek(len).is_some_and(|c| c.is_alphanumeric()) {
+            return Err(self.malformed_number(len));
+        }
+
+        let lexeme = self.chop(len);
+        match token_type {
+            TokenType::Integer if parse_integer(&lexeme).is_none() => Err(Error::new(format!(
+                "Integer literal '{}' is out of range",
+                lexeme
+            ))),
+            TokenType::Real if parse_real(&lexeme).is_none() => Err(Error::new(format!(
+                "Real literal '{}' is out of range",
+                lexeme
+            ))),
+            _ => Ok((token_type, lexeme)),
+        }
+    }
+
+    fn parse_radix_number(&mut self, radix: u32) -> Result<(TokenType, String), Error> {
+        let len = self.digits_len(2, |c| c.is_digit(radix));
+
+        let digits = &self.content[2..len];
+        let has_digits = digits.iter().any(|c| *c != '_');
+        if !has_digits
+            || !separators_between_digits(digits, radix)
+            || self.peek(len).is_some_and(|c| c.is_alphanumeric())
+        {
+            return Err(self.malformed_number(len));
+        }
+
+        let lexeme = self.chop(len);
+        if parse_integer(&lexeme).is_none() {
+            return Err(Error::new(format!(
+                "Integer literal '{}' is out of range",
+                lexeme
+            )));
+        }
+        Ok((TokenType::Integer, lexeme))
+    }
+
+    fn parse_escape(&mut self) -> Result<char, Error> {
+        let escaped = self.chop(1).chars().next();
+        match escaped {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('0') => Ok('\0'),
+            Some(c @ ('\\' | '"' | '{' | '}')) => Ok(c),
+            Some('u') if self.peek(0) == Some('{') => {
+                let len = self.content.iter().position(|c| *c == '}' || *c == '"');
+                let Some(len) = len.filter(|len| self.content[*len] == '}') else {
+                    return Err(Error::new("Unterminated unicode escape".to_string()));
+                };
+                let code = self.chop(len + 1);
+                u32::from_str_radix(&code[1..len], 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(Error::new(format!("Invalid unicode escape '\\u{}'", code)))
+            }
+            Some(c) => Err(Error::new(format!("Unknown escape sequence '\\{}'", c))),
+            None => Err(Error::new("Unterminated string literal".to_string())),
+        }
+    }
+
+    // Lexes string content up to the closing quote or the start of an interpolation.
+    // `continued` is set when we are resuming a string after an interpolated expression.
+    fn parse_string(&mut self, continued: bool) -> Result<Token, Error> {
+        let mut string = String::new();
+        let mut error = None;
+
+        loop {
+            let Some(next) = self.peek(0) else {
+                return Err(Error::new("Unterminated string literal".to_string()));
+            };
+            self.chop(1);
+
+            match next {
+                '"' => {
+                    if let Some(error) = error {
+                        return Err(error);
+                    }
+                    let token_type = match continued {
+                        true => TokenType::InterpolationEnd,
+                        false => TokenType::String,
+                    };
+                    return Ok(Token::new(token_type, string));
+                }
+                '{' => {
+                    self.interpolations.push(0);
+                    if let Some(error) = error {
+                        return Err(error);
+                    }
+                    let token_type = match continued {
+                        true => TokenType::InterpolationMiddle,
+                        false => TokenType::InterpolationStart,
+                    };
+                    return Ok(Token::new(token_type, string));
+                }
+                '\\' => match self.parse_escape() {
+                    Ok(escaped) => string.push(escaped),
+                    Err(err) => error = error.or(Some(err)),
+                },
+                _ => string.push(next),
+            }
+        }
+    }
+
+    fn is_doc_comment(&self) -> bool {
+        self.content.starts_with(&['/', '/', '/']) && self.peek(3) != Some('/')
+    }
+
+    /// Skips a block comment, returns whether it spanned several lines
+    fn skip_block_comment(&mut self) -> Result<bool, Error> {
+        let (line, column, start) = (self.current_line, self.current_column, self.current_offset);
+        let mut depth = 0;
+        loop {
+            if self.content.starts_with(&['/', '*']) {
+                depth += 1;
+                self.advance(2);
+            } else if self.content.starts_with(&['*', '/']) {
+                depth -= 1;
+                self.advance(2);
+                if depth == 0 {
+                    return Ok(self.current_line > line);
+                }
+            } else if self.content.is_empty() {
+                let span = Span {
+                    file: self.file,
+                    line,
+                    column,
+                    start,
+                    end: self.current_offset,
+                };
+                return Err(Error::new("Unterminated block comment".to_string()).with_span(span));
+            } else {
+                self.advance(1);
+            }
+        }
     }
 
     pub fn next_token(&mut self) -> Option<Result<Token, Error>> {
-        self.trim_while(|x| *x != '\n' && x.is_whitespace());
+        let (line, column, start, token) = loop {
+            self.trim_while(|x| *x != '\n' && x.is_whitespace());
+
+            let (line, column, start) =
+                (self.current_line, self.current_column, self.current_offset);
+
+            // Line comments stop before the newline, so it still terminates the statement
+            if self.content.starts_with(&['/', '/']) && !self.is_doc_comment() {
+                self.trim_while(|x| *x != '\n');
+                continue;
+            }
+            // A comment over several lines ends the statement like the newlines inside it would
+            if self.content.starts_with(&['/', '*']) {
+                match self.skip_block_comment() {
+                    Ok(false) => continue,
+                    Ok(true) => {
+                        let newline = Token::new(TokenType::Newline, "\n".to_string());
+                        break (line, column, start, Ok(newline));
+                    }
+                    Err(err) => break (line, column, start, Err(err)),
+                }
+            }
+
+            break (line, column, start, self.lex_token()?);
+        };
 
+        let span = Span {
+            file: self.file,
+            line,
+            column,
+            start,
+            end: self.current_offset,
+        };
+
+        Some(match token {
+            Ok(token) => Ok(token.with_span(span)),
+            Err(err) => Err(err.or_span(span)),
+        })
+    }
+
+    fn lex_token(&mut self) -> Option<Result<Token, Error>> {
         if self.content.is_empty() {
             return None;
         }
 
-        if self.content[0].is_numeric() {
-            let (token_type, num) = self.parse_number();
-            return Some(Ok(Token::new(token_type, num)));
+        let starts_number = self.content[0].is_ascii_digit()
+            || (self.content[0] == '.' && self.peek(1).is_some_and(|c| c.is_ascii_digit()));
+        if starts_number {
+            return Some(
+                self.parse_number()
+                    .map(|(token_type, num)| Token::new(token_type, num)),
+            );
         }
 
-        if self.content[0].is_alphabetic() {
-            let str = self.chop_while(|x| x.is_alphabetic());
+        if self.content[0].is_alphabetic() || self.content[0] == '_' {
+            let str = self.chop_while(|x| x.is_alphanumeric() || *x == '_');
             if let Some(keyword) = KEYWORDS.get(&str).cloned() {
                 return Some(Ok(Token::new(keyword, str)));
             }
@@ -100,15 +367,43 @@ impl<'a> Lexer<'a> {
         }
 
         match self.content[0] {
-            '+' => Some(Ok(Token::new(TokenType::Plus, self.chop(1)))),
-            '-' => Some(Ok(Token::new(TokenType::Minus, self.chop(1)))),
-            '*' => Some(Ok(Token::new(TokenType::Star, self.chop(1)))),
-            '/' => Some(Ok(Token::new(TokenType::Slash, self.chop(1)))),
+            '+' => Some(self.double_opt_token_helper(TokenType::Plus, TokenType::PlusEquals, '=')),
+            '-' if self.peek(1) == Some('>') => {
+                Some(Ok(Token::new(TokenType::Arrow, self.chop(2))))
+            }
+            '-' => {
+                Some(self.double_opt_token_helper(TokenType::Minus, TokenType::MinusEquals, '='))
+            }
+            '*' if self.peek(1) == Some('*') => {
+                Some(Ok(Token::new(TokenType::StarStar, self.chop(2))))
+            }
+            '*' => Some(self.double_opt_token_helper(TokenType::Star, TokenType::StarEquals, '=')),
+            '%' => Some(Ok(Token::new(TokenType::Percent, self.chop(1)))),
+            '&' => Some(Ok(Token::new(TokenType::Ampersand, self.chop(1)))),
+            '|' => Some(Ok(Token::new(TokenType::Pipe, self.chop(1)))),
+            '^' => Some(Ok(Token::new(TokenType::Caret, self.chop(1)))),
+            '/' if self.is_doc_comment() => {
+                self.chop(3);
+                let comment = self.chop_while(|x| *x != '\n');
+                Some(Ok(Token::new(TokenType::DocComment, comment)))
+            }
+            '/' => {
+                Some(self.double_opt_token_helper(TokenType::Slash, TokenType::SlashEquals, '='))
+            }
+            '=' if self.peek(1) == Some('>') => {
+                Some(Ok(Token::new(TokenType::FatArrow, self.chop(2))))
+            }
             '=' => {
                 Some(self.double_opt_token_helper(TokenType::Equals, TokenType::EqualsEquals, '='))
             }
             '!' => Some(self.double_opt_token_helper(TokenType::Bang, TokenType::BangEquals, '=')),
+            '<' if self.peek(1) == Some('<') => {
+                Some(Ok(Token::new(TokenType::LessLess, self.chop(2))))
+            }
             '<' => Some(self.double_opt_token_helper(TokenType::Less, TokenType::LessEquals, '=')),
+            '>' if self.peek(1) == Some('>') => {
+                Some(Ok(Token::new(TokenType::GreaterGreater, self.chop(2))))
+            }
             '>' => Some(self.double_opt_token_helper(
                 TokenType::Greater,
                 TokenType::GreaterEquals,
@@ -118,21 +413,76 @@ impl<'a> Lexer<'a> {
             ')' => Some(Ok(Token::new(TokenType::RightParen, self.chop(1)))),
             '[' => Some(Ok(Token::new(TokenType::LeftSquareBracket, self.chop(1)))),
             ']' => Some(Ok(Token::new(TokenType::RightSquareBracket, self.chop(1)))),
-            '{' => Some(Ok(Token::new(TokenType::LeftCurlyBracket, self.chop(1)))),
-            '}' => Some(Ok(Token::new(TokenType::RightCurlyBracket, self.chop(1)))),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                Some(Ok(Token::new(TokenType::LeftCurlyBracket, self.chop(1))))
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.chop(1);
+                    Some(self.parse_string(true))
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    Some(Ok(Token::new(TokenType::RightCurlyBracket, self.chop(1))))
+                }
+                None => Some(Ok(Token::new(TokenType::RightCurlyBracket, self.chop(1)))),
+            },
+            '"' => {
+                self.chop(1);
+                Some(self.parse_string(false))
+            }
             ',' => Some(Ok(Token::new(TokenType::Comma, self.chop(1)))),
-            '.' => Some(self.double_opt_token_helper(TokenType::Dot, TokenType::DotDot, '.')),
-            '\n' => {
-                self.current_line += 1;
-                Some(Ok(Token::new(TokenType::Newline, self.chop(1))))
+            ':' => Some(Ok(Token::new(TokenType::Colon, self.chop(1)))),
+            '\'' if self.peek(1).is_some_and(|x| x.is_alphabetic() || x == '_') => {
+                let len = 1 + self.content[1..]
+                    .iter()
+                    .take_while(|x| x.is_alphanumeric() || **x == '_')
+                    .count();
+                Some(Ok(Token::new(TokenType::Label, self.chop(len))))
+            }
+            '.' if self.peek(1) == Some('.') && self.peek(2) == Some('=') => {
+                Some(Ok(Token::new(TokenType::DotDotEquals, self.chop(3))))
             }
-            _ => Some(Err(Error {
-                message: format!("Unknown token '{}'", self.chop(1)),
-            })),
+            '.' => Some(self.double_opt_token_helper(TokenType::Dot, TokenType::DotDot, '.')),
+            '\n' => Some(Ok(Token::new(TokenType::Newline, self.chop(1)))),
+            _ => Some(Err(Error::new(format!("Unknown token '{}'", self.chop(1))))),
         }
     }
 }
 
+// Every `_` has to be followed by a digit, so `1__000`, `1_` and `1_.5` are malformed
+fn separators_between_digits(digits: &[char], radix: u32) -> bool {
+    digits
+        .iter()
+        .zip(digits.iter().skip(1).map(Some).chain([None]))
+        .all(|(c, next)| *c != '_' || next.is_some_and(|next| next.is_digit(radix)))
+}
+
+/// Converts an integer lexeme (decimal, `0x` hex or `0b` binary, with optional `_` separators)
+/// into its value. Returns `None` when the value doesn't fit into an `i64`.
+pub fn parse_integer(lexeme: &str) -> Option<i64> {
+    let digits = lexeme.replace('_', "");
+    let (digits, radix) = match digits.get(..2) {
+        Some("0x" | "0X") => (&digits[2..], 16),
+        Some("0b" | "0B") => (&digits[2..], 2),
+        _ => (digits.as_str(), 10),
+    };
+    i64::from_str_radix(digits, radix).ok()
+}
+
+/// Converts a real lexeme into its value. Returns `None` when the value is not finite.
+pub fn parse_real(lexeme: &str) -> Option<f64> {
+    lexeme
+        .replace('_', "")
+        .parse::<f64>()
+        .ok()
+        .filter(|real| real.is_finite())
+}
+
 impl<'a> Iterator for Lexer<'a> {
     type Item = Result<Token, Error>;
 
@@ -148,26 +498,331 @@ mod tests {
     #[test]
     fn test1() {
         let code = "let x = 5;".chars().collect::<Vec<char>>();
-        let mut lexer = Lexer {
-            content: code.as_slice(),
-            current_line: 0,
+        let mut lexer = Lexer::new(code.as_slice());
+        let mut next_token = || {
+            lexer
+                .next_token()
+                .unwrap()
+                .unwrap()
+                .with_span(Span::default())
         };
 
+        assert_eq!(next_token(), Token::new(TokenType::Let, "let".to_string()));
         assert_eq!(
-            lexer.next_token().unwrap().unwrap(),
-            Token::new(TokenType::Let, "let".to_string())
+            next_token(),
+            Token::new(TokenType::Identifier, "x".to_string())
         );
+        assert_eq!(next_token(), Token::new(TokenType::Equals, "=".to_string()));
         assert_eq!(
-            lexer.next_token().unwrap().unwrap(),
-            Token::new(TokenType::Identifier, "x".to_string())
+            next_token(),
+            Token::new(TokenType::Integer, "5".to_string())
         );
+    }
+
+    fn lex(code: &str) -> Result<Vec<Token>, Error> {
+        let code = code.chars().collect::<Vec<char>>();
+        Lexer::new(&code)
+            .map(|token| token.map(|token| token.with_span(Span::default())))
+            .collect()
+    }
+
+    #[test]
+    fn test_real() {
         assert_eq!(
-            lexer.next_token().unwrap().unwrap(),
-            Token::new(TokenType::Equals, "=".to_string())
+            lex("1.5 .5 2e-3 1E10 3.25e+2").unwrap(),
+            vec![
+                Token::new(TokenType::Real, "1.5".to_string()),
+                Token::new(TokenType::Real, ".5".to_string()),
+                Token::new(TokenType::Real, "2e-3".to_string()),
+                Token::new(TokenType::Real, "1E10".to_string()),
+                Token::new(TokenType::Real, "3.25e+2".to_string()),
+            ]
         );
+        assert_eq!(parse_real("2e-3"), Some(2e-3));
+        assert_eq!(parse_real("3.25e+2"), Some(325.0));
+    }
+
+    #[test]
+    fn test_hex_binary() {
         assert_eq!(
-            lexer.next_token().unwrap().unwrap(),
-            Token::new(TokenType::Integer, "5".to_string())
+            lex("0xFF 0b1010 0x_dead_beef").unwrap(),
+            vec![
+                Token::new(TokenType::Integer, "0xFF".to_string()),
+                Token::new(TokenType::Integer, "0b1010".to_string()),
+                Token::new(TokenType::Integer, "0x_dead_beef".to_string()),
+            ]
+        );
+        assert_eq!(parse_integer("0xFF"), Some(255));
+        assert_eq!(parse_integer("0b1010"), Some(10));
+        assert_eq!(parse_integer("0x_dead_beef"), Some(0xdead_beef));
+    }
+
+    #[test]
+    fn test_separators() {
+        assert_eq!(
+            lex("1_000_000 1_000.000_1").unwrap(),
+            vec![
+                Token::new(TokenType::Integer, "1_000_000".to_string()),
+                Token::new(TokenType::Real, "1_000.000_1".to_string()),
+            ]
+        );
+        assert_eq!(parse_integer("1_000_000"), Some(1_000_000));
+        assert_eq!(parse_real("1_000.000_1"), Some(1_000.000_1));
+    }
+
+    #[test]
+    fn test_range_is_not_real() {
+        assert_eq!(
+            lex("1..10 1.5..2 1..=2").unwrap(),
+            vec![
+                Token::new(TokenType::Integer, "1".to_string()),
+                Token::new(TokenType::DotDot, "..".to_string()),
+                Token::new(TokenType::Integer, "10".to_string()),
+                Token::new(TokenType::Real, "1.5".to_string()),
+                Token::new(TokenType::DotDot, "..".to_string()),
+                Token::new(TokenType::Integer, "2".to_string()),
+                Token::new(TokenType::Integer, "1".to_string()),
+                Token::new(TokenType::DotDotEquals, "..=".to_string()),
+                Token::new(TokenType::Integer, "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_numbers() {
+        for code in ["1e", "2e+", "0x", "0b", "0b102", "12abc", "0xFG", "1.5x"] {
+            assert!(lex(code).is_err(), "{} should not lex", code);
+        }
+    }
+
+    #[test]
+    fn test_malformed_separators() {
+        for code in [
+            "1__000", "1_", "1_.5", "1.5_", "1_e3", "2e3_", "0x_", "0xF__F", "0b1_",
+        ] {
+            let code_chars = code.chars().collect::<Vec<char>>();
+            let error = Lexer::new(&code_chars).next().unwrap().unwrap_err();
+            assert!(
+                error.message.starts_with("Malformed number literal"),
+                "{} should not lex",
+                code
+            );
+            assert_eq!(
+                error.span.map(|span| (span.start, span.end)),
+                Some((0, code.len()))
+            );
+        }
+        assert!(lex("1_0 0x_f 1.0_1e1_0").is_ok());
+    }
+
+    #[test]
+    fn test_number_out_of_range() {
+        assert!(lex("9223372036854775807").is_ok());
+        assert!(lex("9223372036854775808").is_err());
+        assert!(lex("0xFFFFFFFFFFFFFFFFF").is_err());
+        assert!(lex("1e999").is_err());
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!(
+            lex(r#""a\tb\n\\ \"q\" \u{1F37A}""#).unwrap(),
+            vec![Token::new(
+                TokenType::String,
+                "a\tb\n\\ \"q\" \u{1F37A}".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        assert_eq!(
+            lex(r#""a{x}b{ {1} }c""#).unwrap(),
+            vec![
+                Token::new(TokenType::InterpolationStart, "a".to_string()),
+                Token::new(TokenType::Identifier, "x".to_string()),
+                Token::new(TokenType::InterpolationMiddle, "b".to_string()),
+                Token::new(TokenType::LeftCurlyBracket, "{".to_string()),
+                Token::new(TokenType::Integer, "1".to_string()),
+                Token::new(TokenType::RightCurlyBracket, "}".to_string()),
+                Token::new(TokenType::InterpolationEnd, "c".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_strings() {
+        for code in [r#""abc"#, r#""\q""#, r#""\u{110000}""#, r#""\u{12""#] {
+            assert!(lex(code).is_err(), "{} should not lex", code);
+        }
+    }
+
+    #[test]
+    fn test_spans() {
+        let code = "x = 1\n  \"ż\" + 2.5".chars().collect::<Vec<char>>();
+        let spans = Lexer::with_file(&code, FileId(3))
+            .map(|token| {
+                let token = token.unwrap();
+                (token.lexeme, token.span)
+            })
+            .collect::<Vec<_>>();
+
+        let span = |line, column, start, end| Span {
+            file: FileId(3),
+            line,
+            column,
+            start,
+            end,
+        };
+        assert_eq!(
+            spans,
+            vec![
+                ("x".to_string(), span(1, 1, 0, 1)),
+                ("=".to_string(), span(1, 3, 2, 3)),
+                ("1".to_string(), span(1, 5, 4, 5)),
+                ("\n".to_string(), span(1, 6, 5, 6)),
+                ("ż".to_string(), span(2, 3, 8, 12)),
+                ("+".to_string(), span(2, 7, 13, 14)),
+                ("2.5".to_string(), span(2, 9, 15, 18)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_span() {
+        let code = "x = 0b12".chars().collect::<Vec<char>>();
+        let error = Lexer::new(&code).find_map(|token| token.err()).unwrap();
+        assert_eq!(error.span.map(|span| (span.start, span.end)), Some((4, 8)));
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(
+            lex("x // comment\n/* block\n /* nested */ still block */ y //// not doc\n").unwrap(),
+            vec![
+                Token::new(TokenType::Identifier, "x".to_string()),
+                Token::new(TokenType::Newline, "\n".to_string()),
+                Token::new(TokenType::Newline, "\n".to_string()),
+                Token::new(TokenType::Identifier, "y".to_string()),
+                Token::new(TokenType::Newline, "\n".to_string()),
+            ]
+        );
+        assert!(lex("x /* /* */").is_err());
+    }
+
+    #[test]
+    fn test_block_comment_newlines() {
+        assert_eq!(
+            lex("let a = 1 /*\n*/ let b = 2 /* same line */ + 1").unwrap(),
+            vec![
+                Token::new(TokenType::Let, "let".to_string()),
+                Token::new(TokenType::Identifier, "a".to_string()),
+                Token::new(TokenType::Equals, "=".to_string()),
+                Token::new(TokenType::Integer, "1".to_string()),
+                Token::new(TokenType::Newline, "\n".to_string()),
+                Token::new(TokenType::Let, "let".to_string()),
+                Token::new(TokenType::Identifier, "b".to_string()),
+                Token::new(TokenType::Equals, "=".to_string()),
+                Token::new(TokenType::Integer, "2".to_string()),
+                Token::new(TokenType::Plus, "+".to_string()),
+                Token::new(TokenType::Integer, "1".to_string()),
+            ]
+        );
+
+        let code = "x\n  /* a\n /* b */\n".chars().collect::<Vec<char>>();
+        let error = Lexer::new(&code).find_map(|token| token.err()).unwrap();
+        assert_eq!(error.message, "Unterminated block comment");
+        let span = error.span.unwrap();
+        assert_eq!(
+            (span.line, span.column, span.start, span.end),
+            (2, 3, 4, 18)
+        );
+    }
+
+    #[test]
+    fn test_doc_comment() {
+        assert_eq!(
+            lex("/// Player speed\nspeed = 2 / 3").unwrap(),
+            vec![
+                Token::new(TokenType::DocComment, " Player speed".to_string()),
+                Token::new(TokenType::Newline, "\n".to_string()),
+                Token::new(TokenType::Identifier, "speed".to_string()),
+                Token::new(TokenType::Equals, "=".to_string()),
+                Token::new(TokenType::Integer, "2".to_string()),
+                Token::new(TokenType::Slash, "/".to_string()),
+                Token::new(TokenType::Integer, "3".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lines_after_block_comment() {
+        let code = "/* a\nb */ x".chars().collect::<Vec<char>>();
+        let mut lexer = Lexer::new(&code);
+        let newline = lexer.next().unwrap().unwrap();
+        assert_eq!(newline.token_type, TokenType::Newline);
+        let token = lexer.next().unwrap().unwrap();
+        assert_eq!((token.span.line, token.span.column), (2, 6));
+    }
+
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(
+            lex("make_counter _x atan2 fn return").unwrap(),
+            vec![
+                Token::new(TokenType::Identifier, "make_counter".to_string()),
+                Token::new(TokenType::Identifier, "_x".to_string()),
+                Token::new(TokenType::Identifier, "atan2".to_string()),
+                Token::new(TokenType::Fn, "fn".to_string()),
+                Token::new(TokenType::Return, "return".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(
+            lex("'outer: loop { break 'outer }").unwrap(),
+            vec![
+                Token::new(TokenType::Label, "'outer".to_string()),
+                Token::new(TokenType::Colon, ":".to_string()),
+                Token::new(TokenType::Loop, "loop".to_string()),
+                Token::new(TokenType::LeftCurlyBracket, "{".to_string()),
+                Token::new(TokenType::Break, "break".to_string()),
+                Token::new(TokenType::Label, "'outer".to_string()),
+                Token::new(TokenType::RightCurlyBracket, "}".to_string()),
+            ]
+        );
+        assert!(lex("' x").is_err());
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(
+            lex("% ** * & | ^ << <= < >> >= > += -= *= /= / => == = ->").unwrap(),
+            vec![
+                Token::new(TokenType::Percent, "%".to_string()),
+                Token::new(TokenType::StarStar, "**".to_string()),
+                Token::new(TokenType::Star, "*".to_string()),
+                Token::new(TokenType::Ampersand, "&".to_string()),
+                Token::new(TokenType::Pipe, "|".to_string()),
+                Token::new(TokenType::Caret, "^".to_string()),
+                Token::new(TokenType::LessLess, "<<".to_string()),
+                Token::new(TokenType::LessEquals, "<=".to_string()),
+                Token::new(TokenType::Less, "<".to_string()),
+                Token::new(TokenType::GreaterGreater, ">>".to_string()),
+                Token::new(TokenType::GreaterEquals, ">=".to_string()),
+                Token::new(TokenType::Greater, ">".to_string()),
+                Token::new(TokenType::PlusEquals, "+=".to_string()),
+                Token::new(TokenType::MinusEquals, "-=".to_string()),
+                Token::new(TokenType::StarEquals, "*=".to_string()),
+                Token::new(TokenType::SlashEquals, "/=".to_string()),
+                Token::new(TokenType::Slash, "/".to_string()),
+                Token::new(TokenType::FatArrow, "=>".to_string()),
+                Token::new(TokenType::EqualsEquals, "==".to_string()),
+                Token::new(TokenType::Equals, "=".to_string()),
+                Token::new(TokenType::Arrow, "->".to_string()),
+            ]
         );
     }
 }
@@ -1,4 +1,7 @@
-pub mod token;
 pub mod error;
 pub mod lexer;
+pub mod optimizer;
 pub mod parser;
+pub mod resolver;
+pub mod span;
+pub mod token;
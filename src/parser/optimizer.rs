@@ -0,0 +1,513 @@
+use std::{io::Write, rc::Rc};
+
+use super::{
+    error::Error,
+    parser::{Expression, FunctionDeclaration, MatchArm, Statement, Value},
+    span::Span,
+    token::TokenType,
+};
+use crate::interpreter::interpreter::{binary_operation, unary_operation};
+
+/// Simplifies the AST before it runs: folds operators on constants, drops branches of `if`s
+/// with a constant condition and removes empty blocks. Operations that fail, like an integer
+/// division by zero, are left alone so they still fail at runtime.
+pub struct Optimizer<'a> {
+    // Where the optimized AST gets printed, so it can be compared with the original
+    output: Option<&'a mut dyn Write>,
+}
+
+impl<'a> Optimizer<'a> {
+    pub fn new() -> Self {
+        Self { output: None }
+    }
+
+    pub fn with_printed_ast(mut self, output: &'a mut dyn Write) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    pub fn optimize(&mut self, statements: Vec<Statement>) -> Result<Vec<Statement>, Error> {
+        let statements = optimize_statements(statements);
+        if let Some(output) = &mut self.output {
+            for statement in &statements {
+                writeln!(output, "{:#?}", statement)
+                    .map_err(|err| Error::new(format!("Failed to print: {}", err)))?;
+            }
+        }
+        Ok(statements)
+    }
+}
+
+impl<'a> Default for Optimizer<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn optimize_statements(statements: Vec<Statement>) -> Vec<Statement> {
+    statements
+        .into_iter()
+        .filter_map(optimize_statement)
+        .collect()
+}
+
+/// Optimizes a statement, returning `None` when it doesn't do anything
+fn optimize_statement(statement: Statement) -> Option<Statement> {
+    let statement = match statement {
+        Statement::Expression { expr } => Statement::Expression {
+            expr: optimize_box(expr),
+        },
+        Statement::Print { expr } => Statement::Print {
+            expr: optimize_box(expr),
+        },
+        Statement::Let {
+            name,
+            mutable,
+            annotation,
+            initializer,
+        } => Statement::Let {
+            name,
+            mutable,
+            annotation,
+            initializer: optimize_box(initializer),
+        },
+        Statement::Destructure {
+            pattern,
+            mutable,
+            initializer,
+        } => Statement::Destructure {
+            pattern,
+            mutable,
+            initializer: optimize_box(initializer),
+        },
+        Statement::Const {
+            name,
+            annotation,
+            initializer,
+        } => Statement::Const {
+            name,
+            annotation,
+            initializer: optimize_box(initializer),
+        },
+        Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            let condition = optimize_box(condition);
+            let span = then_branch.span();
+            let then_branch = optimize_statement(*then_branch);
+            let else_branch = else_branch.and_then(|branch| optimize_statement(*branch));
+            match *condition {
+                Expression::Value {
+                    value: Value::Boolean(true),
+                    ..
+                } => return then_branch,
+                Expression::Value {
+                    value: Value::Boolean(false),
+                    ..
+                } => return else_branch,
+                _ => Statement::If {
+                    condition,
+                    then_branch: Box::new(then_branch.unwrap_or(empty_block(span))),
+                    else_branch: else_branch.map(Box::new),
+                },
+            }
+        }
+        Statement::Block { statements, span } => {
+            let statements = optimize_statements(statements);
+            if statements.is_empty() {
+                return None;
+            }
+            Statement::Block { statements, span }
+        }
+        Statement::For {
+            label,
+            variable,
+            range,
+            body,
+        } => Statement::For {
+            label,
+            variable,
+            range: optimize_box(range),
+            body: Box::new(optimize_branch(*body)),
+        },
+        Statement::While {
+            label,
+            condition,
+            body,
+        } => {
+            let condition = optimize_box(condition);
+            if let Expression::Value {
+                value: Value::Boolean(false),
+                ..
+            } = *condition
+            {
+                return None;
+            }
+            Statement::While {
+                label,
+                condition,
+                body: Box::new(optimize_branch(*body)),
+            }
+        }
+        Statement::Loop { label, body } => Statement::Loop {
+            label,
+            body: Box::new(optimize_branch(*body)),
+        },
+        Statement::Function { declaration } => Statement::Function {
+            declaration: optimize_function(declaration),
+        },
+        Statement::Return { keyword, value } => Statement::Return {
+            keyword,
+            value: value.map(optimize_box),
+        },
+        Statement::Match { value, arms, span } => Statement::Match {
+            value: optimize_box(value),
+            arms: arms
+                .into_iter()
+                .map(|arm| MatchArm {
+                    pattern: arm.pattern,
+                    guard: arm.guard.map(optimize_expression),
+                    body: optimize_branch(arm.body),
+                })
+                .collect(),
+            span,
+        },
+        Statement::Documented { doc, statement } => Statement::Documented {
+            doc,
+            statement: Box::new(optimize_statement(*statement)?),
+        },
+        statement @ (Statement::Break { .. }
+        | Statement::Continue { .. }
+        | Statement::Struct { .. }
+        | Statement::Enum { .. }) => statement,
+    };
+    Some(statement)
+}
+
+/// Optimizes a statement that has to stay, like the body of a loop
+fn optimize_branch(statement: Statement) -> Statement {
+    let span = statement.span();
+    optimize_statement(statement).unwrap_or(empty_block(span))
+}
+
+fn empty_block(span: Span) -> Statement {
+    Statement::Block {
+        statements: Vec::new(),
+        span,
+    }
+}
+
+fn optimize_function(declaration: Rc<FunctionDeclaration>) -> Rc<FunctionDeclaration> {
+    // The parser doesn't share declarations, so they can be taken apart
+    match Rc::try_unwrap(declaration) {
+        Ok(mut declaration) => {
+            declaration.body = optimize_statements(declaration.body);
+            Rc::new(declaration)
+        }
+        Err(declaration) => declaration,
+    }
+}
+
+// Reuses the allocation of the box
+fn optimize_box(mut expression: Box<Expression>) -> Box<Expression> {
+    let placeholder = Expression::Value {
+        value: Value::Nil,
+        span: expression.span(),
+    };
+    *expression = optimize_expression(std::mem::replace(&mut expression, placeholder));
+    expression
+}
+
+fn optimize_expressions(expressions: Vec<Expression>) -> Vec<Expression> {
+    expressions.into_iter().map(optimize_expression).collect()
+}
+
+fn constant(expression: &Expression) -> Option<&Value> {
+    match expression {
+        Expression::Value { value, .. } => Some(value),
+        _ => None,
+    }
+}
+
+fn optimize_expression(expression: Expression) -> Expression {
+    let span = expression.span();
+    let folded = |value| Expression::Value { value, span };
+    match expression {
+        Expression::Unary { operator, right } => {
+            let right = optimize_box(right);
+            match constant(&right).map(|value| unary_operation(&operator.token_type, value.clone()))
+            {
+                Some(Ok(value)) => folded(value),
+                _ => Expression::Unary { operator, right },
+            }
+        }
+        Expression::BinaryExpr {
+            operator,
+            left,
+            right,
+        } => {
+            let left = optimize_box(left);
+            let right = optimize_box(right);
+            let value = constant(&left).zip(constant(&right)).map(|(left, right)| {
+                binary_operation(&operator.token_type, left.clone(), right.clone())
+            });
+            match value {
+                Some(Ok(value)) => folded(value),
+                _ => Expression::BinaryExpr {
+                    operator,
+                    left,
+                    right,
+                },
+            }
+        }
+        Expression::LogicalExpr {
+            operator,
+            left,
+            right,
+        } => {
+            let left = optimize_box(left);
+            let right = optimize_box(right);
+            // Only booleans are folded, anything else is an error at runtime
+            match (operator.token_type, constant(&left), constant(&right)) {
+                (TokenType::And, Some(Value::Boolean(false)), _)
+                | (TokenType::Or, Some(Value::Boolean(true)), _) => *left,
+                (_, Some(Value::Boolean(_)), Some(Value::Boolean(_))) => *right,
+                _ => Expression::LogicalExpr {
+                    operator,
+                    left,
+                    right,
+                },
+            }
+        }
+        Expression::Grouping { expr } => {
+            let expr = optimize_box(expr);
+            match *expr {
+                Expression::Value { .. } => *expr,
+                _ => Expression::Grouping { expr },
+            }
+        }
+        Expression::Interpolation { parts, span } => Expression::Interpolation {
+            parts: optimize_expressions(parts),
+            span,
+        },
+        Expression::Assign { assignee, value } => Expression::Assign {
+            assignee: optimize_box(assignee),
+            value: optimize_box(value),
+        },
+        Expression::CompoundAssign {
+            operator,
+            assignee,
+            value,
+        } => Expression::CompoundAssign {
+            operator,
+            assignee: optimize_box(assignee),
+            value: optimize_box(value),
+        },
+        Expression::Member { object, name } => Expression::Member {
+            object: optimize_box(object),
+            name,
+        },
+        Expression::Construct { name, fields, span } => Expression::Construct {
+            name,
+            fields: fields
+                .into_iter()
+                .map(|(field, value)| (field, optimize_expression(value)))
+                .collect(),
+            span,
+        },
+        Expression::Call {
+            callee,
+            arguments,
+            span,
+        } => Expression::Call {
+            callee: optimize_box(callee),
+            arguments: optimize_expressions(arguments),
+            span,
+        },
+        Expression::List { elements, span } => Expression::List {
+            elements: optimize_expressions(elements),
+            span,
+        },
+        Expression::Tuple { elements, span } => Expression::Tuple {
+            elements: optimize_expressions(elements),
+            span,
+        },
+        Expression::Index {
+            object,
+            index,
+            span,
+        } => Expression::Index {
+            object: optimize_box(object),
+            index: optimize_box(index),
+            span,
+        },
+        Expression::Function { declaration } => Expression::Function {
+            declaration: optimize_function(declaration),
+        },
+        Expression::Match { value, arms, span } => Expression::Match {
+            value: optimize_box(value),
+            arms: arms
+                .into_iter()
+                .map(|arm| MatchArm {
+                    pattern: arm.pattern,
+                    guard: arm.guard.map(optimize_expression),
+                    body: optimize_expression(arm.body),
+                })
+                .collect(),
+            span,
+        },
+        expression @ (Expression::Value { .. } | Expression::Variable { .. }) => expression,
+    }
+}
+
+/// Optimizes the statements without printing them
+pub fn optimize(statements: Vec<Statement>) -> Vec<Statement> {
+    optimize_statements(statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        interpreter::interpreter::interpret_with_output,
+        parser::{lexer::Lexer, parser::parse},
+    };
+
+    fn parse_code(code: &str) -> Vec<Statement> {
+        let code = code.chars().collect::<Vec<char>>();
+        let tokens = Lexer::new(&code).collect::<Result<Vec<_>, _>>().unwrap();
+        parse(&tokens).unwrap()
+    }
+
+    fn run(statements: Vec<Statement>) -> Result<String, Error> {
+        let mut output = Vec::new();
+        interpret_with_output(statements, &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    fn initializer(statement: &Statement) -> &Expression {
+        match statement {
+            Statement::Let { initializer, .. } | Statement::Const { initializer, .. } => {
+                initializer
+            }
+            statement => panic!("Expected a declaration but got {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let code = "const SPEED = (2 + 3) * 1.5\nlet x = 10\nlet y = x * (60 / 4)\nlet z = -(-2) < 3 and !false\nprint SPEED\nprint y\nprint z\n";
+        let statements = optimize(parse_code(code));
+        assert_eq!(
+            constant(initializer(&statements[0])),
+            Some(&Value::Real(7.5))
+        );
+        match initializer(&statements[2]) {
+            Expression::BinaryExpr { right, .. } => {
+                assert_eq!(constant(right), Some(&Value::Integer(15)))
+            }
+            expression => panic!("Expected a binary expression but got {:?}", expression),
+        }
+        assert_eq!(
+            constant(initializer(&statements[3])),
+            Some(&Value::Boolean(true))
+        );
+        assert_eq!(run(statements).unwrap(), run(parse_code(code)).unwrap());
+    }
+
+    #[test]
+    fn test_folded_span() {
+        let statements = optimize(parse_code("print 1 + 2 * 3\n"));
+        let Statement::Print { expr } = &statements[0] else {
+            panic!("Expected print");
+        };
+        assert_eq!(constant(expr), Some(&Value::Integer(7)));
+        assert_eq!((expr.span().start, expr.span().end), (6, 15));
+    }
+
+    #[test]
+    fn test_runtime_errors_are_kept() {
+        let code = "print 1 + 1\nprint 10 / (5 - 5)\n";
+        let statements = optimize(parse_code(code));
+        let Statement::Print { expr } = &statements[1] else {
+            panic!("Expected print");
+        };
+        assert!(matches!(**expr, Expression::BinaryExpr { .. }));
+        let expected = run(parse_code(code)).unwrap_err();
+        let error = run(statements).unwrap_err();
+        assert_eq!(error, expected);
+
+        let statements = optimize(parse_code("print -\"a\"\nprint 1 and true\n"));
+        assert!(matches!(
+            statements[..],
+            [Statement::Print { .. }, Statement::Print { .. }]
+        ));
+        assert!(statements.iter().all(
+            |statement| !matches!(statement, Statement::Print { expr } if constant(expr).is_some())
+        ));
+    }
+
+    #[test]
+    fn test_overflow_is_not_folded() {
+        // Both branches are folded before the condition removes the dead one
+        let code = "if false {\n    print 9223372036854775807 + 1\n    print -(-9223372036854775807 - 1)\n}\nprint 1\n";
+        let statements = optimize(parse_code(code));
+        assert_eq!(run(statements).unwrap(), "1\n");
+
+        let code = "print 2\nprint 9223372036854775807 * 2\n";
+        let statements = optimize(parse_code(code));
+        let Statement::Print { expr } = &statements[1] else {
+            panic!("Expected print");
+        };
+        assert!(matches!(**expr, Expression::BinaryExpr { .. }));
+        let error = run(statements).unwrap_err();
+        assert_eq!(error.message, "Integer overflow");
+        assert_eq!(error, run(parse_code(code)).unwrap_err());
+    }
+
+    #[test]
+    fn test_dead_branches() {
+        let code = "let debug = false\nif true {\n    print 1\n} else {\n    print 2\n}\nif 1 > 2 {\n    print 3\n}\nif false {\n    print 4\n} else {\n    if debug {\n        print 5\n    } else {\n        print 6\n    }\n}\nwhile false {\n    print 7\n}\n";
+        let statements = optimize(parse_code(code));
+        assert_eq!(statements.len(), 3);
+        assert!(matches!(statements[1], Statement::Block { .. }));
+        assert!(matches!(
+            &statements[2],
+            Statement::Block { statements, .. } if matches!(statements[..], [Statement::If { .. }])
+        ));
+        assert_eq!(run(statements).unwrap(), "1\n6\n");
+    }
+
+    #[test]
+    fn test_empty_blocks() {
+        let code = "{\n    {\n    }\n    if false {\n        print 1\n    }\n}\nfn f() {\n    {\n    }\n    return 1\n}\nlet mut i = 0\nwhile i < 3 {\n    i += 1\n    if false {\n    }\n}\nprint f() + i\n";
+        let statements = optimize(parse_code(code));
+        assert_eq!(statements.len(), 4);
+        let Statement::Function { declaration } = &statements[0] else {
+            panic!("Expected a function");
+        };
+        assert_eq!(declaration.body.len(), 1);
+        let Statement::While { body, .. } = &statements[2] else {
+            panic!("Expected a while loop");
+        };
+        assert!(matches!(&**body, Statement::Block { statements, .. } if statements.len() == 1));
+        assert_eq!(run(statements).unwrap(), "4\n");
+    }
+
+    #[test]
+    fn test_printed_ast() {
+        let mut output = Vec::new();
+        let statements = Optimizer::new()
+            .with_printed_ast(&mut output)
+            .optimize(parse_code("print 2 * 21\n"))
+            .unwrap();
+        assert_eq!(statements.len(), 1);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Print {"));
+        assert!(output.contains("Integer(\n"));
+        assert!(output.contains("42"));
+        assert!(!output.contains("BinaryExpr"));
+    }
+}
@@ -1,32 +1,233 @@
-use std::fmt::Display;
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+
+use glam::{DVec2, DVec3};
+
+use crate::interpreter::{
+    interpreter::{Closure, EnumType, Instance, NativeFunction, StructType, Variant},
+    range::Range,
+    vm::CompiledFunction,
+};
 
 use super::{
     error::Error,
+    lexer::{parse_integer, parse_real},
+    span::Span,
     token::{Token, TokenType},
 };
 
-#[derive(Debug, Clone, PartialEq, Copy)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Integer(i64),
     Real(f64),
     Boolean(bool),
-    Range(i64, i64),
+    Range(Range),
+    Vec2(DVec2),
+    Vec3(DVec3),
+    String(String),
+    Function(Rc<Closure>),
+    NativeFunction(Rc<NativeFunction>),
+    // Closures created by the bytecode VM
+    CompiledFunction(Rc<CompiledFunction>),
+    Struct(Rc<StructType>),
+    // Instances are shared like lists
+    Instance(Rc<RefCell<Instance>>),
+    Enum(Rc<EnumType>),
+    // Variants are immutable, so they don't need to be shared like instances
+    Variant(Rc<Variant>),
+    // Lists are shared, every copy of the value refers to the same elements
+    List(Rc<RefCell<Vec<Value>>>),
+    Tuple(Rc<[Value]>),
+    Nil,
+}
+
+impl Value {
+    pub fn list(elements: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
 }
 
+// Lists and instances can contain themselves, so displaying and comparing them keeps track of
+// the ones it is already inside of to stop at a cycle.
+type Visiting = Vec<*const ()>;
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, &mut Visiting::new())
+    }
+}
+
+impl Value {
+    fn write(&self, f: &mut std::fmt::Formatter<'_>, visiting: &mut Visiting) -> std::fmt::Result {
         match self {
             Value::Integer(int) => write!(f, "{}", int),
             Value::Real(real) => write!(f, "{}", real),
             Value::Boolean(bool) => write!(f, "{}", bool),
-            Value::Range(start, end) => write!(f, "{}..{}", start, end),
+            Value::Range(range) => write!(f, "{}", range),
+            Value::Vec2(vector) => write!(f, "vec2({}, {})", vector.x, vector.y),
+            Value::Vec3(vector) => write!(f, "vec3({}, {}, {})", vector.x, vector.y, vector.z),
+            Value::String(string) => write!(f, "{}", string),
+            Value::Function(closure) => match &closure.declaration.name {
+                Some(name) => write!(f, "<fn {}>", name.lexeme),
+                None => write!(f, "<fn>"),
+            },
+            Value::NativeFunction(function) => write!(f, "<native fn {}>", function.name),
+            Value::CompiledFunction(closure) => match &closure.function.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
+            },
+            Value::Struct(struct_type) => write!(f, "<struct {}>", struct_type.name),
+            Value::Instance(instance) => {
+                let pointer = Rc::as_ptr(instance) as *const ();
+                let instance = instance.borrow();
+                if visiting.contains(&pointer) {
+                    return write!(f, "{} {{ ... }}", instance.struct_type.name);
+                }
+                visiting.push(pointer);
+                write!(f, "{} {{", instance.struct_type.name)?;
+                for (i, (field, value)) in instance
+                    .struct_type
+                    .fields
+                    .iter()
+                    .zip(&instance.fields)
+                    .enumerate()
+                {
+                    let separator = if i > 0 { "," } else { "" };
+                    write!(f, "{} {}: ", separator, field)?;
+                    value.write(f, visiting)?;
+                }
+                visiting.pop();
+                write!(f, " }}")
+            }
+            Value::Enum(enum_type) => write!(f, "<enum {}>", enum_type.name),
+            Value::Variant(variant) => {
+                let enum_type = &variant.enum_type;
+                write!(
+                    f,
+                    "{}.{}",
+                    enum_type.name, enum_type.variants[variant.index].name
+                )?;
+                if variant.payload.is_empty() {
+                    return Ok(());
+                }
+                write!(f, "(")?;
+                for (i, value) in variant.payload.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.write(f, visiting)?;
+                }
+                write!(f, ")")
+            }
+            Value::List(list) => {
+                let pointer = Rc::as_ptr(list) as *const ();
+                if visiting.contains(&pointer) {
+                    return write!(f, "[...]");
+                }
+                visiting.push(pointer);
+                write!(f, "[")?;
+                for (i, element) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.write(f, visiting)?;
+                }
+                visiting.pop();
+                write!(f, "]")
+            }
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.write(f, visiting)?;
+                }
+                // A tuple of one element needs a comma to not look like a grouping
+                if elements.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Value::Nil => write!(f, "nil"),
+        }
+    }
+
+    // A pair of lists or instances that is already being compared counts as equal, so two
+    // cycles of the same shape are equal
+    fn equals(&self, other: &Value, comparing: &mut Vec<(*const (), *const ())>) -> bool {
+        match (self, other) {
+            (Value::Integer(left), Value::Integer(right)) => left == right,
+            (Value::Real(left), Value::Real(right)) => left == right,
+            (Value::Boolean(left), Value::Boolean(right)) => left == right,
+            (Value::Range(left), Value::Range(right)) => left == right,
+            (Value::Vec2(left), Value::Vec2(right)) => left == right,
+            (Value::Vec3(left), Value::Vec3(right)) => left == right,
+            (Value::String(left), Value::String(right)) => left == right,
+            (Value::Function(left), Value::Function(right)) => left == right,
+            (Value::NativeFunction(left), Value::NativeFunction(right)) => left == right,
+            (Value::CompiledFunction(left), Value::CompiledFunction(right)) => left == right,
+            (Value::Struct(left), Value::Struct(right)) => left == right,
+            (Value::Instance(left), Value::Instance(right)) => {
+                let pair = (
+                    Rc::as_ptr(left) as *const (),
+                    Rc::as_ptr(right) as *const (),
+                );
+                if pair.0 == pair.1 || comparing.contains(&pair) {
+                    return true;
+                }
+                comparing.push(pair);
+                let (left, right) = (left.borrow(), right.borrow());
+                let equal = left.struct_type == right.struct_type
+                    && all_equal(&left.fields, &right.fields, comparing);
+                comparing.pop();
+                equal
+            }
+            (Value::Enum(left), Value::Enum(right)) => left == right,
+            (Value::Variant(left), Value::Variant(right)) => {
+                left.enum_type == right.enum_type
+                    && left.index == right.index
+                    && all_equal(&left.payload, &right.payload, comparing)
+            }
+            (Value::List(left), Value::List(right)) => {
+                let pair = (
+                    Rc::as_ptr(left) as *const (),
+                    Rc::as_ptr(right) as *const (),
+                );
+                if pair.0 == pair.1 || comparing.contains(&pair) {
+                    return true;
+                }
+                comparing.push(pair);
+                let equal = all_equal(&left.borrow(), &right.borrow(), comparing);
+                comparing.pop();
+                equal
+            }
+            (Value::Tuple(left), Value::Tuple(right)) => all_equal(left, right, comparing),
+            (Value::Nil, Value::Nil) => true,
+            _ => false,
         }
     }
 }
 
+fn all_equal(left: &[Value], right: &[Value], comparing: &mut Vec<(*const (), *const ())>) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .all(|(left, right)| left.equals(right, comparing))
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.equals(other, &mut Vec::new())
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
-    Value(Value),
+    Value {
+        value: Value,
+        span: Span,
+    },
     Unary {
         operator: Token,
         right: Box<Expression>,
@@ -44,6 +245,10 @@ pub enum Expression {
     Grouping {
         expr: Box<Expression>,
     },
+    Interpolation {
+        parts: Vec<Expression>,
+        span: Span,
+    },
     // Variable {
     //     name: Token,
     // },
@@ -51,13 +256,259 @@ pub enum Expression {
         assignee: Box<Expression>,
         value: Box<Expression>,
     },
-    // TODO: There might be a better way to do this
+    CompoundAssign {
+        operator: Token,
+        assignee: Box<Expression>,
+        value: Box<Expression>,
+    },
     Variable {
         name: Token,
-        member: Option<Box<Expression>>,
+    },
+    Member {
+        object: Box<Expression>,
+        name: Token,
+    },
+    Construct {
+        name: Token,
+        fields: Vec<(Token, Expression)>,
+        span: Span,
+    },
+    Call {
+        callee: Box<Expression>,
+        arguments: Vec<Expression>,
+        span: Span,
+    },
+    List {
+        elements: Vec<Expression>,
+        span: Span,
+    },
+    Tuple {
+        elements: Vec<Expression>,
+        span: Span,
+    },
+    Index {
+        object: Box<Expression>,
+        index: Box<Expression>,
+        span: Span,
+    },
+    Function {
+        declaration: Rc<FunctionDeclaration>,
+    },
+    Match {
+        value: Box<Expression>,
+        arms: Vec<MatchArm<Expression>>,
+        span: Span,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FunctionDeclaration {
+    pub name: Option<Token>,
+    pub params: Vec<Parameter>,
+    pub return_type: Option<TypeAnnotation>,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Parameter {
+    pub name: Token,
+    pub annotation: Option<TypeAnnotation>,
+}
+
+/// A type written after a `:` or `->`, like `int`, `Player` or `(real, real)`
+#[derive(Debug, PartialEq, Clone)]
+pub enum TypeAnnotation {
+    Named {
+        name: Token,
+    },
+    Tuple {
+        elements: Vec<TypeAnnotation>,
+        span: Span,
+    },
+}
+
+impl TypeAnnotation {
+    pub fn span(&self) -> Span {
+        match self {
+            TypeAnnotation::Named { name } => name.span,
+            TypeAnnotation::Tuple { span, .. } => *span,
+        }
+    }
+
+    /// Whether values stored under this type can change, because it expects a real somewhere
+    pub fn converts(&self) -> bool {
+        match self {
+            TypeAnnotation::Named { name } => name.lexeme == "real",
+            TypeAnnotation::Tuple { elements, .. } => elements.iter().any(Self::converts),
+        }
+    }
+
+    /// Turns ints into reals where this type expects a real, other values are kept as they are
+    pub fn convert(&self, value: Value) -> Value {
+        match (self, value) {
+            (TypeAnnotation::Named { name }, Value::Integer(int)) if name.lexeme == "real" => {
+                Value::Real(int as f64)
+            }
+            (TypeAnnotation::Tuple { elements, .. }, Value::Tuple(values))
+                if elements.len() == values.len() && self.converts() =>
+            {
+                Value::Tuple(
+                    elements
+                        .iter()
+                        .zip(values.iter())
+                        .map(|(element, value)| element.convert(value.clone()))
+                        .collect(),
+                )
+            }
+            (_, value) => value,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct VariantDeclaration {
+    pub name: Token,
+    pub fields: Vec<Token>,
+}
+
+/// One `pattern if guard => body` arm, where the body is an expression or a statement
+/// depending on whether the match is used as a value
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchArm<B> {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: B,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    Wildcard {
+        span: Span,
+    },
+    Binding {
+        name: Token,
+    },
+    Literal {
+        value: Value,
+        span: Span,
+    },
+    Range {
+        range: Range,
+        span: Span,
+    },
+    Or {
+        alternatives: Vec<Pattern>,
+    },
+    Tuple {
+        elements: Vec<Pattern>,
+        span: Span,
+    },
+    Variant {
+        enum_name: Token,
+        variant: Token,
+        fields: Vec<Pattern>,
+        span: Span,
     },
 }
 
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Wildcard { span } => *span,
+            Pattern::Binding { name } => name.span,
+            Pattern::Literal { span, .. } => *span,
+            Pattern::Range { span, .. } => *span,
+            Pattern::Variant { span, .. } => *span,
+            Pattern::Tuple { span, .. } => *span,
+            Pattern::Or { alternatives } => alternatives[0]
+                .span()
+                .to(alternatives[alternatives.len() - 1].span()),
+        }
+    }
+
+    /// Whether the pattern matches any value of the shape it expects. Tuple patterns assume
+    /// that the value is a tuple of the right size.
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Wildcard { .. } | Pattern::Binding { .. } => true,
+            Pattern::Tuple { elements, .. } => elements.iter().all(Pattern::is_irrefutable),
+            _ => false,
+        }
+    }
+
+    pub fn has_bindings(&self) -> bool {
+        match self {
+            Pattern::Binding { .. } => true,
+            Pattern::Or { alternatives } => alternatives.iter().any(Pattern::has_bindings),
+            Pattern::Variant { fields, .. } => fields.iter().any(Pattern::has_bindings),
+            Pattern::Tuple { elements, .. } => elements.iter().any(Pattern::has_bindings),
+            _ => false,
+        }
+    }
+
+    fn enum_name(&self) -> Option<&str> {
+        match self {
+            Pattern::Variant { enum_name, .. } => Some(&enum_name.lexeme),
+            Pattern::Or { alternatives } => alternatives.iter().find_map(Pattern::enum_name),
+            _ => None,
+        }
+    }
+
+    /// Whether every value matching `other` also matches this pattern, as far as that is known
+    /// without running the program
+    fn covers(&self, other: &Pattern) -> bool {
+        match (self, other) {
+            (pattern, _) if pattern.is_irrefutable() => true,
+            (_, Pattern::Or { alternatives }) => alternatives
+                .iter()
+                .all(|alternative| self.covers(alternative)),
+            (Pattern::Or { alternatives }, _) => alternatives
+                .iter()
+                .any(|alternative| alternative.covers(other)),
+            (Pattern::Literal { value, .. }, Pattern::Literal { value: other, .. }) => {
+                value == other
+            }
+            (Pattern::Range { range, .. }, Pattern::Literal { value, .. }) => range.contains(value),
+            (
+                Pattern::Variant {
+                    enum_name,
+                    variant,
+                    fields,
+                    ..
+                },
+                Pattern::Variant {
+                    enum_name: other_enum_name,
+                    variant: other_variant,
+                    fields: other_fields,
+                    ..
+                },
+            ) => {
+                enum_name.lexeme == other_enum_name.lexeme
+                    && variant.lexeme == other_variant.lexeme
+                    && fields.len() == other_fields.len()
+                    && fields
+                        .iter()
+                        .zip(other_fields)
+                        .all(|(field, other)| field.covers(other))
+            }
+            (
+                Pattern::Tuple { elements, .. },
+                Pattern::Tuple {
+                    elements: others, ..
+                },
+            ) => {
+                elements.len() == others.len()
+                    && elements
+                        .iter()
+                        .zip(others)
+                        .all(|(element, other)| element.covers(other))
+            }
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Statement {
     Expression {
@@ -66,6 +517,23 @@ pub enum Statement {
     Print {
         expr: Box<Expression>,
     },
+    Let {
+        name: Token,
+        mutable: bool,
+        annotation: Option<TypeAnnotation>,
+        initializer: Box<Expression>,
+    },
+    // `let (x, y) = position`
+    Destructure {
+        pattern: Pattern,
+        mutable: bool,
+        initializer: Box<Expression>,
+    },
+    Const {
+        name: Token,
+        annotation: Option<TypeAnnotation>,
+        initializer: Box<Expression>,
+    },
     If {
         condition: Box<Expression>,
         then_branch: Box<Statement>,
@@ -73,22 +541,175 @@ pub enum Statement {
     },
     Block {
         statements: Vec<Statement>,
+        span: Span,
     },
     For {
+        label: Option<Token>,
         variable: Token,
         range: Box<Expression>,
         body: Box<Statement>,
     },
+    While {
+        label: Option<Token>,
+        condition: Box<Expression>,
+        body: Box<Statement>,
+    },
+    Loop {
+        label: Option<Token>,
+        body: Box<Statement>,
+    },
+    Break {
+        keyword: Token,
+        label: Option<Token>,
+    },
+    Continue {
+        keyword: Token,
+        label: Option<Token>,
+    },
+    Function {
+        declaration: Rc<FunctionDeclaration>,
+    },
+    Struct {
+        name: Token,
+        fields: Vec<Token>,
+        span: Span,
+    },
+    Enum {
+        name: Token,
+        variants: Vec<VariantDeclaration>,
+        span: Span,
+    },
+    Return {
+        keyword: Token,
+        value: Option<Box<Expression>>,
+    },
+    Match {
+        value: Box<Expression>,
+        arms: Vec<MatchArm<Statement>>,
+        span: Span,
+    },
+    Documented {
+        doc: String,
+        statement: Box<Statement>,
+    },
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Value { span, .. } => *span,
+            Expression::Unary { operator, right } => operator.span.to(right.span()),
+            Expression::BinaryExpr { left, right, .. } => left.span().to(right.span()),
+            Expression::LogicalExpr { left, right, .. } => left.span().to(right.span()),
+            Expression::Grouping { expr } => expr.span(),
+            Expression::Interpolation { span, .. } => *span,
+            Expression::Assign { assignee, value } => assignee.span().to(value.span()),
+            Expression::CompoundAssign {
+                assignee, value, ..
+            } => assignee.span().to(value.span()),
+            Expression::Variable { name } => name.span,
+            Expression::Member { object, name } => object.span().to(name.span),
+            Expression::Construct { span, .. } => *span,
+            Expression::Call { span, .. } => *span,
+            Expression::List { span, .. } => *span,
+            Expression::Tuple { span, .. } => *span,
+            Expression::Index { span, .. } => *span,
+            Expression::Function { declaration } => declaration.span,
+            Expression::Match { span, .. } => *span,
+        }
+    }
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Expression { expr } => expr.span(),
+            Statement::Print { expr } => expr.span(),
+            Statement::Let {
+                name, initializer, ..
+            } => name.span.to(initializer.span()),
+            Statement::Destructure {
+                pattern,
+                initializer,
+                ..
+            } => pattern.span().to(initializer.span()),
+            Statement::Const {
+                name, initializer, ..
+            } => name.span.to(initializer.span()),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => match else_branch {
+                Some(else_branch) => condition.span().to(else_branch.span()),
+                None => condition.span().to(then_branch.span()),
+            },
+            Statement::Block { span, .. } => *span,
+            Statement::For { variable, body, .. } => variable.span.to(body.span()),
+            Statement::While {
+                condition, body, ..
+            } => condition.span().to(body.span()),
+            Statement::Loop { body, .. } => body.span(),
+            Statement::Break { keyword, label } | Statement::Continue { keyword, label } => {
+                match label {
+                    Some(label) => keyword.span.to(label.span),
+                    None => keyword.span,
+                }
+            }
+            Statement::Function { declaration } => declaration.span,
+            Statement::Struct { span, .. } => *span,
+            Statement::Enum { span, .. } => *span,
+            Statement::Return { keyword, value } => match value {
+                Some(value) => keyword.span.to(value.span()),
+                None => keyword.span,
+            },
+            Statement::Match { span, .. } => *span,
+            Statement::Documented { statement, .. } => statement.span(),
+        }
+    }
 }
 
+const ASSIGNMENT_OPERATORS: &[TokenType] = &[
+    TokenType::Equals,
+    TokenType::PlusEquals,
+    TokenType::MinusEquals,
+    TokenType::StarEquals,
+    TokenType::SlashEquals,
+];
+
 #[derive(Debug, PartialEq)]
 struct Parser<'a> {
     tokens: &'a [Token],
+    // Span of the last chopped token, used for errors at the end of input
+    last_span: Span,
+    errors: Vec<Error>,
+    warnings: Vec<Error>,
+    // Number of function bodies we are currently in
+    function_depth: usize,
+    // Number of blocks we are currently in, including function bodies
+    block_depth: usize,
+    // Labels of the loops we are currently in, inside of the current function
+    loops: Vec<Option<String>>,
+    // Whether `name {` starts a struct literal, which it does not right before a block
+    struct_literals: bool,
+    // Variants of the enums declared so far with the number of their fields,
+    // to tell whether a match covers all of them
+    enums: HashMap<String, Vec<(String, usize)>>,
 }
 
 impl<'a> Parser<'a> {
     fn new(tokens: &'a [Token]) -> Self {
-        Self { tokens }
+        Self {
+            tokens,
+            last_span: Span::default(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            function_depth: 0,
+            block_depth: 0,
+            loops: Vec::new(),
+            struct_literals: true,
+            enums: HashMap::new(),
+        }
     }
 
     fn match_next(&self, token_types: &[TokenType]) -> bool {
@@ -111,16 +732,29 @@ impl<'a> Parser<'a> {
         }
         let token = &self.tokens[0];
         self.tokens = &self.tokens[1..];
+        self.last_span = token.span;
         // TODO: Remove the clone
         Some(token.clone())
     }
 
+    // Doesn't consume a mismatched token, so synchronization can start from it
     fn expect(&mut self, expected_type: TokenType, error_message: String) -> Result<Token, Error> {
-        let next = self.chop().ok_or(Error::new(error_message.clone()))?;
-        if next.token_type != expected_type {
-            return Err(Error::new(error_message));
+        if !self.match_next(&[expected_type]) {
+            return Err(Error::new(error_message).with_span(self.current_span()));
+        }
+        Ok(self.chop().unwrap())
+    }
+
+    // Statements end with a newline, except for the last one in a block or in the file
+    fn expect_terminator(&mut self, error_message: String) -> Result<(), Error> {
+        if self.tokens.is_empty() || self.match_next(&[TokenType::RightCurlyBracket]) {
+            return Ok(());
         }
-        Ok(next)
+        self.expect(TokenType::Newline, error_message).map(|_| ())
+    }
+
+    fn current_span(&self) -> Span {
+        self.peek(0).map_or(self.last_span, |token| token.span)
     }
 
     fn peek(&self, offset: usize) -> Option<&Token> {
@@ -130,15 +764,62 @@ impl<'a> Parser<'a> {
         Some(&self.tokens[offset])
     }
 
-    fn parse(&mut self) -> Result<Vec<Statement>, Error> {
+    fn parse(&mut self) -> Vec<Statement> {
         let mut statements = Vec::new();
 
+        self.consume_whitespace();
         while !self.tokens.is_empty() {
-            statements.push(self.declaration()?);
+            if self.match_next(&[TokenType::RightCurlyBracket]) {
+                let bracket = self.chop().unwrap();
+                self.errors.push(
+                    Error::new("Unexpected closing curly bracket".to_string())
+                        .with_span(bracket.span),
+                );
+            } else {
+                match self.declaration() {
+                    Ok(statement) => statements.push(statement),
+                    Err(err) => {
+                        self.errors.push(err);
+                        self.synchronize();
+                    }
+                }
+            }
             self.consume_whitespace();
         }
 
-        Ok(statements)
+        statements
+    }
+
+    // Skips tokens until the end of the current statement, so parsing can continue after an error.
+    // Blocks opened by the skipped tokens are skipped as a whole.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.peek(0) {
+            match token.token_type {
+                TokenType::Newline if depth == 0 => {
+                    self.chop();
+                    return;
+                }
+                TokenType::RightCurlyBracket if depth == 0 => return,
+                TokenType::RightCurlyBracket => depth -= 1,
+                TokenType::LeftCurlyBracket => depth += 1,
+                _ => {}
+            }
+            self.chop();
+        }
+    }
+
+    // Skips past the end of a block whose opening bracket was already consumed
+    fn skip_block(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.chop() {
+            match token.token_type {
+                TokenType::LeftCurlyBracket => depth += 1,
+                TokenType::RightCurlyBracket if depth == 0 => return,
+                TokenType::RightCurlyBracket => depth -= 1,
+                _ => {}
+            }
+        }
     }
 
     fn consume_whitespace(&mut self) {
@@ -147,105 +828,642 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn declaration(&mut self) -> Result<Statement, Error> {
-        self.statement()
+    /// Skips newlines only if one of `token_types` follows them. Used inside of brackets,
+    /// so that a missing closing bracket is reported at the end of the line.
+    fn consume_whitespace_before(&mut self, token_types: &[TokenType]) {
+        let mut ahead = 0;
+        while self
+            .peek(ahead)
+            .is_some_and(|token| token.token_type == TokenType::Newline)
+        {
+            ahead += 1;
+        }
+        if self
+            .peek(ahead)
+            .is_some_and(|token| token_types.contains(&token.token_type))
+        {
+            self.consume_whitespace();
+        }
     }
 
-    fn statement(&mut self) -> Result<Statement, Error> {
-        let next_type = &self
-            .peek(0)
-            .ok_or(Error::new("Expected a statement".to_string()))?
-            .token_type;
+    fn with_struct_literals<T>(&mut self, allowed: bool, parse: impl FnOnce(&mut Self) -> T) -> T {
+        let previous = std::mem::replace(&mut self.struct_literals, allowed);
+        let result = parse(self);
+        self.struct_literals = previous;
+        result
+    }
 
-        match next_type {
-            TokenType::Print => self.print_statement(),
-            TokenType::If => self.if_statement(),
-            TokenType::LeftCurlyBracket => self.block_statement(),
-            TokenType::For => self.for_statement(),
-            _ => self.expression_statement(),
+    /// Parses the expression in front of a block, like the condition of an if
+    fn condition(&mut self) -> Result<Expression, Error> {
+        self.with_struct_literals(false, Self::expression)
+    }
+
+    fn declaration(&mut self) -> Result<Statement, Error> {
+        if self.match_next(&[TokenType::DocComment]) {
+            return self.documented_declaration();
+        }
+        if self.match_next(&[TokenType::Let]) {
+            return self.let_declaration();
+        }
+        if self.match_next(&[TokenType::Const]) {
+            return self.const_declaration();
+        }
+        if self.match_next(&[TokenType::Struct]) {
+            return self.struct_declaration();
         }
+        if self.match_next(&[TokenType::Enum]) {
+            return self.enum_declaration();
+        }
+        let is_named_function = self
+            .peek(1)
+            .is_some_and(|token| token.token_type == TokenType::Identifier);
+        if self.match_next(&[TokenType::Fn]) && is_named_function {
+            return self.function_declaration();
+        }
+        self.statement()
     }
 
-    fn print_statement(&mut self) -> Result<Statement, Error> {
-        let _print = self.chop().unwrap();
-        let expr = self.expression()?;
+    fn struct_declaration(&mut self) -> Result<Statement, Error> {
+        let keyword = self.chop().unwrap();
+        let name = self.expect(TokenType::Identifier, "Expected a struct name".to_string())?;
         self.expect(
-            TokenType::Newline,
-            "Expected a newline after print statement".to_string(),
+            TokenType::LeftCurlyBracket,
+            "Expected '{' after struct name".to_string(),
         )?;
-        Ok(Statement::Print {
-            expr: Box::new(expr),
-        })
-    }
-
-    fn if_statement(&mut self) -> Result<Statement, Error> {
-        let _if = self.chop().unwrap();
-        let expr = self.expression()?;
-        let block = self.block_statement()?;
 
-        let next = self.peek(0);
+        let mut fields: Vec<Token> = Vec::new();
+        self.consume_whitespace();
+        while !self.match_next(&[TokenType::RightCurlyBracket]) {
+            let field = self.expect(TokenType::Identifier, "Expected a field name".to_string())?;
+            if fields.iter().any(|other| other.lexeme == field.lexeme) {
+                // Reported without leaving the declaration, since the rest still parses fine
+                self.errors.push(
+                    Error::new(format!("Duplicate field {}", field.lexeme)).with_span(field.span),
+                );
+            } else {
+                fields.push(field);
+            }
 
-        let mut else_branch = None;
-        if next.is_some() && next.unwrap().token_type == TokenType::Else {
-            let _else = self.chop().unwrap();
-            let else_block = self.block_statement()?;
-            else_branch = Some(Box::new(else_block));
+            self.consume_whitespace_before(&[TokenType::Comma, TokenType::RightCurlyBracket]);
+            if !self.match_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.chop();
+            self.consume_whitespace();
         }
 
-        Ok(Statement::If {
-            condition: Box::new(expr),
-            then_branch: Box::new(block),
-            else_branch,
+        let right_curly_bracket = self.expect(
+            TokenType::RightCurlyBracket,
+            "Expected '}' after struct fields".to_string(),
+        )?;
+        self.expect_terminator("Expected a newline after struct declaration".to_string())?;
+
+        Ok(Statement::Struct {
+            name,
+            fields,
+            span: keyword.span.to(right_curly_bracket.span),
         })
     }
 
-    fn block_statement(&mut self) -> Result<Statement, Error> {
-        let _left_curly_bracket = self.expect(
+    fn enum_declaration(&mut self) -> Result<Statement, Error> {
+        let keyword = self.chop().unwrap();
+        let name = self.expect(TokenType::Identifier, "Expected an enum name".to_string())?;
+        self.expect(
             TokenType::LeftCurlyBracket,
-            "Expected a curly bracket.".to_string(),
-        );
+            "Expected '{' after enum name".to_string(),
+        )?;
 
+        let mut variants: Vec<VariantDeclaration> = Vec::new();
         self.consume_whitespace();
-
-        let mut statements = Vec::new();
-
         while !self.match_next(&[TokenType::RightCurlyBracket]) {
-            statements.push(self.declaration()?);
+            let variant =
+                self.expect(TokenType::Identifier, "Expected a variant name".to_string())?;
+            let fields = match self.match_next(&[TokenType::LeftParen]) {
+                true => self.variant_fields()?,
+                false => Vec::new(),
+            };
+            if variants
+                .iter()
+                .any(|other| other.name.lexeme == variant.lexeme)
+            {
+                self.errors.push(
+                    Error::new(format!("Duplicate variant {}", variant.lexeme))
+                        .with_span(variant.span),
+                );
+            } else {
+                variants.push(VariantDeclaration {
+                    name: variant,
+                    fields,
+                });
+            }
+
+            self.consume_whitespace_before(&[TokenType::Comma, TokenType::RightCurlyBracket]);
+            if !self.match_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.chop();
+            self.consume_whitespace();
         }
 
-        let _right_curly_bracket = self.expect(
+        let right_curly_bracket = self.expect(
             TokenType::RightCurlyBracket,
-            "Expected a closing curly bracket.".to_string(),
+            "Expected '}' after enum variants".to_string(),
+        )?;
+        self.expect_terminator("Expected a newline after enum declaration".to_string())?;
+
+        self.enums.insert(
+            name.lexeme.clone(),
+            variants
+                .iter()
+                .map(|variant| (variant.name.lexeme.clone(), variant.fields.len()))
+                .collect(),
         );
 
-        Ok(Statement::Block { statements })
+        Ok(Statement::Enum {
+            name,
+            variants,
+            span: keyword.span.to(right_curly_bracket.span),
+        })
     }
 
-    fn for_statement(&mut self) -> Result<Statement, Error> {
-        let _for = self.chop().unwrap();
-        let variable = self
-            .chop()
-            .ok_or(Error::new("Expected a variable name".to_string()))?;
-        let _in = self.expect(
-            TokenType::In,
-            "Expected 'in' after variable name".to_string(),
-        )?;
-        let range = self.expression()?;
-        let body = self.block_statement()?;
+    fn variant_fields(&mut self) -> Result<Vec<Token>, Error> {
+        let _left_paren = self.chop().unwrap();
+
+        let mut fields: Vec<Token> = Vec::new();
+        while !self.match_next(&[TokenType::RightParen]) {
+            let field = self.expect(TokenType::Identifier, "Expected a field name".to_string())?;
+            if fields.iter().any(|other| other.lexeme == field.lexeme) {
+                self.errors.push(
+                    Error::new(format!("Duplicate field {}", field.lexeme)).with_span(field.span),
+                );
+            } else {
+                fields.push(field);
+            }
 
-        Ok(Statement::For {
+            if !self.match_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.chop();
+        }
+
+        self.expect(
+            TokenType::RightParen,
+            "Expected ')' after variant fields".to_string(),
+        )?;
+        Ok(fields)
+    }
+
+    fn function_declaration(&mut self) -> Result<Statement, Error> {
+        let keyword = self.chop().unwrap();
+        let name = self.chop().unwrap();
+        let declaration = self.function(keyword, Some(name))?;
+        Ok(Statement::Function {
+            declaration: Rc::new(declaration),
+        })
+    }
+
+    fn function(
+        &mut self,
+        keyword: Token,
+        name: Option<Token>,
+    ) -> Result<FunctionDeclaration, Error> {
+        self.expect(
+            TokenType::LeftParen,
+            "Expected '(' before function parameters".to_string(),
+        )?;
+
+        let mut params: Vec<Parameter> = Vec::new();
+        while !self.match_next(&[TokenType::RightParen]) {
+            let name = self.expect(
+                TokenType::Identifier,
+                "Expected a parameter name".to_string(),
+            )?;
+            if params.iter().any(|other| other.name.lexeme == name.lexeme) {
+                return Err(
+                    Error::new(format!("Duplicate parameter {}", name.lexeme)).with_span(name.span)
+                );
+            }
+            let annotation = self.optional_annotation()?;
+            params.push(Parameter { name, annotation });
+
+            if !self.match_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.chop();
+        }
+
+        self.expect(
+            TokenType::RightParen,
+            "Expected ')' after function parameters".to_string(),
+        )?;
+        let return_type = match self.match_next(&[TokenType::Arrow]) {
+            true => {
+                self.chop();
+                Some(self.type_annotation()?)
+            }
+            false => None,
+        };
+
+        self.function_depth += 1;
+        let loops = std::mem::take(&mut self.loops);
+        let body = self.with_struct_literals(true, Self::block_statement);
+        self.loops = loops;
+        self.function_depth -= 1;
+
+        let Statement::Block { statements, span } = body? else {
+            unreachable!("block_statement always returns a block");
+        };
+
+        Ok(FunctionDeclaration {
+            name,
+            params,
+            return_type,
+            body: statements,
+            span: keyword.span.to(span),
+        })
+    }
+
+    fn optional_annotation(&mut self) -> Result<Option<TypeAnnotation>, Error> {
+        if !self.match_next(&[TokenType::Colon]) {
+            return Ok(None);
+        }
+        self.chop();
+        self.type_annotation().map(Some)
+    }
+
+    fn type_annotation(&mut self) -> Result<TypeAnnotation, Error> {
+        if self.match_next(&[TokenType::Fn]) {
+            let name = self.chop().unwrap();
+            return Ok(TypeAnnotation::Named { name });
+        }
+        if !self.match_next(&[TokenType::LeftParen]) {
+            let name = self.expect(TokenType::Identifier, "Expected a type".to_string())?;
+            return Ok(TypeAnnotation::Named { name });
+        }
+
+        let left_paren = self.chop().unwrap();
+        let mut elements = Vec::new();
+        while !self.match_next(&[TokenType::RightParen]) {
+            elements.push(self.type_annotation()?);
+            if !self.match_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.chop();
+        }
+        let right_paren = self.expect(
+            TokenType::RightParen,
+            "Expected ')' after tuple type".to_string(),
+        )?;
+        Ok(TypeAnnotation::Tuple {
+            elements,
+            span: left_paren.span.to(right_paren.span),
+        })
+    }
+
+    fn let_declaration(&mut self) -> Result<Statement, Error> {
+        let _let = self.chop().unwrap();
+        let mutable = self.match_next(&[TokenType::Mut]);
+        if mutable {
+            self.chop();
+        }
+        if self.match_next(&[TokenType::LeftParen]) {
+            return self.destructure_declaration(mutable);
+        }
+        let name = self.expect(
+            TokenType::Identifier,
+            "Expected a variable name after 'let'".to_string(),
+        )?;
+        let annotation = self.optional_annotation()?;
+        self.expect(
+            TokenType::Equals,
+            "Expected '=' after variable name".to_string(),
+        )?;
+        let initializer = self.expression_list()?;
+        self.expect_terminator("Expected a newline after let declaration".to_string())?;
+
+        Ok(Statement::Let {
+            name,
+            mutable,
+            annotation,
+            initializer: Box::new(initializer),
+        })
+    }
+
+    fn destructure_declaration(&mut self, mutable: bool) -> Result<Statement, Error> {
+        let pattern = self.pattern()?;
+        if !pattern.is_irrefutable() {
+            return Err(Error::new(
+                "Only variables, '_' and tuples of them can be declared with let".to_string(),
+            )
+            .with_span(pattern.span()));
+        }
+        self.expect(TokenType::Equals, "Expected '=' after pattern".to_string())?;
+        let initializer = self.expression_list()?;
+        self.expect_terminator("Expected a newline after let declaration".to_string())?;
+
+        Ok(Statement::Destructure {
+            pattern,
+            mutable,
+            initializer: Box::new(initializer),
+        })
+    }
+
+    fn const_declaration(&mut self) -> Result<Statement, Error> {
+        let keyword = self.chop().unwrap();
+        if self.block_depth > 0 {
+            return Err(
+                Error::new("Constants can only be declared at the top level".to_string())
+                    .with_span(keyword.span),
+            );
+        }
+        let name = self.expect(
+            TokenType::Identifier,
+            "Expected a constant name after 'const'".to_string(),
+        )?;
+        let annotation = self.optional_annotation()?;
+        self.expect(
+            TokenType::Equals,
+            "Expected '=' after constant name".to_string(),
+        )?;
+        let initializer = self.expression_list()?;
+        self.expect_terminator("Expected a newline after const declaration".to_string())?;
+
+        Ok(Statement::Const {
+            name,
+            annotation,
+            initializer: Box::new(initializer),
+        })
+    }
+
+    fn documented_declaration(&mut self) -> Result<Statement, Error> {
+        let mut lines = Vec::new();
+        let mut span = self.current_span();
+
+        while self.match_next(&[TokenType::DocComment]) {
+            let comment = self.chop().unwrap();
+            span = span.to(comment.span);
+            let line = comment.lexeme.strip_prefix(' ').unwrap_or(&comment.lexeme);
+            lines.push(line.to_string());
+            self.consume_whitespace();
+        }
+
+        if self.tokens.is_empty() || self.match_next(&[TokenType::RightCurlyBracket]) {
+            return Err(
+                Error::new("Expected a statement after doc comment".to_string()).with_span(span),
+            );
+        }
+
+        Ok(Statement::Documented {
+            doc: lines.join("\n"),
+            statement: Box::new(self.declaration()?),
+        })
+    }
+
+    fn statement(&mut self) -> Result<Statement, Error> {
+        let next_type = &self
+            .peek(0)
+            .ok_or_else(|| {
+                Error::new("Expected a statement".to_string()).with_span(self.last_span)
+            })?
+            .token_type;
+
+        match next_type {
+            TokenType::Print => self.print_statement(),
+            TokenType::If => self.if_statement(),
+            TokenType::LeftCurlyBracket => self.block_statement(),
+            TokenType::For => self.for_statement(None),
+            TokenType::While => self.while_statement(None),
+            TokenType::Loop => self.loop_statement(None),
+            TokenType::Label => self.labeled_statement(),
+            TokenType::Break | TokenType::Continue => self.break_statement(),
+            TokenType::Return => self.return_statement(),
+            TokenType::Match => self.match_statement(),
+            _ => self.expression_statement(),
+        }
+    }
+
+    fn print_statement(&mut self) -> Result<Statement, Error> {
+        let _print = self.chop().unwrap();
+        let expr = self.expression()?;
+        self.expect_terminator("Expected a newline after print statement".to_string())?;
+        Ok(Statement::Print {
+            expr: Box::new(expr),
+        })
+    }
+
+    fn if_statement(&mut self) -> Result<Statement, Error> {
+        let _if = self.chop().unwrap();
+        let expr = self.condition()?;
+        let block = self.block_statement()?;
+
+        let next = self.peek(0);
+
+        let mut else_branch = None;
+        if next.is_some() && next.unwrap().token_type == TokenType::Else {
+            let _else = self.chop().unwrap();
+            let else_block = self.block_statement()?;
+            else_branch = Some(Box::new(else_block));
+        }
+
+        Ok(Statement::If {
+            condition: Box::new(expr),
+            then_branch: Box::new(block),
+            else_branch,
+        })
+    }
+
+    fn block_statement(&mut self) -> Result<Statement, Error> {
+        let left_curly_bracket = self.expect(
+            TokenType::LeftCurlyBracket,
+            "Expected a curly bracket.".to_string(),
+        )?;
+
+        self.consume_whitespace();
+
+        let mut statements = Vec::new();
+
+        self.block_depth += 1;
+        while !self.tokens.is_empty() && !self.match_next(&[TokenType::RightCurlyBracket]) {
+            match self.declaration() {
+                Ok(statement) => statements.push(statement),
+                Err(err) => {
+                    self.errors.push(err);
+                    self.synchronize();
+                }
+            }
+            self.consume_whitespace();
+        }
+        self.block_depth -= 1;
+
+        let right_curly_bracket = self.expect(
+            TokenType::RightCurlyBracket,
+            "Expected a closing curly bracket.".to_string(),
+        )?;
+
+        Ok(Statement::Block {
+            statements,
+            span: left_curly_bracket.span.to(right_curly_bracket.span),
+        })
+    }
+
+    fn labeled_statement(&mut self) -> Result<Statement, Error> {
+        let label = self.chop().unwrap();
+        self.expect(
+            TokenType::Colon,
+            "Expected ':' after loop label".to_string(),
+        )?;
+
+        match self.peek(0).map(|token| &token.token_type) {
+            Some(TokenType::For) => self.for_statement(Some(label)),
+            Some(TokenType::While) => self.while_statement(Some(label)),
+            Some(TokenType::Loop) => self.loop_statement(Some(label)),
+            _ => Err(Error::new("Expected a loop after label".to_string())
+                .with_span(self.current_span())),
+        }
+    }
+
+    fn loop_body(&mut self, label: &Option<Token>) -> Result<Statement, Error> {
+        self.loops
+            .push(label.as_ref().map(|label| label.lexeme.clone()));
+        let body = self.block_statement();
+        self.loops.pop();
+        body
+    }
+
+    fn while_statement(&mut self, label: Option<Token>) -> Result<Statement, Error> {
+        let _while = self.chop().unwrap();
+        let condition = self.condition()?;
+        let body = self.loop_body(&label)?;
+
+        Ok(Statement::While {
+            label,
+            condition: Box::new(condition),
+            body: Box::new(body),
+        })
+    }
+
+    fn loop_statement(&mut self, label: Option<Token>) -> Result<Statement, Error> {
+        let _loop = self.chop().unwrap();
+        let body = self.loop_body(&label)?;
+
+        Ok(Statement::Loop {
+            label,
+            body: Box::new(body),
+        })
+    }
+
+    fn break_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.chop().unwrap();
+        let label = match self.match_next(&[TokenType::Label]) {
+            true => self.chop(),
+            false => None,
+        };
+
+        if self.loops.is_empty() {
+            return Err(
+                Error::new(format!("Can't {} outside of a loop", keyword.lexeme))
+                    .with_span(keyword.span),
+            );
+        }
+        if let Some(label) = &label {
+            if !self.loops.contains(&Some(label.lexeme.clone())) {
+                return Err(Error::new(format!("Unknown loop label {}", label.lexeme))
+                    .with_span(label.span));
+            }
+        }
+        self.expect_terminator(format!("Expected a newline after {}", keyword.lexeme))?;
+
+        match keyword.token_type {
+            TokenType::Break => Ok(Statement::Break { keyword, label }),
+            _ => Ok(Statement::Continue { keyword, label }),
+        }
+    }
+
+    fn for_statement(&mut self, label: Option<Token>) -> Result<Statement, Error> {
+        let _for = self.chop().unwrap();
+        let variable = self.expect(
+            TokenType::Identifier,
+            "Expected a variable name".to_string(),
+        )?;
+        let _in = self.expect(
+            TokenType::In,
+            "Expected 'in' after variable name".to_string(),
+        )?;
+        let range = self.condition()?;
+        let body = self.loop_body(&label)?;
+
+        Ok(Statement::For {
+            label,
             variable,
             range: Box::new(range),
             body: Box::new(body),
         })
     }
 
+    fn return_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.chop().unwrap();
+        if self.function_depth == 0 {
+            return Err(Error::new("Can't return outside of a function".to_string())
+                .with_span(keyword.span));
+        }
+
+        let mut value = None;
+        if !self.tokens.is_empty()
+            && !self.match_next(&[TokenType::Newline, TokenType::RightCurlyBracket])
+        {
+            value = Some(Box::new(self.expression_list()?));
+        }
+        self.expect_terminator("Expected a newline after return statement".to_string())?;
+
+        Ok(Statement::Return { keyword, value })
+    }
+
+    fn match_statement(&mut self) -> Result<Statement, Error> {
+        let keyword = self.chop().unwrap();
+        let value = self.condition()?;
+        let (arms, span) = self.match_arms(&keyword, |parser| {
+            if !parser.match_next(&[TokenType::LeftCurlyBracket]) {
+                // Other statements already end their line
+                return parser.statement();
+            }
+            let body = parser.block_statement()?;
+            parser.match_arm_end()?;
+            Ok(body)
+        })?;
+
+        Ok(Statement::Match {
+            value: Box::new(value),
+            arms,
+            span,
+        })
+    }
+
     fn expression_statement(&mut self) -> Result<Statement, Error> {
-        let expr = self.expression()?;
-        self.expect(
-            TokenType::Newline,
-            "Expected a newline after expression statement".to_string(),
-        )?;
+        let mut expr = self.expression()?;
+
+        // `a, b = b, a` assigns to several targets at once
+        if self.match_next(&[TokenType::Comma]) {
+            let mut targets = vec![expr];
+            while self.match_next(&[TokenType::Comma]) {
+                self.chop();
+                targets.push(self.logic_or_expression()?);
+            }
+            if !self.match_next(ASSIGNMENT_OPERATORS) {
+                return Err(
+                    Error::new("Expected '=' after assignment targets".to_string())
+                        .with_span(self.current_span()),
+                );
+            }
+            let operator = self.chop().unwrap();
+            let value = self.expression_list()?;
+            let assignee = Expression::Tuple {
+                span: targets[0].span().to(targets[targets.len() - 1].span()),
+                elements: targets,
+            };
+            expr = self.assignment(assignee, operator, value)?;
+        }
+
+        self.expect_terminator("Expected a newline after expression statement".to_string())?;
         Ok(Statement::Expression {
             expr: Box::new(expr),
         })
@@ -255,26 +1473,76 @@ impl<'a> Parser<'a> {
         self.assignment_expression()
     }
 
+    /// Parses `a, b, c` into a tuple, or a single expression if there is no comma
+    fn expression_list(&mut self) -> Result<Expression, Error> {
+        let expr = self.expression()?;
+        if !self.match_next(&[TokenType::Comma]) {
+            return Ok(expr);
+        }
+
+        let mut elements = vec![expr];
+        while self.match_next(&[TokenType::Comma]) {
+            self.chop();
+            elements.push(self.expression()?);
+        }
+        Ok(Expression::Tuple {
+            span: elements[0].span().to(elements[elements.len() - 1].span()),
+            elements,
+        })
+    }
+
     fn assignment_expression(&mut self) -> Result<Expression, Error> {
         let expr = self.logic_or_expression()?;
 
-        if self.match_next(&[TokenType::Equals]) {
-            let _equals = self.chop().unwrap();
+        if self.match_next(ASSIGNMENT_OPERATORS) {
+            let operator = self.chop().unwrap();
             let value = self.assignment_expression()?;
+            return self.assignment(expr, operator, value);
+        }
 
-            if let Expression::Variable { name, member } = expr {
-                return Ok(Expression::Assign {
-                    assignee: Box::new(Expression::Variable { name, member }),
-                    value: Box::new(value),
-                });
-            }
+        Ok(expr)
+    }
 
-            return Err(Error::new(
-                "Can't assign that expression to a variable".to_string(),
-            ));
+    fn assignment(
+        &mut self,
+        assignee: Expression,
+        operator: Token,
+        value: Expression,
+    ) -> Result<Expression, Error> {
+        fn is_assignable(expr: &Expression) -> bool {
+            match expr {
+                Expression::Variable { .. }
+                | Expression::Member { .. }
+                | Expression::Index { .. } => true,
+                Expression::Tuple { elements, .. } => elements.iter().all(is_assignable),
+                _ => false,
+            }
         }
 
-        Ok(expr)
+        if !is_assignable(&assignee) {
+            return Err(
+                Error::new("Can't assign that expression to a variable".to_string())
+                    .with_span(assignee.span()),
+            );
+        }
+        if operator.token_type == TokenType::Equals {
+            return Ok(Expression::Assign {
+                assignee: Box::new(assignee),
+                value: Box::new(value),
+            });
+        }
+        if let Expression::Tuple { .. } = assignee {
+            return Err(Error::new(format!(
+                "Can't use {} with multiple targets",
+                operator.lexeme
+            ))
+            .with_span(operator.span));
+        }
+        Ok(Expression::CompoundAssign {
+            operator,
+            assignee: Box::new(assignee),
+            value: Box::new(value),
+        })
     }
 
     fn logic_or_expression(&mut self) -> Result<Expression, Error> {
@@ -284,7 +1552,7 @@ impl<'a> Parser<'a> {
             let operator = self.chop().unwrap();
             let right = self.logic_and_expression()?;
 
-            expr = Expression::BinaryExpr {
+            expr = Expression::LogicalExpr {
                 operator,
                 left: Box::new(expr),
                 right: Box::new(right),
@@ -301,7 +1569,7 @@ impl<'a> Parser<'a> {
             let operator = self.chop().unwrap();
             let right = self.equality_expression()?;
 
-            expr = Expression::BinaryExpr {
+            expr = Expression::LogicalExpr {
                 operator,
                 left: Box::new(expr),
                 right: Box::new(right),
@@ -329,7 +1597,7 @@ impl<'a> Parser<'a> {
     }
 
     fn comparison_expression(&mut self) -> Result<Expression, Error> {
-        let mut expr = self.term_expression()?;
+        let mut expr = self.range_expression()?;
 
         while self.match_next(&[
             TokenType::Greater,
@@ -337,6 +1605,74 @@ impl<'a> Parser<'a> {
             TokenType::Less,
             TokenType::LessEquals,
         ]) {
+            let operator = self.chop().unwrap();
+            let right = self.range_expression()?;
+
+            expr = Expression::BinaryExpr {
+                operator,
+                left: Box::new(expr),
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_or_expression(&mut self) -> Result<Expression, Error> {
+        let mut expr = self.bit_xor_expression()?;
+
+        while self.match_next(&[TokenType::Pipe]) {
+            let operator = self.chop().unwrap();
+            let right = self.bit_xor_expression()?;
+
+            expr = Expression::BinaryExpr {
+                operator,
+                left: Box::new(expr),
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_xor_expression(&mut self) -> Result<Expression, Error> {
+        let mut expr = self.bit_and_expression()?;
+
+        while self.match_next(&[TokenType::Caret]) {
+            let operator = self.chop().unwrap();
+            let right = self.bit_and_expression()?;
+
+            expr = Expression::BinaryExpr {
+                operator,
+                left: Box::new(expr),
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn bit_and_expression(&mut self) -> Result<Expression, Error> {
+        let mut expr = self.shift_expression()?;
+
+        while self.match_next(&[TokenType::Ampersand]) {
+            let operator = self.chop().unwrap();
+            let right = self.shift_expression()?;
+
+            expr = Expression::BinaryExpr {
+                operator,
+                left: Box::new(expr),
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn shift_expression(&mut self) -> Result<Expression, Error> {
+        let mut expr = self.term_expression()?;
+
+        while self.match_next(&[TokenType::LessLess, TokenType::GreaterGreater]) {
             let operator = self.chop().unwrap();
             let right = self.term_expression()?;
 
@@ -368,9 +1704,9 @@ impl<'a> Parser<'a> {
     }
 
     fn factor_expression(&mut self) -> Result<Expression, Error> {
-        let mut expr = self.range_expression()?;
+        let mut expr = self.unary_expression()?;
 
-        while self.match_next(&[TokenType::Star, TokenType::Slash]) {
+        while self.match_next(&[TokenType::Star, TokenType::Slash, TokenType::Percent]) {
             let operator = self.chop().unwrap();
             let right = self.unary_expression()?;
 
@@ -385,17 +1721,32 @@ impl<'a> Parser<'a> {
     }
 
     fn range_expression(&mut self) -> Result<Expression, Error> {
-        let mut expr = self.unary_expression()?;
+        let mut expr = self.bit_or_expression()?;
 
-        if self.match_next(&[TokenType::DotDot]) {
-            let _dot_dot = self.chop().unwrap();
-            let right = self.unary_expression()?;
+        if self.match_next(&[TokenType::DotDot, TokenType::DotDotEquals]) {
+            let operator = self.chop().unwrap();
+            let right = self.bit_or_expression()?;
 
             expr = Expression::BinaryExpr {
-                operator: Token::new(TokenType::DotDot, "..".to_string()),
+                operator,
                 left: Box::new(expr),
                 right: Box::new(right),
             };
+
+            let is_step = self.peek(0).is_some_and(|token| {
+                token.token_type == TokenType::Identifier && token.lexeme == "step"
+            });
+            if is_step {
+                let step = self.chop().unwrap();
+                let operator = Token::new(TokenType::Step, step.lexeme).with_span(step.span);
+                let right = self.bit_or_expression()?;
+
+                expr = Expression::BinaryExpr {
+                    operator,
+                    left: Box::new(expr),
+                    right: Box::new(right),
+                };
+            }
         }
 
         Ok(expr)
@@ -412,66 +1763,636 @@ impl<'a> Parser<'a> {
             });
         }
 
-        self.primary_expression()
+        self.power_expression()
     }
 
-    fn primary_expression(&mut self) -> Result<Expression, Error> {
-        let next = self
-            .chop()
-            .ok_or(Error::new("Expected an expression".to_string()))?;
-
-        match next.token_type {
-            TokenType::Integer => Ok(Expression::Value(Value::Integer(
-                next.lexeme.parse::<i64>().unwrap(),
-            ))),
-            TokenType::Real => Ok(Expression::Value(Value::Real(
-                next.lexeme.parse::<f64>().unwrap(),
-            ))),
-            TokenType::True => Ok(Expression::Value(Value::Boolean(true))),
-            TokenType::False => Ok(Expression::Value(Value::Boolean(false))),
-            TokenType::LeftParen => {
-                let expr = self.expression()?;
-
-                self.expect(
-                    TokenType::RightParen,
-                    "Expected a closing parenthesis".to_string(),
+    fn power_expression(&mut self) -> Result<Expression, Error> {
+        let expr = self.call_expression()?;
+
+        // Right associative and binds tighter than a unary minus on its left, so -2 ** 2 is -4
+        if self.match_next(&[TokenType::StarStar]) {
+            let operator = self.chop().unwrap();
+            let right = self.unary_expression()?;
+
+            return Ok(Expression::BinaryExpr {
+                operator,
+                left: Box::new(expr),
+                right: Box::new(right),
+            });
+        }
+
+        Ok(expr)
+    }
+
+    fn call_expression(&mut self) -> Result<Expression, Error> {
+        let mut expr = self.primary_expression()?;
+
+        while self.match_next(&[
+            TokenType::LeftParen,
+            TokenType::LeftSquareBracket,
+            TokenType::Dot,
+        ]) {
+            if self.match_next(&[TokenType::Dot]) {
+                let _dot = self.chop().unwrap();
+                let name =
+                    self.expect(TokenType::Identifier, "Expected a member name".to_string())?;
+
+                expr = Expression::Member {
+                    object: Box::new(expr),
+                    name,
+                };
+                continue;
+            }
+            if self.match_next(&[TokenType::LeftSquareBracket]) {
+                let _left_bracket = self.chop().unwrap();
+                let index = self.with_struct_literals(true, Self::expression)?;
+                let right_bracket = self.expect(
+                    TokenType::RightSquareBracket,
+                    "Expected ']' after index".to_string(),
                 )?;
-                Ok(Expression::Grouping {
-                    expr: Box::new(expr),
-                })
+
+                expr = Expression::Index {
+                    span: expr.span().to(right_bracket.span),
+                    object: Box::new(expr),
+                    index: Box::new(index),
+                };
+                continue;
             }
-            TokenType::Identifier => self.variable(next),
+
+            let _left_paren = self.chop().unwrap();
+
+            let mut arguments = Vec::new();
+            while !self.match_next(&[TokenType::RightParen]) {
+                arguments.push(self.with_struct_literals(true, Self::expression)?);
+                if !self.match_next(&[TokenType::Comma]) {
+                    break;
+                }
+                self.chop();
+            }
+
+            let right_paren = self.expect(
+                TokenType::RightParen,
+                "Expected ')' after call arguments".to_string(),
+            )?;
+
+            expr = Expression::Call {
+                span: expr.span().to(right_paren.span),
+                callee: Box::new(expr),
+                arguments,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn primary_expression(&mut self) -> Result<Expression, Error> {
+        if !self.match_next(&[
+            TokenType::Integer,
+            TokenType::Real,
+            TokenType::String,
+            TokenType::InterpolationStart,
+            TokenType::True,
+            TokenType::False,
+            TokenType::LeftParen,
+            TokenType::LeftSquareBracket,
+            TokenType::Identifier,
+            TokenType::Fn,
+            TokenType::Match,
+        ]) {
+            return Err(
+                Error::new("Expected an expression".to_string()).with_span(self.current_span())
+            );
+        }
+        let next = self.chop().unwrap();
+        let span = next.span;
+
+        let value = match next.token_type {
+            TokenType::Integer => {
+                parse_integer(&next.lexeme)
+                    .map(Value::Integer)
+                    .ok_or(Error::new(format!(
+                        "Invalid integer literal '{}'",
+                        next.lexeme
+                    )))
+            }
+            TokenType::Real => {
+                parse_real(&next.lexeme)
+                    .map(Value::Real)
+                    .ok_or(Error::new(format!(
+                        "Invalid real literal '{}'",
+                        next.lexeme
+                    )))
+            }
+            TokenType::String => Ok(Value::String(next.lexeme)),
+            TokenType::True => Ok(Value::Boolean(true)),
+            TokenType::False => Ok(Value::Boolean(false)),
+            TokenType::InterpolationStart => return self.interpolation(next),
+            TokenType::LeftParen => return self.grouping(next),
+            TokenType::LeftSquareBracket => return self.list(next),
+            TokenType::Identifier
+                if self.struct_literals && self.match_next(&[TokenType::LeftCurlyBracket]) =>
+            {
+                return self.construct(next)
+            }
+            TokenType::Identifier => return Ok(Expression::Variable { name: next }),
+            TokenType::Fn => {
+                let declaration = self.function(next, None)?;
+                return Ok(Expression::Function {
+                    declaration: Rc::new(declaration),
+                });
+            }
+            TokenType::Match => return self.match_expression(next),
             _ => Err(Error::new("Expected an expression".to_string())),
+        };
+
+        value
+            .map(|value| Expression::Value { value, span })
+            .map_err(|err| err.with_span(span))
+    }
+
+    fn grouping(&mut self, left_paren: Token) -> Result<Expression, Error> {
+        let expr = self.with_struct_literals(true, Self::expression)?;
+        if !self.match_next(&[TokenType::Comma]) {
+            self.expect(
+                TokenType::RightParen,
+                "Expected a closing parenthesis".to_string(),
+            )?;
+            return Ok(Expression::Grouping {
+                expr: Box::new(expr),
+            });
+        }
+
+        // A trailing comma makes a tuple of one element, like `(a,)`
+        let mut elements = vec![expr];
+        while self.match_next(&[TokenType::Comma]) {
+            self.chop();
+            if self.match_next(&[TokenType::RightParen]) {
+                break;
+            }
+            elements.push(self.with_struct_literals(true, Self::expression)?);
+        }
+        let right_paren = self.expect(
+            TokenType::RightParen,
+            "Expected ')' after tuple elements".to_string(),
+        )?;
+
+        Ok(Expression::Tuple {
+            elements,
+            span: left_paren.span.to(right_paren.span),
+        })
+    }
+
+    fn list(&mut self, left_bracket: Token) -> Result<Expression, Error> {
+        let mut elements = Vec::new();
+
+        // Lists may span multiple lines and end with a trailing comma
+        self.consume_whitespace();
+        while !self.match_next(&[TokenType::RightSquareBracket]) {
+            elements.push(self.with_struct_literals(true, Self::expression)?);
+            self.consume_whitespace_before(&[TokenType::Comma, TokenType::RightSquareBracket]);
+            if !self.match_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.chop();
+            self.consume_whitespace();
+        }
+
+        let right_bracket = self.expect(
+            TokenType::RightSquareBracket,
+            "Expected ']' after list elements".to_string(),
+        )?;
+
+        Ok(Expression::List {
+            elements,
+            span: left_bracket.span.to(right_bracket.span),
+        })
+    }
+
+    fn match_expression(&mut self, keyword: Token) -> Result<Expression, Error> {
+        let value = self.condition()?;
+        let (arms, span) = self.match_arms(&keyword, |parser| {
+            let body = parser.with_struct_literals(true, Self::expression)?;
+            parser.match_arm_end()?;
+            Ok(body)
+        })?;
+
+        Ok(Expression::Match {
+            value: Box::new(value),
+            arms,
+            span,
+        })
+    }
+
+    fn match_arms<B>(
+        &mut self,
+        keyword: &Token,
+        body: impl Fn(&mut Self) -> Result<B, Error>,
+    ) -> Result<(Vec<MatchArm<B>>, Span), Error> {
+        self.expect(
+            TokenType::LeftCurlyBracket,
+            "Expected '{' after match value".to_string(),
+        )?;
+
+        let mut arms = Vec::new();
+        self.consume_whitespace();
+        while !self.match_next(&[TokenType::RightCurlyBracket]) {
+            match self.match_arm(&body) {
+                Ok(arm) => arms.push(arm),
+                Err(err) => {
+                    // Skips the rest of the match, so its closing bracket isn't reported as well
+                    self.errors.push(err);
+                    self.skip_block();
+                    return Ok((arms, keyword.span.to(self.last_span)));
+                }
+            }
+            self.consume_whitespace();
         }
+
+        let right_curly_bracket = self.expect(
+            TokenType::RightCurlyBracket,
+            "Expected '}' after match arms".to_string(),
+        )?;
+        self.check_match_arms(&arms, keyword.span);
+
+        Ok((arms, keyword.span.to(right_curly_bracket.span)))
     }
 
-    fn variable(&mut self, name: Token) -> Result<Expression, Error> {
-        if self.match_next(&[TokenType::Dot]) {
-            let _dot = self.chop().unwrap();
-            let next_name = self
-                .chop()
-                .ok_or(Error::new("Expected a member name".to_string()))?;
-            let member = self.variable(next_name)?;
+    fn match_arm<B>(
+        &mut self,
+        body: &impl Fn(&mut Self) -> Result<B, Error>,
+    ) -> Result<MatchArm<B>, Error> {
+        let pattern = self.pattern()?;
+        let guard = match self.match_next(&[TokenType::If]) {
+            true => {
+                self.chop();
+                Some(self.with_struct_literals(true, Self::expression)?)
+            }
+            false => None,
+        };
+        self.expect(
+            TokenType::FatArrow,
+            "Expected '=>' after match pattern".to_string(),
+        )?;
+
+        Ok(MatchArm {
+            pattern,
+            guard,
+            body: body(self)?,
+        })
+    }
+
+    // Arms are separated by commas or newlines
+    fn match_arm_end(&mut self) -> Result<(), Error> {
+        if self.match_next(&[TokenType::Comma]) {
+            self.chop();
+            return Ok(());
+        }
+        if self.tokens.is_empty()
+            || self.match_next(&[TokenType::Newline, TokenType::RightCurlyBracket])
+        {
+            return Ok(());
+        }
+        Err(
+            Error::new("Expected ',' or a newline after match arm".to_string())
+                .with_span(self.current_span()),
+        )
+    }
+
+    fn check_match_arms<B>(&mut self, arms: &[MatchArm<B>], keyword: Span) {
+        // Guarded arms may not match, so only unguarded ones make later arms unreachable
+        let unguarded = |arms: &[MatchArm<B>], pattern: &Pattern| {
+            arms.iter()
+                .any(|arm| arm.guard.is_none() && arm.pattern.covers(pattern))
+        };
+
+        for (i, arm) in arms.iter().enumerate() {
+            if unguarded(&arms[..i], &arm.pattern) {
+                self.warnings.push(
+                    Error::new("Unreachable match arm".to_string()).with_span(arm.pattern.span()),
+                );
+            }
+        }
+
+        // The type of the value isn't known, but matching both true and false suggests a boolean
+        let covers_boolean = |value| {
+            unguarded(
+                arms,
+                &Pattern::Literal {
+                    value: Value::Boolean(value),
+                    span: keyword,
+                },
+            )
+        };
+        let wildcard = Pattern::Wildcard { span: keyword };
+        if unguarded(arms, &wildcard) || (covers_boolean(true) && covers_boolean(false)) {
+            return;
+        }
+
+        // Matching every variant of an enum declared earlier is exhaustive too
+        let variants = arms
+            .iter()
+            .find_map(|arm| arm.pattern.enum_name())
+            .and_then(|name| Some((name, self.enums.get(name)?)));
+        let Some((enum_name, variants)) = variants else {
+            self.warnings.push(
+                Error::new("Match is not exhaustive, add a '_' arm for other values".to_string())
+                    .with_span(keyword),
+            );
+            return;
+        };
+
+        let missing = variants
+            .iter()
+            .filter(|(variant, fields)| {
+                let pattern = Pattern::Variant {
+                    enum_name: Token::new(TokenType::Identifier, enum_name.to_string()),
+                    variant: Token::new(TokenType::Identifier, variant.clone()),
+                    fields: vec![wildcard.clone(); *fields],
+                    span: keyword,
+                };
+                !unguarded(arms, &pattern)
+            })
+            .map(|(variant, _)| format!("{}.{}", enum_name, variant))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            self.warnings.push(
+                Error::new(format!(
+                    "Match is not exhaustive, missing {}",
+                    missing.join(", ")
+                ))
+                .with_span(keyword),
+            );
+        }
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, Error> {
+        let pattern = self.single_pattern()?;
+        if !self.match_next(&[TokenType::Pipe]) {
+            return Ok(pattern);
+        }
+
+        let mut alternatives = vec![pattern];
+        while self.match_next(&[TokenType::Pipe]) {
+            self.chop();
+            alternatives.push(self.single_pattern()?);
+        }
+        if let Some(binding) = alternatives.iter().find(|pattern| pattern.has_bindings()) {
+            return Err(
+                Error::new("Can't bind a variable in an alternative pattern".to_string())
+                    .with_span(binding.span()),
+            );
+        }
+
+        Ok(Pattern::Or { alternatives })
+    }
+
+    fn single_pattern(&mut self) -> Result<Pattern, Error> {
+        let is_variant = self
+            .peek(1)
+            .is_some_and(|token| token.token_type == TokenType::Dot);
+        if self.match_next(&[TokenType::Identifier]) && is_variant {
+            return self.variant_pattern();
+        }
+        if self.match_next(&[TokenType::LeftParen]) {
+            return self.tuple_pattern();
+        }
+        if self.match_next(&[TokenType::Identifier]) {
+            let name = self.chop().unwrap();
+            if name.lexeme == "_" {
+                return Ok(Pattern::Wildcard { span: name.span });
+            }
+            return Ok(Pattern::Binding { name });
+        }
+
+        let (start, span) = self.literal_pattern()?;
+        if !self.match_next(&[TokenType::DotDot, TokenType::DotDotEquals]) {
+            return Ok(Pattern::Literal { value: start, span });
+        }
+
+        let operator = self.chop().unwrap();
+        let (end, end_span) = self.literal_pattern()?;
+        let span = span.to(end_span);
+        let range = Range::new(start, end, operator.token_type == TokenType::DotDotEquals)
+            .map_err(|_| {
+                Error::new("Expected numbers as range pattern bounds".to_string()).with_span(span)
+            })?;
+
+        Ok(Pattern::Range { range, span })
+    }
+
+    fn tuple_pattern(&mut self) -> Result<Pattern, Error> {
+        let left_paren = self.chop().unwrap();
+        let pattern = self.pattern()?;
+        if !self.match_next(&[TokenType::Comma]) {
+            self.expect(
+                TokenType::RightParen,
+                "Expected a closing parenthesis".to_string(),
+            )?;
+            return Ok(pattern);
+        }
+
+        let mut elements = vec![pattern];
+        while self.match_next(&[TokenType::Comma]) {
+            self.chop();
+            if self.match_next(&[TokenType::RightParen]) {
+                break;
+            }
+            elements.push(self.pattern()?);
+        }
+        let right_paren = self.expect(
+            TokenType::RightParen,
+            "Expected ')' after tuple patterns".to_string(),
+        )?;
+
+        Ok(Pattern::Tuple {
+            elements,
+            span: left_paren.span.to(right_paren.span),
+        })
+    }
 
-            return Ok(Expression::Variable {
-                name,
-                member: Some(Box::new(member)),
+    fn variant_pattern(&mut self) -> Result<Pattern, Error> {
+        let enum_name = self.chop().unwrap();
+        let _dot = self.chop().unwrap();
+        let variant = self.expect(TokenType::Identifier, "Expected a variant name".to_string())?;
+        if !self.match_next(&[TokenType::LeftParen]) {
+            return Ok(Pattern::Variant {
+                span: enum_name.span.to(variant.span),
+                enum_name,
+                variant,
+                fields: Vec::new(),
             });
         }
 
-        Ok(Expression::Variable { name, member: None })
+        let _left_paren = self.chop().unwrap();
+        let mut fields = Vec::new();
+        while !self.match_next(&[TokenType::RightParen]) {
+            fields.push(self.pattern()?);
+            if !self.match_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.chop();
+        }
+        let right_paren = self.expect(
+            TokenType::RightParen,
+            "Expected ')' after variant patterns".to_string(),
+        )?;
+
+        Ok(Pattern::Variant {
+            span: enum_name.span.to(right_paren.span),
+            enum_name,
+            variant,
+            fields,
+        })
+    }
+
+    fn literal_pattern(&mut self) -> Result<(Value, Span), Error> {
+        let minus = match self.match_next(&[TokenType::Minus]) {
+            true => self.chop(),
+            false => None,
+        };
+        let literals: &[TokenType] = match minus {
+            Some(_) => &[TokenType::Integer, TokenType::Real],
+            None => &[
+                TokenType::Integer,
+                TokenType::Real,
+                TokenType::String,
+                TokenType::True,
+                TokenType::False,
+            ],
+        };
+        if !self.match_next(literals) {
+            return Err(Error::new("Expected a pattern".to_string()).with_span(self.current_span()));
+        }
+
+        let Expression::Value { value, span } = self.primary_expression()? else {
+            unreachable!("literals are parsed into values");
+        };
+        match (minus, value) {
+            (Some(minus), Value::Integer(int)) => Ok((Value::Integer(-int), minus.span.to(span))),
+            (Some(minus), Value::Real(real)) => Ok((Value::Real(-real), minus.span.to(span))),
+            (_, value) => Ok((value, span)),
+        }
+    }
+
+    fn interpolation(&mut self, start: Token) -> Result<Expression, Error> {
+        let mut parts = Vec::new();
+        let mut literal = start;
+        let start_span = literal.span;
+
+        loop {
+            if !literal.lexeme.is_empty() {
+                parts.push(Expression::Value {
+                    value: Value::String(literal.lexeme),
+                    span: literal.span,
+                });
+            }
+            if literal.token_type == TokenType::InterpolationEnd {
+                break;
+            }
+
+            parts.push(self.expression()?);
+
+            if !self.match_next(&[TokenType::InterpolationMiddle, TokenType::InterpolationEnd]) {
+                return Err(Error::new(
+                    "Expected a closing curly bracket after interpolated expression".to_string(),
+                )
+                .with_span(self.current_span()));
+            }
+            literal = self.chop().unwrap();
+        }
+
+        Ok(Expression::Interpolation {
+            parts,
+            span: start_span.to(literal.span),
+        })
+    }
+
+    fn construct(&mut self, name: Token) -> Result<Expression, Error> {
+        let _left_curly_bracket = self.chop().unwrap();
+        let mut fields: Vec<(Token, Expression)> = Vec::new();
+
+        self.consume_whitespace();
+        while !self.match_next(&[TokenType::RightCurlyBracket]) {
+            let field = self.expect(TokenType::Identifier, "Expected a field name".to_string())?;
+            if fields.iter().any(|(other, _)| other.lexeme == field.lexeme) {
+                self.errors.push(
+                    Error::new(format!("Duplicate field {}", field.lexeme)).with_span(field.span),
+                );
+            }
+
+            // `Player { x }` is short for `Player { x: x }`
+            let value = match self.match_next(&[TokenType::Colon]) {
+                true => {
+                    self.chop();
+                    self.expression()?
+                }
+                false => Expression::Variable {
+                    name: field.clone(),
+                },
+            };
+            fields.push((field, value));
+
+            self.consume_whitespace_before(&[TokenType::Comma, TokenType::RightCurlyBracket]);
+            if !self.match_next(&[TokenType::Comma]) {
+                break;
+            }
+            self.chop();
+            self.consume_whitespace();
+        }
+
+        let right_curly_bracket = self.expect(
+            TokenType::RightCurlyBracket,
+            "Expected '}' after struct fields".to_string(),
+        )?;
+
+        Ok(Expression::Construct {
+            span: name.span.to(right_curly_bracket.span),
+            name,
+            fields,
+        })
     }
 }
 
-pub fn parse(tokens: &[Token]) -> Result<Vec<Statement>, Error> {
+/// Parses as much as possible, returning every statement that parsed along with all syntax errors.
+pub fn parse_with_errors(tokens: &[Token]) -> (Vec<Statement>, Vec<Error>) {
+    let (statements, errors, _) = parse_with_warnings(tokens);
+    (statements, errors)
+}
+
+/// Like `parse_with_errors`, but also returns warnings about code that parses but is likely wrong.
+pub fn parse_with_warnings(tokens: &[Token]) -> (Vec<Statement>, Vec<Error>, Vec<Error>) {
     let mut parser = Parser::new(tokens);
 
-    parser.parse()
+    let statements = parser.parse();
+    (statements, parser.errors, parser.warnings)
+}
+
+pub fn parse(tokens: &[Token]) -> Result<Vec<Statement>, Vec<Error>> {
+    let (statements, errors) = parse_with_errors(tokens);
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(statements)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::lexer::Lexer;
+
+    fn parse_code(code: &str) -> (Vec<Statement>, Vec<Error>) {
+        let code = code.chars().collect::<Vec<char>>();
+        let tokens = Lexer::new(&code).collect::<Result<Vec<_>, _>>().unwrap();
+        parse_with_errors(&tokens)
+    }
+
+    fn value(value: Value) -> Expression {
+        Expression::Value {
+            value,
+            span: Span::default(),
+        }
+    }
 
     #[test]
     fn test_assign_var() {
@@ -490,9 +2411,8 @@ mod tests {
                 expr: Box::new(Expression::Assign {
                     assignee: Box::new(Expression::Variable {
                         name: Token::new(TokenType::Identifier, "x".to_string()),
-                        member: None
                     }),
-                    value: Box::new(Expression::Value(Value::Integer(5))),
+                    value: Box::new(value(Value::Integer(5))),
                 })
             }]
         );
@@ -519,15 +2439,13 @@ mod tests {
                     expr: Box::new(Expression::Assign {
                         assignee: Box::new(Expression::Variable {
                             name: Token::new(TokenType::Identifier, "x".to_string()),
-                            member: None
                         }),
-                        value: Box::new(Expression::Value(Value::Integer(5))),
+                        value: Box::new(value(Value::Integer(5))),
                     })
                 },
                 Statement::Print {
                     expr: Box::new(Expression::Variable {
                         name: Token::new(TokenType::Identifier, "x".to_string()),
-                        member: None
                     })
                 }
             ]
@@ -549,7 +2467,7 @@ mod tests {
             statements,
             vec![Statement::Expression {
                 expr: Box::new(Expression::Grouping {
-                    expr: Box::new(Expression::Value(Value::Integer(5))),
+                    expr: Box::new(value(Value::Integer(5))),
                 })
             }]
         );
@@ -569,10 +2487,10 @@ mod tests {
         assert_eq!(
             statements,
             vec![Statement::Expression {
-                expr: Box::new(Expression::BinaryExpr {
+                expr: Box::new(Expression::LogicalExpr {
                     operator: Token::new(TokenType::Or, "or".to_string()),
-                    left: Box::new(Expression::Value(Value::Boolean(true))),
-                    right: Box::new(Expression::Value(Value::Boolean(false))),
+                    left: Box::new(value(Value::Boolean(true))),
+                    right: Box::new(value(Value::Boolean(false))),
                 })
             }]
         );
@@ -592,10 +2510,10 @@ mod tests {
         assert_eq!(
             statements,
             vec![Statement::Expression {
-                expr: Box::new(Expression::BinaryExpr {
+                expr: Box::new(Expression::LogicalExpr {
                     operator: Token::new(TokenType::And, "and".to_string()),
-                    left: Box::new(Expression::Value(Value::Boolean(true))),
-                    right: Box::new(Expression::Value(Value::Boolean(false))),
+                    left: Box::new(value(Value::Boolean(true))),
+                    right: Box::new(value(Value::Boolean(false))),
                 })
             }]
         );
@@ -617,19 +2535,104 @@ mod tests {
         assert_eq!(
             statements,
             vec![Statement::Expression {
-                expr: Box::new(Expression::BinaryExpr {
+                expr: Box::new(Expression::LogicalExpr {
                     operator: Token::new(TokenType::Or, "or".to_string()),
-                    left: Box::new(Expression::BinaryExpr {
+                    left: Box::new(Expression::LogicalExpr {
                         operator: Token::new(TokenType::And, "and".to_string()),
-                        left: Box::new(Expression::Value(Value::Boolean(true))),
-                        right: Box::new(Expression::Value(Value::Boolean(false))),
+                        left: Box::new(value(Value::Boolean(true))),
+                        right: Box::new(value(Value::Boolean(false))),
                     }),
-                    right: Box::new(Expression::Value(Value::Boolean(true))),
+                    right: Box::new(value(Value::Boolean(true))),
                 })
             }]
         );
     }
 
+    #[test]
+    fn test_logic_precedence() {
+        let (statements, errors) = parse_code("let x = a or b and c == d\n");
+        assert!(errors.is_empty());
+        let Statement::Let { initializer, .. } = &statements[0] else {
+            panic!("Expected a let declaration");
+        };
+        let Expression::LogicalExpr {
+            operator, right, ..
+        } = &**initializer
+        else {
+            panic!("Expected a logical expression");
+        };
+        assert_eq!(operator.token_type, TokenType::Or);
+        assert!(matches!(
+            &**right,
+            Expression::LogicalExpr { operator, right, .. }
+                if operator.token_type == TokenType::And
+                    && matches!(**right, Expression::BinaryExpr { .. })
+        ));
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        fn shape(expr: &Expression) -> String {
+            match expr {
+                Expression::BinaryExpr {
+                    operator,
+                    left,
+                    right,
+                } => format!("({} {} {})", shape(left), operator.lexeme, shape(right)),
+                Expression::Unary { operator, right } => {
+                    format!("({}{})", operator.lexeme, shape(right))
+                }
+                Expression::Value { value, .. } => value.to_string(),
+                Expression::Variable { name } => name.lexeme.clone(),
+                _ => panic!("Unexpected expression {:?}", expr),
+            }
+        }
+
+        let cases = [
+            ("1 + 2 * 3 % 4", "(1 + ((2 * 3) % 4))"),
+            ("-2 ** 2", "(-(2 ** 2))"),
+            ("2 ** 3 ** 2", "(2 ** (3 ** 2))"),
+            ("2 ** -1", "(2 ** (-1))"),
+            ("a & 4 == 4", "((a & 4) == 4)"),
+            ("a | b ^ c & d", "(a | (b ^ (c & d)))"),
+            ("1 << 2 + 1", "(1 << (2 + 1))"),
+            ("a >> 1 < b", "((a >> 1) < b)"),
+        ];
+        for (code, expected) in cases {
+            let (statements, errors) = parse_code(&format!("{}\n", code));
+            assert!(errors.is_empty());
+            let Statement::Expression { expr } = &statements[0] else {
+                panic!("Expected an expression statement");
+            };
+            assert_eq!(shape(expr), expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let (statements, errors) = parse_code("a.b[0] += 1\nx -= y *= 2\n");
+        assert!(errors.is_empty());
+        assert!(matches!(
+            &statements[0],
+            Statement::Expression { expr }
+                if matches!(&**expr, Expression::CompoundAssign { operator, assignee, .. }
+                    if operator.token_type == TokenType::PlusEquals
+                        && matches!(**assignee, Expression::Index { .. }))
+        ));
+        assert!(matches!(
+            &statements[1],
+            Statement::Expression { expr }
+                if matches!(&**expr, Expression::CompoundAssign { value, .. }
+                    if matches!(**value, Expression::CompoundAssign { .. }))
+        ));
+
+        let (_, errors) = parse_code("1 += 2\n");
+        assert_eq!(
+            errors[0].message,
+            "Can't assign that expression to a variable"
+        );
+    }
+
     #[test]
     fn test_equality() {
         let tokens = vec![
@@ -646,8 +2649,8 @@ mod tests {
             vec![Statement::Expression {
                 expr: Box::new(Expression::BinaryExpr {
                     operator: Token::new(TokenType::EqualsEquals, "==".to_string()),
-                    left: Box::new(Expression::Value(Value::Integer(5))),
-                    right: Box::new(Expression::Value(Value::Integer(5))),
+                    left: Box::new(value(Value::Integer(5))),
+                    right: Box::new(value(Value::Integer(5))),
                 })
             }]
         );
@@ -669,8 +2672,8 @@ mod tests {
             vec![Statement::Expression {
                 expr: Box::new(Expression::BinaryExpr {
                     operator: Token::new(TokenType::Less, "<".to_string()),
-                    left: Box::new(Expression::Value(Value::Integer(5))),
-                    right: Box::new(Expression::Value(Value::Integer(5))),
+                    left: Box::new(value(Value::Integer(5))),
+                    right: Box::new(value(Value::Integer(5))),
                 })
             }]
         );
@@ -692,8 +2695,8 @@ mod tests {
             vec![Statement::Expression {
                 expr: Box::new(Expression::BinaryExpr {
                     operator: Token::new(TokenType::Star, "*".to_string()),
-                    left: Box::new(Expression::Value(Value::Integer(5))),
-                    right: Box::new(Expression::Value(Value::Integer(5))),
+                    left: Box::new(value(Value::Integer(5))),
+                    right: Box::new(value(Value::Integer(5))),
                 })
             }]
         );
@@ -721,10 +2724,10 @@ mod tests {
                     operator: Token::new(TokenType::Slash, "/".to_string()),
                     left: Box::new(Expression::BinaryExpr {
                         operator: Token::new(TokenType::Star, "*".to_string()),
-                        left: Box::new(Expression::Value(Value::Integer(5))),
-                        right: Box::new(Expression::Value(Value::Integer(5))),
+                        left: Box::new(value(Value::Integer(5))),
+                        right: Box::new(value(Value::Integer(5))),
                     }),
-                    right: Box::new(Expression::Value(Value::Integer(5))),
+                    right: Box::new(value(Value::Integer(5))),
                 })
             }]
         );
@@ -755,11 +2758,11 @@ mod tests {
                         operator: Token::new(TokenType::Star, "*".to_string()),
                         left: Box::new(Expression::Unary {
                             operator: Token::new(TokenType::Minus, "-".to_string()),
-                            right: Box::new(Expression::Value(Value::Integer(5))),
+                            right: Box::new(value(Value::Integer(5))),
                         }),
-                        right: Box::new(Expression::Value(Value::Integer(5))),
+                        right: Box::new(value(Value::Integer(5))),
                     }),
-                    right: Box::new(Expression::Value(Value::Integer(5))),
+                    right: Box::new(value(Value::Integer(5))),
                 })
             }]
         );
@@ -783,8 +2786,9 @@ mod tests {
             statements,
             vec![Statement::Block {
                 statements: vec![Statement::Expression {
-                    expr: Box::new(Expression::Value(Value::Integer(5))),
-                }]
+                    expr: Box::new(value(Value::Integer(5))),
+                }],
+                span: Span::default(),
             }]
         );
     }
@@ -808,11 +2812,12 @@ mod tests {
         assert_eq!(
             statements,
             vec![Statement::If {
-                condition: Box::new(Expression::Value(Value::Boolean(true))),
+                condition: Box::new(value(Value::Boolean(true))),
                 then_branch: Box::new(Statement::Block {
                     statements: vec![Statement::Expression {
-                        expr: Box::new(Expression::Value(Value::Integer(5))),
-                    }]
+                        expr: Box::new(value(Value::Integer(5))),
+                    }],
+                    span: Span::default(),
                 }),
                 else_branch: None,
             }]
@@ -843,16 +2848,18 @@ mod tests {
         assert_eq!(
             statements,
             vec![Statement::If {
-                condition: Box::new(Expression::Value(Value::Boolean(true))),
+                condition: Box::new(value(Value::Boolean(true))),
                 then_branch: Box::new(Statement::Block {
                     statements: vec![Statement::Expression {
-                        expr: Box::new(Expression::Value(Value::Integer(5))),
-                    }]
+                        expr: Box::new(value(Value::Integer(5))),
+                    }],
+                    span: Span::default(),
                 }),
                 else_branch: Some(Box::new(Statement::Block {
                     statements: vec![Statement::Expression {
-                        expr: Box::new(Expression::Value(Value::Integer(10))),
-                    }]
+                        expr: Box::new(value(Value::Integer(10))),
+                    }],
+                    span: Span::default(),
                 })),
             }]
         );
@@ -876,13 +2883,716 @@ mod tests {
             vec![Statement::Expression {
                 expr: Box::new(Expression::BinaryExpr {
                     operator: Token::new(TokenType::DotDot, "..".to_string()),
-                    left: Box::new(Expression::Value(Value::Integer(5))),
-                    right: Box::new(Expression::Value(Value::Integer(10))),
+                    left: Box::new(value(Value::Integer(5))),
+                    right: Box::new(value(Value::Integer(10))),
+                })
+            }]
+        );
+    }
+
+    #[test]
+    fn test_range_step() {
+        let (statements, errors) = parse_code("for i in 0..=n - 1 step 2 {\n}\nlet step = 1\n");
+        assert!(errors.is_empty());
+        let Statement::For { range, .. } = &statements[0] else {
+            panic!("Expected a for loop");
+        };
+        let Expression::BinaryExpr { operator, left, .. } = &**range else {
+            panic!("Expected a stepped range");
+        };
+        assert_eq!(operator.token_type, TokenType::Step);
+        assert!(matches!(
+            &**left,
+            Expression::BinaryExpr { operator, right, .. }
+                if operator.token_type == TokenType::DotDotEquals
+                    && matches!(**right, Expression::BinaryExpr { .. })
+        ));
+        assert!(matches!(&statements[1], Statement::Let { name, .. } if name.lexeme == "step"));
+    }
+
+    #[test]
+    fn test_error_span() {
+        let span = |start| Span {
+            start,
+            end: start + 1,
+            ..Span::default()
+        };
+        let tokens = vec![
+            Token::new(TokenType::Identifier, "x".to_string()).with_span(span(0)),
+            Token::new(TokenType::Equals, "=".to_string()).with_span(span(2)),
+            Token::new(TokenType::Star, "*".to_string()).with_span(span(4)),
+            Token::new(TokenType::Newline, "\n".to_string()).with_span(span(5)),
+        ];
+
+        assert_eq!(parse(&tokens).unwrap_err()[0].span, Some(span(4)));
+        assert_eq!(parse(&tokens[..2]).unwrap_err()[0].span, Some(span(2)));
+    }
+
+    #[test]
+    fn test_doc_comment() {
+        let tokens = vec![
+            Token::new(TokenType::DocComment, " Gravity".to_string()),
+            Token::new(TokenType::Newline, "\n".to_string()),
+            Token::new(TokenType::DocComment, "   in units/s".to_string()),
+            Token::new(TokenType::Newline, "\n".to_string()),
+            Token::new(TokenType::Newline, "\n".to_string()),
+            Token::new(TokenType::Identifier, "g".to_string()),
+            Token::new(TokenType::Equals, "=".to_string()),
+            Token::new(TokenType::Integer, "10".to_string()),
+            Token::new(TokenType::Newline, "\n".to_string()),
+        ];
+
+        let statements = parse(&tokens).unwrap();
+
+        assert_eq!(
+            statements,
+            vec![Statement::Documented {
+                doc: "Gravity\n  in units/s".to_string(),
+                statement: Box::new(Statement::Expression {
+                    expr: Box::new(Expression::Assign {
+                        assignee: Box::new(Expression::Variable {
+                            name: Token::new(TokenType::Identifier, "g".to_string()),
+                        }),
+                        value: Box::new(value(Value::Integer(10))),
+                    })
+                }),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_dangling_doc_comment() {
+        let tokens = vec![
+            Token::new(TokenType::DocComment, " Nothing".to_string()),
+            Token::new(TokenType::Newline, "\n".to_string()),
+        ];
+
+        assert!(parse(&tokens).is_err());
+    }
+
+    #[test]
+    fn test_let() {
+        let tokens = vec![
+            Token::new(TokenType::Let, "let".to_string()),
+            Token::new(TokenType::Identifier, "x".to_string()),
+            Token::new(TokenType::Equals, "=".to_string()),
+            Token::new(TokenType::Integer, "5".to_string()),
+            Token::new(TokenType::Newline, "\n".to_string()),
+        ];
+
+        let statements = parse(&tokens).unwrap();
+
+        assert_eq!(
+            statements,
+            vec![Statement::Let {
+                name: Token::new(TokenType::Identifier, "x".to_string()),
+                mutable: false,
+                annotation: None,
+                initializer: Box::new(value(Value::Integer(5))),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_let_without_initializer() {
+        let tokens = vec![
+            Token::new(TokenType::Let, "let".to_string()),
+            Token::new(TokenType::Identifier, "x".to_string()),
+            Token::new(TokenType::Newline, "\n".to_string()),
+        ];
+
+        assert!(parse(&tokens).is_err());
+    }
+
+    #[test]
+    fn test_recovery_reports_every_error() {
+        let (statements, errors) = parse_code(
+            "let x = \nprint 1\nlet = 2\n{\n    print *\n    print 2\n}\nprint 3 4\nprint 5\n",
+        );
+
+        let lines = errors
+            .iter()
+            .map(|error| error.span.unwrap().line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![1, 3, 5, 8]);
+
+        assert_eq!(statements.len(), 3);
+        assert!(matches!(
+            &statements[0],
+            Statement::Print { expr } if matches!(**expr, Expression::Value { value: Value::Integer(1), .. })
+        ));
+        assert!(matches!(
+            &statements[1],
+            Statement::Block { statements, .. } if statements.len() == 1
+        ));
+        assert!(matches!(
+            &statements[2],
+            Statement::Print { expr } if matches!(**expr, Expression::Value { value: Value::Integer(5), .. })
+        ));
+    }
+
+    #[test]
+    fn test_missing_curly_brackets() {
+        let (_, errors) = parse_code("if true\n    print 1\n}\n");
+        assert_eq!(errors[0].message, "Expected a curly bracket.");
+
+        let (_, errors) = parse_code("if true {\n    print 1\n");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Expected a closing curly bracket."]
+        );
+    }
+
+    #[test]
+    fn test_stray_closing_bracket() {
+        let (statements, errors) = parse_code("print 1\n}\nprint 2");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(statements.len(), 2);
+    }
+
+    #[test]
+    fn test_optional_terminators() {
+        let (statements, errors) = parse_code("if true { print 1 } else { print 2 }\nprint 3");
+        assert!(errors.is_empty());
+        assert_eq!(statements.len(), 2);
+    }
+
+    #[test]
+    fn test_function_declaration() {
+        let (statements, errors) =
+            parse_code("fn add(a, b) {\n    return a + b\n}\nadd(1, 2)(3)\n");
+        assert!(errors.is_empty());
+
+        let Statement::Function { declaration } = &statements[0] else {
+            panic!("Expected a function declaration");
+        };
+        assert_eq!(declaration.name.as_ref().unwrap().lexeme, "add");
+        assert_eq!(
+            declaration
+                .params
+                .iter()
+                .map(|param| param.name.lexeme.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+        assert!(matches!(
+            declaration.body[..],
+            [Statement::Return { value: Some(_), .. }]
+        ));
+
+        let Statement::Expression { expr } = &statements[1] else {
+            panic!("Expected an expression statement");
+        };
+        let Expression::Call {
+            callee, arguments, ..
+        } = &**expr
+        else {
+            panic!("Expected a call");
+        };
+        assert_eq!(arguments.len(), 1);
+        assert!(matches!(**callee, Expression::Call { ref arguments, .. } if arguments.len() == 2));
+    }
+
+    #[test]
+    fn test_anonymous_function() {
+        let (statements, errors) = parse_code("let f = fn(x) {\n    return\n}\n");
+        assert!(errors.is_empty());
+        assert!(matches!(
+            &statements[0],
+            Statement::Let { initializer, .. }
+                if matches!(&**initializer, Expression::Function { declaration } if declaration.name.is_none())
+        ));
+    }
+
+    #[test]
+    fn test_function_errors() {
+        let (_, errors) = parse_code("return 5\nfn f(a, a) {\n}\nfn g(a b) {\n}\n");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Can't return outside of a function",
+                "Duplicate parameter a",
+                "Expected ')' after function parameters",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loops() {
+        let (statements, errors) = parse_code(
+            "'outer: while x < 10 {\n    loop {\n        break 'outer\n    }\n    continue\n}\n",
+        );
+        assert!(errors.is_empty());
+        let Statement::While {
+            label: Some(label),
+            body,
+            ..
+        } = &statements[0]
+        else {
+            panic!("Expected a labeled while loop");
+        };
+        assert_eq!(label.lexeme, "'outer");
+        assert!(matches!(
+            &**body,
+            Statement::Block { statements, .. }
+                if matches!(&statements[0], Statement::Loop { label: None, .. })
+                    && matches!(&statements[1], Statement::Continue { label: None, .. })
+        ));
+    }
+
+    #[test]
+    fn test_loop_errors() {
+        let (_, errors) = parse_code(
+            "break\nloop {\n    continue 'outer\n}\nloop {\n    fn f() {\n        break\n    }\n}\n'outer: print 5\n",
+        );
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Can't break outside of a loop",
+                "Unknown loop label 'outer",
+                "Can't break outside of a loop",
+                "Expected a loop after label",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        let (statements, errors) =
+            parse_code("let a = [\n    1,\n    [2, 3],\n]\na[1][0] = a[0]\n");
+        assert!(errors.is_empty());
+        assert!(matches!(
+            &statements[0],
+            Statement::Let { initializer, .. }
+                if matches!(&**initializer, Expression::List { elements, .. } if elements.len() == 2)
+        ));
+        let Statement::Expression { expr } = &statements[1] else {
+            panic!("Expected an expression statement");
+        };
+        let Expression::Assign { assignee, value } = &**expr else {
+            panic!("Expected an assignment");
+        };
+        assert!(matches!(
+            &**assignee,
+            Expression::Index { object, .. } if matches!(**object, Expression::Index { .. })
+        ));
+        assert!(matches!(**value, Expression::Index { .. }));
+
+        let (_, errors) = parse_code("let a = [1, 2\nlet b = a[0\n");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Expected ']' after list elements",
+                "Expected ']' after index"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_struct() {
+        let (statements, errors) = parse_code(
+            "struct Player { x, y }\nlet p = Player {\n    x: 1,\n    y\n}\np.x = p.y\nif p.x == 1 {\n}\n",
+        );
+        assert!(errors.is_empty());
+        assert!(matches!(
+            &statements[0],
+            Statement::Struct { name, fields, .. } if name.lexeme == "Player" && fields.len() == 2
+        ));
+        assert!(matches!(
+            &statements[1],
+            Statement::Let { initializer, .. }
+                if matches!(&**initializer, Expression::Construct { fields, .. } if fields.len() == 2)
+        ));
+        assert!(matches!(
+            &statements[2],
+            Statement::Expression { expr }
+                if matches!(&**expr, Expression::Assign { assignee, .. }
+                    if matches!(**assignee, Expression::Member { .. }))
+        ));
+        assert!(matches!(&statements[3], Statement::If { .. }));
+
+        let (_, errors) =
+            parse_code("struct A { x, x }\nlet a = A { x: 1, x: 2 }\nlet b = A { x: 1\n");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Duplicate field x",
+                "Duplicate field x",
+                "Expected '}' after struct fields"
+            ]
+        );
+    }
+
+    fn parse_warnings(code: &str) -> Vec<String> {
+        let code = code.chars().collect::<Vec<char>>();
+        let tokens = Lexer::new(&code).collect::<Result<Vec<_>, _>>().unwrap();
+        let (_, errors, warnings) = parse_with_warnings(&tokens);
+        assert!(errors.is_empty(), "{:?}", errors);
+        warnings
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_match() {
+        let (statements, errors) = parse_code(
+            "let name = match n { 0 => \"none\", 1 | 2 => \"few\", -5..=-1 => \"negative\", x if x > 9 => \"many\", _ => \"some\" }\nmatch state {\n    0 => print \"idle\"\n    1 => {\n        state = 0\n    }\n    _ => {}\n}\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let Statement::Let { initializer, .. } = &statements[0] else {
+            panic!("expected a let, got {:?}", statements[0]);
+        };
+        let Expression::Match { arms, .. } = &**initializer else {
+            panic!("expected a match, got {:?}", initializer);
+        };
+        assert_eq!(arms.len(), 5);
+        assert!(matches!(
+            arms[0].pattern,
+            Pattern::Literal {
+                value: Value::Integer(0),
+                ..
+            }
+        ));
+        assert!(
+            matches!(&arms[1].pattern, Pattern::Or { alternatives } if alternatives.len() == 2)
+        );
+        assert!(matches!(
+            &arms[2].pattern,
+            Pattern::Range { range, .. }
+                if *range == Range::new(Value::Integer(-5), Value::Integer(-1), true).unwrap()
+        ));
+        assert!(matches!(&arms[3].pattern, Pattern::Binding { name } if name.lexeme == "x"));
+        assert!(arms[3].guard.is_some());
+        assert!(matches!(arms[4].pattern, Pattern::Wildcard { .. }));
+
+        let Statement::Match { arms, .. } = &statements[1] else {
+            panic!("expected a match, got {:?}", statements[1]);
+        };
+        assert!(matches!(arms[0].body, Statement::Print { .. }));
+        assert!(matches!(arms[1].body, Statement::Block { .. }));
+    }
+
+    #[test]
+    fn test_match_errors() {
+        let (_, errors) = parse_code(
+            "match x { 1 2 }\nmatch x { 1 => 2 }\nprint x\nlet a = match x { y | 1 => 2 }\nlet b = match x { 1 => 2 3 => 4 }\nlet c = match x { \"a\"..\"b\" => 1 }\n",
+        );
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Expected '=>' after match pattern",
+                "Can't bind a variable in an alternative pattern",
+                "Expected ',' or a newline after match arm",
+                "Expected numbers as range pattern bounds",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_warnings() {
+        assert!(parse_warnings("print match x { 1 => 2, n => n }\n").is_empty());
+        assert!(parse_warnings("print match x { true => 1, false => 0 }\n").is_empty());
+        assert_eq!(
+            parse_warnings("print match x { 1 => 2, n if n > 2 => n }\n"),
+            vec!["Match is not exhaustive, add a '_' arm for other values"]
+        );
+        assert_eq!(
+            parse_warnings("print match x { 0..10 => 1, 3 => 2, 1 | 2 => 3, _ => 4, 5 => 5 }\n"),
+            vec![
+                "Unreachable match arm",
+                "Unreachable match arm",
+                "Unreachable match arm"
+            ]
+        );
+        assert_eq!(
+            parse_warnings(
+                "match x {\n    n if n > 1 => print n\n    1 => print 1\n    1 => print 2\n}\n"
+            ),
+            vec![
+                "Unreachable match arm",
+                "Match is not exhaustive, add a '_' arm for other values"
+            ]
+        );
+
+        let state = "enum State { Idle, Walking(speed), Dead }\n";
+        assert!(parse_warnings(&format!(
+            "{}print match s {{ State.Idle | State.Dead => 0, State.Walking(n) => n }}\n",
+            state
+        ))
+        .is_empty());
+        assert_eq!(
+            parse_warnings(&format!(
+                "{}print match s {{ State.Walking(0) => 0, State.Walking(n) if n > 1 => n, State.Walking(1) => 1, State.Walking(1) => 2, State.Dead => 3 }}\n",
+                state
+            )),
+            vec![
+                "Unreachable match arm",
+                "Match is not exhaustive, missing State.Idle, State.Walking"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_enum() {
+        let (statements, errors) = parse_code(
+            "enum State {\n    Idle,\n    Walking(speed),\n    Attacking(target, damage),\n}\nmatch s {\n    State.Walking(0 | 1) => print 1\n    State.Attacking(t, _) => print t\n}\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        let Statement::Enum { name, variants, .. } = &statements[0] else {
+            panic!("expected an enum, got {:?}", statements[0]);
+        };
+        assert_eq!(name.lexeme, "State");
+        assert_eq!(
+            variants
+                .iter()
+                .map(|variant| (variant.name.lexeme.as_str(), variant.fields.len()))
+                .collect::<Vec<_>>(),
+            vec![("Idle", 0), ("Walking", 1), ("Attacking", 2)]
+        );
+        let Statement::Match { arms, .. } = &statements[1] else {
+            panic!("expected a match, got {:?}", statements[1]);
+        };
+        assert!(matches!(
+            &arms[1].pattern,
+            Pattern::Variant { variant, fields, .. }
+                if variant.lexeme == "Attacking"
+                    && matches!(fields[..], [Pattern::Binding { .. }, Pattern::Wildcard { .. }])
+        ));
+
+        let (_, errors) = parse_code(
+            "enum A { X, X, Y(a, a) }\nlet b = match x { A.Y(n) | A.X => n }\nenum C { D\n",
+        );
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Duplicate variant X",
+                "Duplicate field a",
+                "Can't bind a variable in an alternative pattern",
+                "Expected '}' after enum variants"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tuples() {
+        let (statements, errors) = parse_code(
+            "let a = (1, 2)\nlet b = (1)\nlet c = (1,)\nlet (x, (y, _)) = a\nx, y = y, x\nfn f() {\n    return 1, true\n}\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        let initializer = |statement: &Statement| match statement {
+            Statement::Let { initializer, .. } => (**initializer).clone(),
+            _ => panic!("expected a let, got {:?}", statement),
+        };
+        assert!(
+            matches!(initializer(&statements[0]), Expression::Tuple { elements, .. } if elements.len() == 2)
+        );
+        assert!(matches!(
+            initializer(&statements[1]),
+            Expression::Grouping { .. }
+        ));
+        assert!(
+            matches!(initializer(&statements[2]), Expression::Tuple { elements, .. } if elements.len() == 1)
+        );
+        assert!(matches!(
+            &statements[3],
+            Statement::Destructure { pattern: Pattern::Tuple { elements, .. }, .. }
+                if matches!(elements[1], Pattern::Tuple { .. })
+        ));
+        assert!(matches!(
+            &statements[4],
+            Statement::Expression { expr }
+                if matches!(&**expr, Expression::Assign { assignee, value }
+                    if matches!(**assignee, Expression::Tuple { .. })
+                        && matches!(**value, Expression::Tuple { .. }))
+        ));
+        let Statement::Function { declaration } = &statements[5] else {
+            panic!("expected a function, got {:?}", statements[5]);
+        };
+        assert!(matches!(
+            &declaration.body[0],
+            Statement::Return { value: Some(value), .. } if matches!(**value, Expression::Tuple { .. })
+        ));
+
+        let (_, errors) = parse_code("a, b + 1 = 1, 2\na, b += 1\na, b\nlet (x, 1) = t\n");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Can't assign that expression to a variable",
+                "Can't use += with multiple targets",
+                "Expected '=' after assignment targets",
+                "Only variables, '_' and tuples of them can be declared with let"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_const_and_mut() {
+        let (statements, errors) =
+            parse_code("const SPEED = 2.5\nlet mut x = 1\nlet mut (a, b) = (1, 2)\nlet y = x\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(matches!(&statements[0], Statement::Const { name, .. } if name.lexeme == "SPEED"));
+        assert!(matches!(
+            &statements[1],
+            Statement::Let { mutable: true, .. }
+        ));
+        assert!(matches!(
+            &statements[2],
+            Statement::Destructure { mutable: true, .. }
+        ));
+        assert!(matches!(
+            &statements[3],
+            Statement::Let { mutable: false, .. }
+        ));
+
+        let (_, errors) =
+            parse_code("fn f() {\n    const A = 1\n}\nif true {\n    const B = 2\n}\n");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Constants can only be declared at the top level",
+                "Constants can only be declared at the top level"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let (statements, errors) = parse_code(
+            "let mut speed: real = 2.0\nconst ORIGIN: (int, int) = (0, 0)\nfn f(x: int, g: fn, y) -> bool {\n    return true\n}\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(matches!(
+            &statements[0],
+            Statement::Let { annotation: Some(TypeAnnotation::Named { name }), .. } if name.lexeme == "real"
+        ));
+        assert!(matches!(
+            &statements[1],
+            Statement::Const { annotation: Some(TypeAnnotation::Tuple { elements, .. }), .. } if elements.len() == 2
+        ));
+        let Statement::Function { declaration } = &statements[2] else {
+            panic!("Expected a function declaration");
+        };
+        assert_eq!(
+            declaration
+                .params
+                .iter()
+                .map(|param| match &param.annotation {
+                    Some(TypeAnnotation::Named { name }) => name.lexeme.as_str(),
+                    _ => "",
+                })
+                .collect::<Vec<_>>(),
+            vec!["int", "fn", ""]
+        );
+        assert!(matches!(
+            &declaration.return_type,
+            Some(TypeAnnotation::Named { name }) if name.lexeme == "bool"
+        ));
+
+        let (_, errors) = parse_code("let x: = 1\nfn f(a: 1) {\n}\n");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Expected a type", "Expected a type"]
+        );
+    }
+
+    #[test]
+    fn test_string() {
+        let tokens = vec![
+            Token::new(TokenType::Print, "print".to_string()),
+            Token::new(TokenType::String, "hello".to_string()),
+            Token::new(TokenType::Newline, "\n".to_string()),
+        ];
+
+        let statements = parse(&tokens).unwrap();
+
+        assert_eq!(
+            statements,
+            vec![Statement::Print {
+                expr: Box::new(value(Value::String("hello".to_string()))),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let tokens = vec![
+            Token::new(TokenType::InterpolationStart, "x = ".to_string()),
+            Token::new(TokenType::Identifier, "x".to_string()),
+            Token::new(TokenType::InterpolationMiddle, "".to_string()),
+            Token::new(TokenType::Integer, "1".to_string()),
+            Token::new(TokenType::Plus, "+".to_string()),
+            Token::new(TokenType::Integer, "2".to_string()),
+            Token::new(TokenType::InterpolationEnd, "!".to_string()),
+            Token::new(TokenType::Newline, "\n".to_string()),
+        ];
+
+        let statements = parse(&tokens).unwrap();
+
+        assert_eq!(
+            statements,
+            vec![Statement::Expression {
+                expr: Box::new(Expression::Interpolation {
+                    parts: vec![
+                        value(Value::String("x = ".to_string())),
+                        Expression::Variable {
+                            name: Token::new(TokenType::Identifier, "x".to_string()),
+                        },
+                        Expression::BinaryExpr {
+                            operator: Token::new(TokenType::Plus, "+".to_string()),
+                            left: Box::new(value(Value::Integer(1))),
+                            right: Box::new(value(Value::Integer(2))),
+                        },
+                        value(Value::String("!".to_string())),
+                    ],
+                    span: Span::default(),
                 })
             }]
         );
     }
 
+    #[test]
+    fn test_unterminated_interpolation() {
+        let tokens = vec![
+            Token::new(TokenType::InterpolationStart, "x = ".to_string()),
+            Token::new(TokenType::Identifier, "x".to_string()),
+            Token::new(TokenType::Newline, "\n".to_string()),
+        ];
+
+        assert!(parse(&tokens).is_err());
+    }
+
     // FIXME: That test should be passing but it does not
     // #[test]
     // fn test_assign_grouping() {
@@ -907,7 +3617,7 @@ mod tests {
     //             expr: Box::new(Expression::Assign {
     //                 name: Token::new(TokenType::Identifier, "x".to_string()),
     //                 value: Box::new(Expression::Grouping {
-    //                     expr: Box::new(Expression::Value(Value::Integer(5))),
+    //                     expr: Box::new(value(Value::Integer(5))),
     //                 }),
     //             })
     //         }]
@@ -0,0 +1,1318 @@
+use std::{collections::HashMap, fmt::Display, rc::Rc};
+
+use glam::{DVec2, DVec3};
+
+use super::{
+    error::Error,
+    parser::{
+        Expression, FunctionDeclaration, MatchArm, Pattern, Statement, TypeAnnotation, Value,
+    },
+    span::Span,
+    token::{Token, TokenType},
+};
+use crate::interpreter::{
+    interpreter::{binary_operation, Environment},
+    vector,
+};
+
+/// How a name was declared, which decides whether it can be assigned to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    Variable,
+    MutableVariable,
+    Constant,
+    Parameter,
+    LoopVariable,
+    MatchVariable,
+    Function,
+    Struct,
+    Enum,
+}
+
+impl Binding {
+    fn assignment_error(&self, name: &Token) -> Option<Error> {
+        let message = match self {
+            Binding::MutableVariable => return None,
+            Binding::Variable => format!(
+                "Can't assign to immutable variable {}, declare it with 'let mut'",
+                name.lexeme
+            ),
+            Binding::Constant => format!("Can't assign to constant {}", name.lexeme),
+            Binding::Parameter => format!("Can't assign to parameter {}", name.lexeme),
+            Binding::LoopVariable => format!("Can't assign to loop variable {}", name.lexeme),
+            Binding::MatchVariable => format!("Can't assign to match binding {}", name.lexeme),
+            Binding::Function => format!("Can't assign to function {}", name.lexeme),
+            Binding::Struct => format!("Can't assign to struct {}", name.lexeme),
+            Binding::Enum => format!("Can't assign to enum {}", name.lexeme),
+        };
+        Some(Error::new(message).with_span(name.span))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct StructShape {
+    name: String,
+    fields: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct EnumShape {
+    name: String,
+    // Names of the variants and how many fields they have
+    variants: Vec<(String, usize)>,
+}
+
+/// What is known about a value before the program runs. Values of unknown type are never
+/// reported, so scripts without annotations are only checked where literals make types obvious.
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Unknown,
+    Nil,
+    Int,
+    Real,
+    Bool,
+    String,
+    Range,
+    Vec2,
+    Vec3,
+    List,
+    Tuple(Vec<Type>),
+    Function {
+        // None when only known from a `fn` annotation
+        params: Option<Vec<Type>>,
+        result: Box<Type>,
+    },
+    Struct(Rc<StructShape>),
+    Instance(Rc<StructShape>),
+    Enum(Rc<EnumShape>),
+    Variant(Rc<EnumShape>),
+}
+
+impl Type {
+    fn of(value: &Value) -> Type {
+        match value {
+            Value::Integer(_) => Type::Int,
+            Value::Real(_) => Type::Real,
+            Value::Boolean(_) => Type::Bool,
+            Value::Range(_) => Type::Range,
+            Value::Vec2(_) => Type::Vec2,
+            Value::Vec3(_) => Type::Vec3,
+            Value::String(_) => Type::String,
+            Value::Function(closure) => Type::Function {
+                params: Some(vec![Type::Unknown; closure.declaration.params.len()]),
+                result: Box::new(Type::Unknown),
+            },
+            Value::NativeFunction(native) => Type::Function {
+                params: Some(vec![Type::Unknown; native.arity]),
+                result: Box::new(Type::Unknown),
+            },
+            Value::CompiledFunction(closure) => Type::Function {
+                params: Some(vec![Type::Unknown; closure.function.arity]),
+                result: Box::new(Type::Unknown),
+            },
+            Value::Struct(struct_type) => Type::Struct(Rc::new(StructShape {
+                name: struct_type.name.clone(),
+                fields: struct_type.fields.clone(),
+            })),
+            Value::Instance(instance) => {
+                match Type::of(&Value::Struct(instance.borrow().struct_type.clone())) {
+                    Type::Struct(shape) => Type::Instance(shape),
+                    _ => unreachable!(),
+                }
+            }
+            Value::Enum(enum_type) => Type::Enum(Rc::new(EnumShape {
+                name: enum_type.name.clone(),
+                variants: enum_type
+                    .variants
+                    .iter()
+                    .map(|variant| (variant.name.clone(), variant.fields.len()))
+                    .collect(),
+            })),
+            Value::Variant(variant) => match Type::of(&Value::Enum(variant.enum_type.clone())) {
+                Type::Enum(shape) => Type::Variant(shape),
+                _ => unreachable!(),
+            },
+            Value::List(_) => Type::List,
+            Value::Tuple(elements) => Type::Tuple(elements.iter().map(Type::of).collect()),
+            Value::Nil => Type::Nil,
+        }
+    }
+
+    /// A value of this type to run operators on, so they are checked exactly like at runtime
+    fn sample(&self) -> Option<Value> {
+        match self {
+            Type::Nil => Some(Value::Nil),
+            Type::Int => Some(Value::Integer(1)),
+            Type::Real => Some(Value::Real(1.0)),
+            Type::Bool => Some(Value::Boolean(true)),
+            Type::String => Some(Value::String(String::new())),
+            Type::Vec2 => Some(Value::Vec2(DVec2::ONE)),
+            Type::Vec3 => Some(Value::Vec3(DVec3::ONE)),
+            Type::List => Some(Value::list(Vec::new())),
+            // Integer and real ranges accept different operands
+            _ => None,
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        *self != Type::Unknown
+    }
+
+    /// Whether a value of type `other` can be stored where this type is expected
+    fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (Type::Tuple(elements), Type::Tuple(others)) => {
+                elements.len() == others.len()
+                    && elements
+                        .iter()
+                        .zip(others)
+                        .all(|(element, other)| element.accepts(other))
+            }
+            (Type::Function { .. }, Type::Function { .. }) => true,
+            // Both backends convert an int to a real when it is stored under a real annotation
+            (Type::Real, Type::Int) => true,
+            (expected, other) => expected == other,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Unknown => write!(f, "any"),
+            Type::Nil => write!(f, "nil"),
+            Type::Int => write!(f, "int"),
+            Type::Real => write!(f, "real"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Range => write!(f, "range"),
+            Type::Vec2 => write!(f, "vec2"),
+            Type::Vec3 => write!(f, "vec3"),
+            Type::List => write!(f, "list"),
+            Type::Tuple(elements) => {
+                let elements = elements.iter().map(Type::to_string).collect::<Vec<_>>();
+                match elements.len() {
+                    1 => write!(f, "({},)", elements[0]),
+                    _ => write!(f, "({})", elements.join(", ")),
+                }
+            }
+            Type::Function { .. } => write!(f, "fn"),
+            Type::Struct(shape) => write!(f, "struct {}", shape.name),
+            Type::Instance(shape) => write!(f, "{}", shape.name),
+            Type::Enum(shape) => write!(f, "enum {}", shape.name),
+            Type::Variant(shape) => write!(f, "{}", shape.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Symbol {
+    binding: Binding,
+    ty: Type,
+}
+
+enum Lookup {
+    Found(Symbol),
+    // Declared further down in a scope we are in, so it doesn't exist yet
+    Later,
+    Missing,
+}
+
+struct Scope {
+    symbols: HashMap<String, Symbol>,
+    // Declarations further down in the scope. Functions created in the scope can already use
+    // them, because they only run after the scope was set up.
+    hoisted: HashMap<String, Symbol>,
+    // How many functions the scope is nested in
+    function_depth: usize,
+}
+
+/// Checks a parsed program before it runs, mirroring the scopes the interpreter will create.
+/// Fields and list elements can still be changed through immutable bindings, since they
+/// belong to the shared object rather than to the variable.
+struct Resolver<'a> {
+    // Scopes we are currently in, the top level first and the innermost last
+    scopes: Vec<Scope>,
+    // Return types of the functions we are currently in, innermost last
+    functions: Vec<Type>,
+    // Natives and variables that exist before the program runs
+    globals: &'a dyn GlobalScope,
+    errors: Vec<Error>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(globals: &'a dyn GlobalScope) -> Self {
+        Self {
+            scopes: Vec::new(),
+            functions: Vec::new(),
+            globals,
+            errors: Vec::new(),
+        }
+    }
+
+    fn declare(&mut self, name: &Token, symbol: Symbol) {
+        let top_level = self.functions.is_empty() && self.scopes.len() == 1;
+        let scope = self.scopes.last_mut().unwrap();
+        let previous = scope.symbols.insert(name.lexeme.clone(), symbol);
+        if top_level && previous.is_some_and(|previous| previous.binding == Binding::Constant) {
+            self.errors.push(
+                Error::new(format!("Constant {} is already declared", name.lexeme))
+                    .with_span(name.span),
+            );
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Lookup {
+        let mut later = false;
+        for scope in self.scopes.iter().rev() {
+            if let Some(symbol) = scope.symbols.get(name) {
+                return Lookup::Found(symbol.clone());
+            }
+            if let Some(symbol) = scope.hoisted.get(name) {
+                if self.functions.len() > scope.function_depth {
+                    return Lookup::Found(symbol.clone());
+                }
+                later = true;
+            }
+        }
+        match self.globals.global(name) {
+            Some(value) => Lookup::Found(Symbol {
+                binding: match (self.globals.binding(name), &value) {
+                    (Some(binding), _) => binding,
+                    (None, Value::NativeFunction(_)) => Binding::Function,
+                    (None, _) => Binding::MutableVariable,
+                },
+                ty: Type::of(&value),
+            }),
+            None if later => Lookup::Later,
+            None => Lookup::Missing,
+        }
+    }
+
+    fn variable(&mut self, name: &Token) -> Type {
+        match self.lookup(&name.lexeme) {
+            Lookup::Found(symbol) => symbol.ty,
+            Lookup::Later => self.error(
+                format!("Variable {} is used before it is declared", name.lexeme),
+                name.span,
+            ),
+            Lookup::Missing => self.error(format!("Variable {} not found", name.lexeme), name.span),
+        }
+    }
+
+    /// Reports an error, the expression it was found in is of unknown type from then on
+    fn error(&mut self, message: String, span: Span) -> Type {
+        self.errors.push(Error::new(message).with_span(span));
+        Type::Unknown
+    }
+
+    fn expect_type(&mut self, expected: &Type, actual: &Type, what: &str, span: Span) {
+        if !expected.accepts(actual) {
+            self.error(
+                format!("Expected {} for {} but got {}", expected, what, actual),
+                span,
+            );
+        }
+    }
+
+    fn annotation_type(&self, annotation: &TypeAnnotation) -> Result<Type, Error> {
+        let name = match annotation {
+            TypeAnnotation::Named { name } => name,
+            TypeAnnotation::Tuple { elements, .. } => {
+                return Ok(Type::Tuple(
+                    elements
+                        .iter()
+                        .map(|element| self.annotation_type(element))
+                        .collect::<Result<_, _>>()?,
+                ))
+            }
+        };
+        let ty = match name.lexeme.as_str() {
+            "any" => Type::Unknown,
+            "nil" => Type::Nil,
+            "int" => Type::Int,
+            "real" => Type::Real,
+            "bool" => Type::Bool,
+            "string" => Type::String,
+            "range" => Type::Range,
+            "vec2" => Type::Vec2,
+            "vec3" => Type::Vec3,
+            "list" => Type::List,
+            "fn" => Type::Function {
+                params: None,
+                result: Box::new(Type::Unknown),
+            },
+            type_name => match self.lookup(type_name) {
+                Lookup::Found(Symbol {
+                    ty: Type::Struct(shape),
+                    ..
+                }) => Type::Instance(shape),
+                Lookup::Found(Symbol {
+                    ty: Type::Enum(shape),
+                    ..
+                }) => Type::Variant(shape),
+                _ => {
+                    return Err(
+                        Error::new(format!("Unknown type {}", type_name)).with_span(name.span)
+                    )
+                }
+            },
+        };
+        Ok(ty)
+    }
+
+    // Types named by annotations of declarations further down are used without reporting
+    // errors, they are reported once the declaration itself is resolved
+    fn declared_type(&mut self, annotation: &Option<TypeAnnotation>, report: bool) -> Type {
+        match annotation
+            .as_ref()
+            .map(|annotation| self.annotation_type(annotation))
+        {
+            Some(Ok(ty)) => ty,
+            Some(Err(error)) if report => {
+                self.errors.push(error);
+                Type::Unknown
+            }
+            _ => Type::Unknown,
+        }
+    }
+
+    fn function_type(&mut self, declaration: &FunctionDeclaration, report: bool) -> Type {
+        let params = declaration
+            .params
+            .iter()
+            .map(|param| self.declared_type(&param.annotation, report))
+            .collect();
+        Type::Function {
+            params: Some(params),
+            result: Box::new(self.declared_type(&declaration.return_type, report)),
+        }
+    }
+
+    fn declared_symbol<'s>(&mut self, statement: &'s Statement) -> Option<(&'s Token, Symbol)> {
+        let (name, binding, ty) = match statement {
+            Statement::Let {
+                name,
+                mutable,
+                annotation,
+                ..
+            } => {
+                let binding = match mutable {
+                    true => Binding::MutableVariable,
+                    false => Binding::Variable,
+                };
+                (name, binding, self.declared_type(annotation, false))
+            }
+            Statement::Const {
+                name, annotation, ..
+            } => (
+                name,
+                Binding::Constant,
+                self.declared_type(annotation, false),
+            ),
+            Statement::Function { declaration } => (
+                declaration.name.as_ref()?,
+                Binding::Function,
+                self.function_type(declaration, false),
+            ),
+            Statement::Struct { name, fields, .. } => {
+                (name, Binding::Struct, struct_type(name, fields))
+            }
+            Statement::Enum { name, variants, .. } => (
+                name,
+                Binding::Enum,
+                Type::Enum(Rc::new(EnumShape {
+                    name: name.lexeme.clone(),
+                    variants: variants
+                        .iter()
+                        .map(|variant| (variant.name.lexeme.clone(), variant.fields.len()))
+                        .collect(),
+                })),
+            ),
+            Statement::Documented { statement, .. } => return self.declared_symbol(statement),
+            _ => return None,
+        };
+        Some((name, Symbol { binding, ty }))
+    }
+
+    fn scoped(&mut self, statements: &[Statement], resolve: impl FnOnce(&mut Self)) {
+        self.scopes.push(Scope {
+            symbols: HashMap::new(),
+            hoisted: HashMap::new(),
+            function_depth: self.functions.len(),
+        });
+        // Structs and enums first, so the annotations of other declarations can name them
+        for types in [true, false] {
+            for statement in statements {
+                if let Some((name, symbol)) = self.declared_symbol(statement) {
+                    let is_type = matches!(symbol.binding, Binding::Struct | Binding::Enum);
+                    if is_type == types {
+                        let scope = self.scopes.last_mut().unwrap();
+                        scope.hoisted.insert(name.lexeme.clone(), symbol);
+                    }
+                }
+            }
+        }
+        resolve(self);
+        self.scopes.pop();
+    }
+
+    fn resolve_statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression { expr } | Statement::Print { expr } => {
+                self.resolve_expression(expr);
+            }
+            Statement::Let {
+                name,
+                mutable,
+                annotation,
+                initializer,
+            } => {
+                let binding = match mutable {
+                    true => Binding::MutableVariable,
+                    false => Binding::Variable,
+                };
+                self.declare_variable(name, binding, annotation, initializer);
+            }
+            Statement::Const {
+                name,
+                annotation,
+                initializer,
+            } => self.declare_variable(name, Binding::Constant, annotation, initializer),
+            Statement::Destructure {
+                pattern,
+                mutable,
+                initializer,
+            } => {
+                let value = self.resolve_expression(initializer);
+                let binding = match mutable {
+                    true => Binding::MutableVariable,
+                    false => Binding::Variable,
+                };
+                self.resolve_pattern(pattern, &value, binding, initializer.span());
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_condition(condition, "Expected boolean in an if condition");
+                self.resolve_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(else_branch);
+                }
+            }
+            Statement::Block { statements, .. } => self.scoped(statements, |resolver| {
+                resolver.resolve_statements(statements)
+            }),
+            Statement::For {
+                variable,
+                range,
+                body,
+                ..
+            } => {
+                let ty = self.resolve_expression(range);
+                if !matches!(ty, Type::Unknown | Type::Range | Type::List) {
+                    self.error("Expected range or list".to_string(), range.span());
+                }
+                self.scoped(&[], |resolver| {
+                    let symbol = Symbol {
+                        binding: Binding::LoopVariable,
+                        ty: Type::Unknown,
+                    };
+                    resolver.declare(variable, symbol);
+                    resolver.resolve_statement(body);
+                });
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.resolve_condition(condition, "Expected boolean in a while condition");
+                self.resolve_statement(body);
+            }
+            Statement::Loop { body, .. } => self.resolve_statement(body),
+            Statement::Break { .. } | Statement::Continue { .. } => {}
+            Statement::Function { declaration } => {
+                if let Some(name) = &declaration.name {
+                    let symbol = Symbol {
+                        binding: Binding::Function,
+                        ty: self.function_type(declaration, false),
+                    };
+                    self.declare(name, symbol);
+                }
+                self.resolve_function(declaration);
+            }
+            Statement::Struct { .. } | Statement::Enum { .. } => {
+                if let Some((name, symbol)) = self.declared_symbol(statement) {
+                    self.declare(name, symbol);
+                }
+            }
+            Statement::Return { keyword, value } => {
+                let (ty, span) = match value {
+                    Some(value) => (self.resolve_expression(value), value.span()),
+                    None => (Type::Nil, keyword.span),
+                };
+                if let Some(expected) = self.functions.last().cloned() {
+                    self.expect_type(&expected, &ty, "the return value", span);
+                }
+            }
+            Statement::Match { value, arms, .. } => {
+                let subject = self.resolve_expression(value);
+                self.resolve_arms(&subject, arms, Self::resolve_statement);
+            }
+            Statement::Documented { statement, .. } => self.resolve_statement(statement),
+        }
+    }
+
+    fn declare_variable(
+        &mut self,
+        name: &Token,
+        binding: Binding,
+        annotation: &Option<TypeAnnotation>,
+        initializer: &Expression,
+    ) {
+        let value = self.resolve_expression(initializer);
+        let ty = match annotation {
+            Some(_) => {
+                let declared = self.declared_type(annotation, true);
+                self.expect_type(&declared, &value, &name.lexeme, initializer.span());
+                declared
+            }
+            // Without an annotation the type is only known while the value can't change
+            None if binding == Binding::MutableVariable => Type::Unknown,
+            None => value,
+        };
+        self.declare(name, Symbol { binding, ty });
+    }
+
+    fn resolve_condition(&mut self, condition: &Expression, message: &str) {
+        let ty = self.resolve_expression(condition);
+        if !Type::Bool.accepts(&ty) {
+            self.error(message.to_string(), condition.span());
+        }
+    }
+
+    fn resolve_function(&mut self, declaration: &FunctionDeclaration) -> Type {
+        let ty = self.function_type(declaration, true);
+        let Type::Function { params, result } = &ty else {
+            unreachable!("function_type always returns a function");
+        };
+
+        self.functions.push((**result).clone());
+        self.scoped(&declaration.body, |resolver| {
+            for (param, ty) in declaration.params.iter().zip(params.iter().flatten()) {
+                let symbol = Symbol {
+                    binding: Binding::Parameter,
+                    ty: ty.clone(),
+                };
+                resolver.declare(&param.name, symbol);
+            }
+            resolver.resolve_statements(&declaration.body);
+        });
+        self.functions.pop();
+        ty
+    }
+
+    /// Resolves the arms of a match and returns the types of their bodies
+    fn resolve_arms<B, T>(
+        &mut self,
+        subject: &Type,
+        arms: &[MatchArm<B>],
+        resolve_body: fn(&mut Self, &B) -> T,
+    ) -> Vec<T> {
+        let mut types = Vec::new();
+        for arm in arms {
+            self.scoped(&[], |resolver| {
+                let span = arm.pattern.span();
+                resolver.resolve_pattern(&arm.pattern, subject, Binding::MatchVariable, span);
+                if let Some(guard) = &arm.guard {
+                    resolver.resolve_condition(guard, "Expected boolean in a match guard");
+                }
+                types.push(resolve_body(resolver, &arm.body));
+            });
+        }
+        types
+    }
+
+    /// Declares the variables bound by a pattern matching a value of type `subject`
+    fn resolve_pattern(&mut self, pattern: &Pattern, subject: &Type, binding: Binding, span: Span) {
+        match pattern {
+            Pattern::Binding { name } => {
+                // Without an annotation the type is only known while the value can't change
+                let ty = match binding {
+                    Binding::MutableVariable => Type::Unknown,
+                    _ => subject.clone(),
+                };
+                self.declare(name, Symbol { binding, ty });
+            }
+            Pattern::Tuple { elements, .. } => {
+                let types = match subject {
+                    Type::Tuple(types) if types.len() == elements.len() => types.clone(),
+                    // Match arms can try tuples of other sizes
+                    ty if ty.is_known() && binding != Binding::MatchVariable => {
+                        self.error(
+                            format!(
+                                "Expected a tuple of {} values but got {}",
+                                elements.len(),
+                                ty
+                            ),
+                            span,
+                        );
+                        vec![Type::Unknown; elements.len()]
+                    }
+                    _ => vec![Type::Unknown; elements.len()],
+                };
+                for (element, ty) in elements.iter().zip(&types) {
+                    self.resolve_pattern(element, ty, binding, span);
+                }
+            }
+            Pattern::Or { alternatives } => {
+                for alternative in alternatives {
+                    self.resolve_pattern(alternative, subject, binding, span);
+                }
+            }
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+                span: pattern_span,
+            } => {
+                match self.variable(enum_name) {
+                    Type::Enum(shape) => match shape
+                        .variants
+                        .iter()
+                        .find(|(name, _)| *name == variant.lexeme)
+                    {
+                        Some((_, count)) if *count != fields.len() => {
+                            self.error(
+                                format!(
+                                    "Variant {}.{} has {} fields but the pattern has {}",
+                                    shape.name,
+                                    variant.lexeme,
+                                    count,
+                                    fields.len()
+                                ),
+                                *pattern_span,
+                            );
+                        }
+                        Some(_) => {}
+                        None => {
+                            self.error(
+                                format!("Enum {} has no variant {}", shape.name, variant.lexeme),
+                                variant.span,
+                            );
+                        }
+                    },
+                    Type::Unknown => {}
+                    _ => {
+                        self.error(
+                            format!("{} is not an enum", enum_name.lexeme),
+                            enum_name.span,
+                        );
+                    }
+                }
+                for field in fields {
+                    self.resolve_pattern(field, &Type::Unknown, binding, span);
+                }
+            }
+            Pattern::Wildcard { .. } | Pattern::Literal { .. } | Pattern::Range { .. } => {}
+        }
+    }
+
+    fn resolve_expression(&mut self, expression: &Expression) -> Type {
+        match expression {
+            Expression::Value { value, .. } => Type::of(value),
+            Expression::Variable { name } => self.variable(name),
+            Expression::Unary { operator, right } => {
+                let ty = self.resolve_expression(right);
+                self.unary_type(operator, ty, expression.span())
+            }
+            Expression::BinaryExpr {
+                operator,
+                left,
+                right,
+            } => {
+                let left = self.resolve_expression(left);
+                let right = self.resolve_expression(right);
+                self.binary_type(&operator.token_type, left, right, expression.span())
+            }
+            Expression::LogicalExpr {
+                operator,
+                left,
+                right,
+            } => {
+                for operand in [left, right] {
+                    let ty = self.resolve_expression(operand);
+                    if !Type::Bool.accepts(&ty) {
+                        self.error(
+                            format!(
+                                "Expected boolean operands for {} but got {}",
+                                operator.lexeme, ty
+                            ),
+                            operand.span(),
+                        );
+                    }
+                }
+                Type::Bool
+            }
+            Expression::Grouping { expr } => self.resolve_expression(expr),
+            Expression::Interpolation { parts, .. } => {
+                for part in parts {
+                    self.resolve_expression(part);
+                }
+                Type::String
+            }
+            Expression::Assign { assignee, value } => {
+                let ty = self.resolve_expression(value);
+                self.resolve_assignee(assignee, &ty, value.span());
+                ty
+            }
+            Expression::CompoundAssign {
+                operator,
+                assignee,
+                value,
+            } => {
+                let right = self.resolve_expression(value);
+                let left = self.resolve_expression(assignee);
+                let ty = match operator.token_type.compound_operator() {
+                    Some(operator) => self.binary_type(&operator, left, right, expression.span()),
+                    None => Type::Unknown,
+                };
+                self.resolve_assignee(assignee, &ty, expression.span());
+                ty
+            }
+            Expression::Member { object, name } => {
+                let object = self.resolve_expression(object);
+                self.member_type(object, name)
+            }
+            Expression::Construct { name, fields, span } => {
+                self.construct_type(name, fields, *span)
+            }
+            Expression::Call {
+                callee,
+                arguments,
+                span,
+            } => {
+                let callee = self.resolve_expression(callee);
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| (self.resolve_expression(argument), argument.span()))
+                    .collect::<Vec<_>>();
+                self.call_type(callee, arguments, *span)
+            }
+            Expression::List { elements, .. } => {
+                for element in elements {
+                    self.resolve_expression(element);
+                }
+                Type::List
+            }
+            Expression::Tuple { elements, .. } => Type::Tuple(
+                elements
+                    .iter()
+                    .map(|element| self.resolve_expression(element))
+                    .collect(),
+            ),
+            Expression::Index { object, index, .. } => {
+                let object_type = self.resolve_expression(object);
+                let index_type = self.resolve_expression(index);
+                if !Type::Int.accepts(&index_type) {
+                    return self.error(
+                        format!("Expected integer index but got {}", index_type),
+                        index.span(),
+                    );
+                }
+                match object_type {
+                    Type::Tuple(elements) => match index.as_ref() {
+                        Expression::Value {
+                            value: Value::Integer(int),
+                            ..
+                        } => match elements.get(*int as usize) {
+                            Some(ty) if *int >= 0 => ty.clone(),
+                            _ => self.error(
+                                format!(
+                                    "Index {} is out of bounds for a list of length {}",
+                                    int,
+                                    elements.len()
+                                ),
+                                index.span(),
+                            ),
+                        },
+                        _ => Type::Unknown,
+                    },
+                    Type::Unknown | Type::List => Type::Unknown,
+                    ty => self.error(format!("Type {} can't be indexed", ty), object.span()),
+                }
+            }
+            Expression::Function { declaration } => self.resolve_function(declaration),
+            Expression::Match { value, arms, .. } => {
+                let subject = self.resolve_expression(value);
+                let types = self.resolve_arms(&subject, arms, Self::resolve_expression);
+                match types.split_first() {
+                    Some((first, rest)) if rest.iter().all(|ty| ty == first) => first.clone(),
+                    _ => Type::Unknown,
+                }
+            }
+        }
+    }
+
+    fn unary_type(&mut self, operator: &Token, ty: Type, span: Span) -> Type {
+        match (&operator.token_type, ty) {
+            (_, Type::Unknown) => Type::Unknown,
+            (TokenType::Minus, ty @ (Type::Int | Type::Real | Type::Vec2 | Type::Vec3)) => ty,
+            (TokenType::Minus, _) => self.error("Expected number".to_string(), span),
+            (TokenType::Bang, ty @ (Type::Bool | Type::Int)) => ty,
+            (TokenType::Bang, _) => self.error("Expected boolean or integer".to_string(), span),
+            _ => Type::Unknown,
+        }
+    }
+
+    fn binary_type(&mut self, operator: &TokenType, left: Type, right: Type, span: Span) -> Type {
+        if let (Some(left), Some(right)) = (left.sample(), right.sample()) {
+            return match binary_operation(operator, left, right) {
+                Ok(value) => Type::of(&value),
+                Err(error) => {
+                    self.errors.push(error.or_span(span));
+                    Type::Unknown
+                }
+            };
+        }
+        match operator {
+            TokenType::EqualsEquals
+            | TokenType::BangEquals
+            | TokenType::Less
+            | TokenType::LessEquals
+            | TokenType::Greater
+            | TokenType::GreaterEquals => Type::Bool,
+            TokenType::DotDot | TokenType::DotDotEquals => Type::Range,
+            _ => Type::Unknown,
+        }
+    }
+
+    fn member_type(&mut self, object: Type, name: &Token) -> Type {
+        let member = name.lexeme.as_str();
+        match (&object, member) {
+            (Type::Unknown, _) => Type::Unknown,
+            (Type::String | Type::List | Type::Tuple(_) | Type::Range, "len") => Type::Int,
+            (Type::Range, "start" | "last") => Type::Unknown,
+            (Type::Vec2 | Type::Vec3, _) => {
+                let dimensions = if object == Type::Vec2 { 2 } else { 3 };
+                match vector::swizzle(member, dimensions).map(|swizzle| swizzle.len()) {
+                    Some(1) => Type::Real,
+                    Some(2) => Type::Vec2,
+                    Some(_) => Type::Vec3,
+                    None => self.error(
+                        format!("Type {} has no member {}", object, member),
+                        name.span,
+                    ),
+                }
+            }
+            (Type::Enum(shape), _) => {
+                match shape.variants.iter().find(|(variant, _)| variant == member) {
+                    Some((_, 0)) => Type::Variant(shape.clone()),
+                    Some((_, fields)) => Type::Function {
+                        params: Some(vec![Type::Unknown; *fields]),
+                        result: Box::new(Type::Variant(shape.clone())),
+                    },
+                    None => self.error(
+                        format!("Enum {} has no variant {}", shape.name, member),
+                        name.span,
+                    ),
+                }
+            }
+            (Type::Instance(shape), _) => match shape.fields.iter().any(|field| field == member) {
+                true => Type::Unknown,
+                false => self.error(
+                    format!("Struct {} has no field {}", shape.name, member),
+                    name.span,
+                ),
+            },
+            _ => self.error(
+                format!("Type {} has no member {}", object, member),
+                name.span,
+            ),
+        }
+    }
+
+    fn construct_type(&mut self, name: &Token, fields: &[(Token, Expression)], span: Span) -> Type {
+        for (_, value) in fields {
+            self.resolve_expression(value);
+        }
+        let shape = match self.variable(name) {
+            Type::Struct(shape) => shape,
+            Type::Unknown => return Type::Unknown,
+            _ => return self.error(format!("{} is not a struct", name.lexeme), name.span),
+        };
+
+        for (field, _) in fields {
+            if !shape.fields.contains(&field.lexeme) {
+                self.error(
+                    format!("Struct {} has no field {}", shape.name, field.lexeme),
+                    field.span,
+                );
+            }
+        }
+        for field in &shape.fields {
+            if !fields.iter().any(|(other, _)| other.lexeme == *field) {
+                self.error(
+                    format!("Missing field {} when constructing {}", field, shape.name),
+                    span,
+                );
+            }
+        }
+        Type::Instance(shape)
+    }
+
+    fn call_type(&mut self, callee: Type, arguments: Vec<(Type, Span)>, span: Span) -> Type {
+        let (params, result) = match callee {
+            Type::Function { params, result } => (params, *result),
+            Type::Unknown => return Type::Unknown,
+            ty => return self.error(format!("Type {} is not callable", ty), span),
+        };
+        let Some(params) = params else {
+            return result;
+        };
+
+        if params.len() != arguments.len() {
+            return self.error(
+                format!(
+                    "Expected {} arguments but got {}",
+                    params.len(),
+                    arguments.len()
+                ),
+                span,
+            );
+        }
+        for (i, (param, (argument, span))) in params.iter().zip(&arguments).enumerate() {
+            self.expect_type(param, argument, &format!("argument {}", i + 1), *span);
+        }
+        result
+    }
+
+    /// Checks that `assignee` can be assigned a value of type `ty`
+    fn resolve_assignee(&mut self, assignee: &Expression, ty: &Type, span: Span) {
+        match assignee {
+            Expression::Variable { name } => {
+                let symbol = match self.lookup(&name.lexeme) {
+                    Lookup::Found(symbol) => symbol,
+                    Lookup::Later => {
+                        self.error(
+                            format!("Variable {} is used before it is declared", name.lexeme),
+                            name.span,
+                        );
+                        return;
+                    }
+                    Lookup::Missing => {
+                        self.error(
+                            format!("Variable {} is not declared", name.lexeme),
+                            name.span,
+                        );
+                        return;
+                    }
+                };
+                match symbol.binding.assignment_error(name) {
+                    Some(error) => self.errors.push(error),
+                    None => self.expect_type(&symbol.ty, ty, &name.lexeme, span),
+                }
+            }
+            Expression::Tuple { elements, .. } => {
+                let types = match ty {
+                    Type::Tuple(types) if types.len() == elements.len() => types.clone(),
+                    Type::Unknown => vec![Type::Unknown; elements.len()],
+                    ty => {
+                        self.error(
+                            format!(
+                                "Expected a tuple of {} values but got {}",
+                                elements.len(),
+                                ty
+                            ),
+                            span,
+                        );
+                        vec![Type::Unknown; elements.len()]
+                    }
+                };
+                for (element, ty) in elements.iter().zip(&types) {
+                    self.resolve_assignee(element, ty, span);
+                }
+            }
+            // Writing a field or an element only reads the variable holding the object. Vectors
+            // are values though, writing a component writes the whole vector back.
+            assignee => {
+                if let Some((name, member)) = member_root(assignee) {
+                    if let Lookup::Found(symbol) = self.lookup(&name.lexeme) {
+                        let vector = match symbol.ty {
+                            Type::Vec2 | Type::Vec3 => true,
+                            Type::Unknown => vector::swizzle(&member.lexeme, 3).is_some(),
+                            _ => false,
+                        };
+                        match symbol.binding.assignment_error(name) {
+                            Some(error) if vector => self.errors.push(error),
+                            _ => {}
+                        }
+                    }
+                }
+                self.resolve_expression(assignee);
+            }
+        }
+    }
+}
+
+/// The variable a chain of members like `a.b.c` starts at and the member read from it
+fn member_root(assignee: &Expression) -> Option<(&Token, &Token)> {
+    let mut object = assignee;
+    let mut member = None;
+    while let Expression::Member {
+        object: inner,
+        name,
+    } = object
+    {
+        object = inner;
+        member = Some(name);
+    }
+    match object {
+        Expression::Variable { name } => Some((name, member?)),
+        _ => None,
+    }
+}
+
+fn struct_type(name: &Token, fields: &[Token]) -> Type {
+    Type::Struct(Rc::new(StructShape {
+        name: name.lexeme.clone(),
+        fields: fields.iter().map(|field| field.lexeme.clone()).collect(),
+    }))
+}
+
+/// Name of the type of a value, like `int` or `(real, string)`
+pub fn type_name(value: &Value) -> String {
+    Type::of(value).to_string()
+}
+
+/// Variables that exist before the program runs, like natives or the globals of code that was
+/// loaded earlier
+pub trait GlobalScope {
+    fn global(&self, name: &str) -> Option<Value>;
+
+    /// How a global was declared. Without a binding, natives are functions and any other
+    /// value is a mutable variable.
+    fn binding(&self, _name: &str) -> Option<Binding> {
+        None
+    }
+}
+
+impl GlobalScope for Environment {
+    fn global(&self, name: &str) -> Option<Value> {
+        self.get(name)
+    }
+
+    fn binding(&self, name: &str) -> Option<Binding> {
+        self.is_constant(name).then_some(Binding::Constant)
+    }
+}
+
+/// Checks the program for mistakes that can be found without running it: names used before
+/// they are declared, unknown members, operators applied to the wrong types and values that
+/// don't match their annotations. Names the program doesn't declare are looked up in the
+/// globals it will run with.
+pub fn resolve(statements: &[Statement], globals: &dyn GlobalScope) -> Result<(), Vec<Error>> {
+    let mut resolver = Resolver::new(globals);
+    resolver.scoped(statements, |resolver| {
+        resolver.resolve_statements(statements)
+    });
+
+    if !resolver.errors.is_empty() {
+        return Err(resolver.errors);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{lexer::Lexer, parser::parse};
+
+    fn check(code: &str) -> Result<(), Vec<Error>> {
+        let code = code.chars().collect::<Vec<char>>();
+        let tokens = Lexer::new(&code).collect::<Result<Vec<_>, _>>().unwrap();
+        resolve(&parse(&tokens).unwrap(), &Environment::new())
+    }
+
+    fn resolve_code(code: &str) -> Vec<String> {
+        match check(code) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+        }
+    }
+
+    #[test]
+    fn test_mutable_bindings() {
+        assert!(resolve_code(
+            "let mut a = 1\na = 2\na += 1\nlet mut (b, c) = (1, 2)\nb, c = c, b\nlet list = [1]\nlist[0] = 2\nstruct P { x }\nlet p = P { x: 1 }\np.x = 2\n"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_immutable_bindings() {
+        assert_eq!(
+            resolve_code(
+                "const GRAVITY = 9.8\nlet speed = 2\nlet (x, y) = (1, 2)\nfn f(n) {\n    n = 1\n    speed += 1\n}\nGRAVITY = 1\nx, y = y, x\nfor i in 0..3 {\n    i = 0\n}\nf = 1\nprint match 1 {\n    m => m = 2\n}\n"
+            ),
+            vec![
+                "Can't assign to parameter n",
+                "Can't assign to immutable variable speed, declare it with 'let mut'",
+                "Can't assign to constant GRAVITY",
+                "Can't assign to immutable variable x, declare it with 'let mut'",
+                "Can't assign to immutable variable y, declare it with 'let mut'",
+                "Can't assign to loop variable i",
+                "Can't assign to function f",
+                "Can't assign to match binding m",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_vector_components() {
+        assert_eq!(
+            resolve_code(
+                "const V = vec2(1, 2)\nV.x = 5\nlet v = vec3(1, 2, 3)\nv.x += 1\nv.yz = vec2(0, 0)\nfn f(a: vec2, b) {\n    a.y = 1\n    b.x = 1\n}\n"
+            ),
+            vec![
+                "Can't assign to constant V",
+                "Can't assign to immutable variable v, declare it with 'let mut'",
+                "Can't assign to immutable variable v, declare it with 'let mut'",
+                "Can't assign to parameter a",
+                "Can't assign to parameter b",
+            ]
+        );
+        assert!(resolve_code(
+            "struct P { x, position }\nlet p = P { x: 1, position: vec2(0, 0) }\np.x = 2\np.position.y = 3\nlet mut v = vec2(1, 2)\nv.x = 3\nfn hit(target) {\n    target.health -= 1\n    target.position.x = 0\n}\n"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_shadowing() {
+        assert!(resolve_code(
+            "let a = 1\n{\n    let mut a = 2\n    a = 3\n}\nfn f(n) {\n    let mut n = n\n    n += 1\n    later = n\n}\nlet mut later = 0\n"
+        )
+        .is_empty());
+        assert_eq!(
+            resolve_code("let mut a = 1\n{\n    let a = 2\n    a = 3\n}\na = 4\n"),
+            vec!["Can't assign to immutable variable a, declare it with 'let mut'"]
+        );
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(
+            resolve_code("const SPEED = 2\nlet SPEED = 3\nfn f() {\n    let SPEED = 4\n}\n"),
+            vec!["Constant SPEED is already declared"]
+        );
+        assert_eq!(
+            resolve_code("PI = 3\nTAU += 1\nlet x = sin(PI) + TAU\n"),
+            vec![
+                "Can't assign to constant PI",
+                "Can't assign to constant TAU"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_use_before_declaration() {
+        assert_eq!(
+            resolve_code(
+                "print later\nlet later = 1\n{\n    print inner\n    let inner = 2\n}\nprint missing\nmissing = 1\n"
+            ),
+            vec![
+                "Variable later is used before it is declared",
+                "Variable inner is used before it is declared",
+                "Variable missing not found",
+                "Variable missing is not declared",
+            ]
+        );
+        // Functions only run after the declarations they use, and natives always exist
+        assert!(resolve_code(
+            "fn a(n) {\n    return b(n) + len([LIMIT])\n}\nfn b(n) {\n    return a(n - 1)\n}\n{\n    let f = fn() {\n        return g()\n    }\n    fn g() {\n        return f\n    }\n}\nconst LIMIT = 3\n"
+        )
+        .is_empty());
+        // A declaration further down doesn't hide a variable of an outer scope
+        assert!(resolve_code("let x = 1\n{\n    print x\n    let x = 2\n}\n").is_empty());
+    }
+
+    #[test]
+    fn test_unknown_members() {
+        assert_eq!(
+            resolve_code(
+                "struct Player { position, health }\nenum State { Idle, Walking(speed) }\nlet p = Player { position: vec2(0, 0), health: 3 }\nprint p.mana\nprint State.Running\nlet v = 1.5\nprint v.x\nprint \"abc\".len\nprint Player { position: 0, speed: 1 }\nmatch State.Idle {\n    State.Walking(a, b) => print a\n    State.Flying => print 0\n}\n"
+            ),
+            vec![
+                "Struct Player has no field mana",
+                "Enum State has no variant Running",
+                "Type real has no member x",
+                "Struct Player has no field speed",
+                "Missing field health when constructing Player",
+                "Variant State.Walking has 1 fields but the pattern has 2",
+                "Enum State has no variant Flying",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operator_types() {
+        assert_eq!(
+            resolve_code(
+                "fn f(x) {\n    print true + 1\n    print -\"a\"\n    print x + 1\n    if 1 {\n        print 1.5 << 2\n    }\n    return (1, 2.5) == x and 3\n}\n"
+            ),
+            vec![
+                "Expected number or string",
+                "Expected number",
+                "Expected boolean in an if condition",
+                "Expected integers",
+                "Expected boolean operands for and but got int",
+            ]
+        );
+        let errors = check("let speed = 2\nlet t = speed > 1\nprint t * 2\n").unwrap_err();
+        let span = errors[0].span.unwrap();
+        assert_eq!((span.line, span.column), (3, 7));
+    }
+
+    #[test]
+    fn test_annotations() {
+        assert!(resolve_code(
+            "struct P { x }\nlet speed: real = 2.0\nlet mut count: int = 0\ncount += 1\nconst ORIGIN: (int, int) = (0, 0)\nfn f(x: int, p: P, g: fn) -> bool {\n    return x > p.x\n}\nlet ok: bool = f(1, P { x: 0 }, f)\nlet anything: any = \"a\"\n"
+        )
+        .is_empty());
+        assert!(resolve_code(
+            "let x: real = 2\nlet mut speed: real = 1.5\nspeed = 2\nspeed += 1\nconst SIZE: (real, real) = (640, 480.5)\nfn scale(factor: real) -> real {\n    return 3\n}\nlet y: real = scale(2) * x\n"
+        )
+        .is_empty());
+        assert_eq!(
+            resolve_code(
+                "let speed: int = 2.5\nlet mut count: int = 0\ncount = \"many\"\ncount += 0.5\nfn f(x: int) -> bool {\n    return x\n}\nf(true)\nf(1, 2)\nlet p: Point = 1\nfn g() -> int {\n    return\n}\n"
+            ),
+            vec![
+                "Expected int for speed but got real",
+                "Expected int for count but got string",
+                "Expected int for count but got real",
+                "Expected bool for the return value but got int",
+                "Expected int for argument 1 but got bool",
+                "Expected 1 arguments but got 2",
+                "Unknown type Point",
+                "Expected int for the return value but got nil",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_location() {
+        let errors = check("let x = 1\nx = 2\n").unwrap_err();
+        let span = errors[0].span.unwrap();
+        assert_eq!((span.line, span.column), (2, 1));
+    }
+}
@@ -1,9 +1,15 @@
 use std::fmt::Display;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+use super::span::Span;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum TokenType {
     Real,
     Integer,
+    String,
+    InterpolationStart,
+    InterpolationMiddle,
+    InterpolationEnd,
     Plus,
     Minus,
     Star,
@@ -16,6 +22,17 @@ pub enum TokenType {
     Greater,
     LessEquals,
     GreaterEquals,
+    Percent,
+    StarStar,
+    Ampersand,
+    Pipe,
+    Caret,
+    LessLess,
+    GreaterGreater,
+    PlusEquals,
+    MinusEquals,
+    StarEquals,
+    SlashEquals,
     LeftParen,
     RightParen,
     LeftSquareBracket,
@@ -26,10 +43,14 @@ pub enum TokenType {
     Comma,
     Dot,
     DotDot,
+    DotDotEquals,
+    // Only produced by the parser, `step` is an identifier everywhere but after a range
+    Step,
     Let,
     True,
     False,
     Newline,
+    DocComment,
     Print,
     Or,
     And,
@@ -37,6 +58,34 @@ pub enum TokenType {
     Else,
     For,
     In,
+    Fn,
+    Return,
+    While,
+    Loop,
+    Break,
+    Continue,
+    Label,
+    Colon,
+    Struct,
+    Match,
+    FatArrow,
+    Enum,
+    Const,
+    Mut,
+    Arrow,
+}
+
+impl TokenType {
+    /// The binary operator applied by a compound assignment like `+=`
+    pub fn compound_operator(&self) -> Option<TokenType> {
+        match self {
+            TokenType::PlusEquals => Some(TokenType::Plus),
+            TokenType::MinusEquals => Some(TokenType::Minus),
+            TokenType::StarEquals => Some(TokenType::Star),
+            TokenType::SlashEquals => Some(TokenType::Slash),
+            _ => None,
+        }
+    }
 }
 
 impl Display for TokenType {
@@ -44,6 +93,10 @@ impl Display for TokenType {
         let printable = match self {
             TokenType::Real => "Real",
             TokenType::Integer => "Integer",
+            TokenType::String => "String",
+            TokenType::InterpolationStart => "InterpolationStart",
+            TokenType::InterpolationMiddle => "InterpolationMiddle",
+            TokenType::InterpolationEnd => "InterpolationEnd",
             TokenType::Plus => "Plus",
             TokenType::Minus => "Minus",
             TokenType::Star => "Star",
@@ -56,6 +109,17 @@ impl Display for TokenType {
             TokenType::Greater => "Greater",
             TokenType::LessEquals => "LessEquals",
             TokenType::GreaterEquals => "GreaterEquals",
+            TokenType::Percent => "Percent",
+            TokenType::StarStar => "StarStar",
+            TokenType::Ampersand => "Ampersand",
+            TokenType::Pipe => "Pipe",
+            TokenType::Caret => "Caret",
+            TokenType::LessLess => "LessLess",
+            TokenType::GreaterGreater => "GreaterGreater",
+            TokenType::PlusEquals => "PlusEquals",
+            TokenType::MinusEquals => "MinusEquals",
+            TokenType::StarEquals => "StarEquals",
+            TokenType::SlashEquals => "SlashEquals",
             TokenType::LeftParen => "LeftParen",
             TokenType::RightParen => "RightParen",
             TokenType::LeftSquareBracket => "LeftSquareBracket",
@@ -66,10 +130,13 @@ impl Display for TokenType {
             TokenType::Comma => "Comma",
             TokenType::Dot => "Dot",
             TokenType::DotDot => "DotDot",
+            TokenType::DotDotEquals => "DotDotEquals",
+            TokenType::Step => "Step",
             TokenType::Let => "Let",
             TokenType::True => "True",
             TokenType::False => "False",
             TokenType::Newline => "Newline",
+            TokenType::DocComment => "DocComment",
             TokenType::Print => "Print",
             TokenType::Or => "Or",
             TokenType::And => "And",
@@ -77,6 +144,21 @@ impl Display for TokenType {
             TokenType::Else => "Else",
             TokenType::For => "For",
             TokenType::In => "In",
+            TokenType::Fn => "Fn",
+            TokenType::Return => "Return",
+            TokenType::While => "While",
+            TokenType::Loop => "Loop",
+            TokenType::Break => "Break",
+            TokenType::Continue => "Continue",
+            TokenType::Label => "Label",
+            TokenType::Colon => "Colon",
+            TokenType::Struct => "Struct",
+            TokenType::Match => "Match",
+            TokenType::FatArrow => "FatArrow",
+            TokenType::Enum => "Enum",
+            TokenType::Const => "Const",
+            TokenType::Mut => "Mut",
+            TokenType::Arrow => "Arrow",
         };
         write!(f, "{}", printable)
     }
@@ -86,10 +168,20 @@ impl Display for TokenType {
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
+    pub span: Span,
 }
 
 impl Token {
     pub fn new(token_type: TokenType, lexeme: String) -> Self {
-        Self { token_type, lexeme }
+        Self {
+            token_type,
+            lexeme,
+            span: Span::default(),
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
     }
 }